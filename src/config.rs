@@ -0,0 +1,755 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, path::Path};
+
+use crate::playground::Playground;
+
+// No playground the quadtree can hold is this many pixels across, so a longer lidar range is
+// surely a mistake.
+const MAX_LIDAR_RANGE: f64 = 100_000.0;
+
+// A scan of more rays than this would take too long to cast for every step of a simulation or an
+// exploration run, so a finer lidar resolution is surely a mistake too.
+const MAX_LIDAR_RAYS: f64 = 10_000.0;
+
+// How the raw RRT path is simplified before the robot follows it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum CompactionMode {
+    None,   // Follow every RRT vertex
+    Greedy, // Single forward pass, skipping vertices while the straight line stays valid
+}
+
+// Which search builds the raw path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    Rrt, // Rapid Random Tree grown from the start
+    Prm, // Probabilistic roadmap, built and collision checked in parallel
+}
+
+// How the planner measures the cost of moving between two poses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    Euclidean, // Distance travelled, ignoring rotation
+    Se2,       // Distance travelled plus heading_weight per radian turned
+    // Distance travelled plus the area the footprint sweeps while turning, counted as the
+    // distance that would sweep the same area driving straight ahead
+    SweptArea,
+}
+
+// Ways of improving the compacted path. Each keeps every edge of the path collision free.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Optimiser {
+    Shortcut,        // Join random pairs of points along the path with straight lines
+    PartialShortcut, // Like Shortcut, but straightening only one of x, y and heading at a time
+    ElasticBand,     // Pull the path taut while pushing it away from obstacles
+}
+
+// How the compacted path is turned into the curve the robot follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Smoothing {
+    Linear,     // Straight lines between waypoints
+    CatmullRom, // Cubic curve through every waypoint
+    Hermite,    // Cubic curve through every waypoint, with tangents limited to avoid overshoot
+    BSpline,    // Clamped cubic B-spline, which cuts inside the waypoints
+    Arcs,       // Straight lines with the corners rounded off by circular arcs
+}
+
+// How the robot's speed varies along the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    Constant,    // Always at full speed, starting and stopping instantly
+    Trapezoidal, // Acceleration limited, slowing for turns and curves
+    SCurve,      // Like Trapezoidal, but with the jerk limited too
+}
+
+// How the simulated robot steers to follow the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Controller {
+    PurePursuit, // Drive along the circular arc through a point lookahead pixels down the path
+    Stanley,     // Steer to match the path's heading, plus a correction for the cross-track error
+    PidHeading,  // PID control of the heading towards a point lookahead pixels down the path
+    Mpc,         // Optimise the next few seconds of commands against the robot model and obstacles
+    Dwa,         // Pick the best of a window of reachable velocities each tick, avoiding obstacles
+}
+
+// What the MPC planner's cost function charges for, per step of its horizon.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MpcWeights {
+    pub tracking: f64,   // Per square pixel between the robot and where the path has it
+    pub heading: f64,    // Per square radian between the robot's heading and the path's
+    pub smoothness: f64, // Per square change in the commands, as fractions of their limits
+    pub clearance: f64,  // Per square pixel of the robot that is too close to an obstacle
+}
+
+impl Default for MpcWeights {
+    fn default() -> Self {
+        return Self {
+            tracking: 1.0,
+            heading: 20.0,
+            smoothness: 10.0,
+            clearance: 0.1,
+        };
+    }
+}
+
+// How the DWA planner scores each arc it tries. Each term is scaled to about 0 to 1 first.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DwaWeights {
+    pub progress: f64, // For closing on a point down the path, less any distance off it
+    pub clearance: f64, // For keeping away from obstacles along the arc
+    pub speed: f64,    // For going fast
+}
+
+impl Default for DwaWeights {
+    fn default() -> Self {
+        return Self {
+            progress: 1.0,
+            clearance: 0.2,
+            speed: 0.1,
+        };
+    }
+}
+
+// The simulated range sensor. Angles are in radians, ranges in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LidarConfig {
+    pub fov: f64,        // Total sweep, centred on the robot's heading, up to a full circle
+    pub resolution: f64, // Between neighbouring rays
+    pub max_range: f64,  // Rays that hit nothing nearer read this far
+    pub noise: f64,      // Standard deviation of the Gaussian noise on each reading
+}
+
+impl Default for LidarConfig {
+    fn default() -> Self {
+        return Self {
+            fov: 1.5 * std::f64::consts::PI,
+            resolution: std::f64::consts::PI / 90.0,
+            max_range: 200.0,
+            noise: 1.0,
+        };
+    }
+}
+
+// Monte Carlo localisation for the closed-loop simulation. Distances are in pixels, angles in
+// radians.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalisationConfig {
+    pub particles: usize,           // Guesses at the pose the filter keeps
+    pub odometry_noise: f64,        // Error in each odometry reading, as a fraction of the motion
+    pub heading_drift: f64,         // Heading error odometry picks up per pixel travelled
+    pub sensor_noise: f64,          // Error the filter allows for in each range reading
+    pub beams: usize,               // Rays of each scan the filter compares, spread across it
+    pub scan_interval: f64,         // Seconds between scans
+    pub initial_spread: (f64, f64), // How far off the first guesses are, in position and heading
+}
+
+impl Default for LocalisationConfig {
+    fn default() -> Self {
+        return Self {
+            particles: 300,
+            odometry_noise: 0.1,
+            heading_drift: 0.002,
+            sensor_noise: 5.0,
+            beams: 24,
+            scan_interval: 0.1,
+            initial_spread: (5.0, 0.05),
+        };
+    }
+}
+
+// Tunable planner parameters. Missing fields in a config file fall back to the defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlannerConfig {
+    pub algorithm: Algorithm,
+    pub grid_size: i32,                // Position sampling resolution, in pixels
+    pub angle_step: i32,               // Heading sampling resolution, in degrees
+    pub goal_bias: f64,                // Probability of sampling the goal instead of a random pose
+    pub step_size: Option<f64>,        // Longest edge added to the tree, or unlimited if unset
+    pub max_iterations: Option<usize>, // Samples to draw before giving up, or unlimited if unset
+    pub neighbours: usize,             // Nearest tree nodes to try connecting each sample to
+    pub heading_weight: f64,           // Distance equivalent of turning one radian, in pixels
+    pub metric: DistanceMetric,        // Used for nearest neighbours, path costs and timing
+    pub robot_size: (i32, i32),        // Robot footprint (width, length) at heading 0
+    pub speed: f64,                    // Travel speed along the path, in metric units per second
+    pub profile: Profile,
+    pub max_angular_speed: f64,        // Radians per second
+    pub max_acceleration: f64,         // Pixels per second squared
+    pub max_angular_acceleration: f64, // Radians per second squared
+    pub max_jerk: f64,                 // Pixels per second cubed, for Profile::SCurve
+    pub max_lateral_acceleration: f64, // Pixels per second squared, sideways on curves
+    pub compaction: CompactionMode,
+    pub optimisers: Vec<Optimiser>, // Run in order on the compacted path
+    pub optimise_iterations: usize, // Attempts each optimiser makes
+    pub smoothing: Smoothing,
+    pub corner_radius: f64, // Arc radius for Smoothing::Arcs, in pixels
+    pub controller: Controller,
+    pub lookahead: f64,    // Pixels down the path for pure pursuit and PID heading
+    pub stanley_gain: f64, // Stanley's cross-track error gain
+    pub heading_gains: (f64, f64, f64), // PID gains for Controller::PidHeading
+    pub timestep: f64,     // Seconds per step of the closed-loop simulation
+    pub mpc_horizon: usize, // Steps the MPC planner looks ahead
+    pub mpc_step: f64,     // Seconds per MPC step, and between re-plans
+    pub mpc_iterations: usize, // Optimisation passes per MPC re-plan
+    pub mpc_weights: MpcWeights,
+    pub dwa_horizon: f64,   // Seconds of each arc the DWA planner simulates
+    pub dwa_samples: usize, // Linear and angular speeds the DWA planner tries, each
+    pub dwa_weights: DwaWeights,
+    pub lidar: LidarConfig,
+    pub localise: bool, // Steer the simulated robot by a particle filter's estimate of its pose
+    pub localisation: LocalisationConfig,
+    pub belief_cell: i32,       // Side of a cell of the explored map, in pixels
+    pub explore_step: f64,      // Pixels the exploring robot moves between scans
+    pub frontier_weight: f64,   // Unknown cells a frontier must promise per pixel away
+    pub tool_width: f64,        // Width of the strip a coverage sweep covers, in pixels
+    pub overlap: f64,           // Fraction of the tool's width neighbouring sweeps share
+    pub roadmap_samples: usize, // Poses sampled for the PRM roadmap
+    pub runs: usize,            // Independently seeded searches to run, keeping the shortest path
+    pub threads: Option<usize>, // Worker threads for parallel work, or one per core if unset
+    pub anytime_rounds: usize,  // Searches an anytime plan runs while looking for shorter paths
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        return Self {
+            algorithm: Algorithm::Rrt,
+            grid_size: 10,
+            angle_step: 10,
+            goal_bias: 0.01,
+            step_size: None,
+            max_iterations: Some(1_000_000),
+            neighbours: 8,
+            heading_weight: 20.0,
+            metric: DistanceMetric::Se2,
+            robot_size: (10, 50),
+            speed: 25.0,
+            profile: Profile::Constant,
+            max_angular_speed: 1.0,
+            max_acceleration: 25.0,
+            max_angular_acceleration: 2.0,
+            max_jerk: 100.0,
+            max_lateral_acceleration: 10.0,
+            compaction: CompactionMode::Greedy,
+            optimisers: vec![],
+            optimise_iterations: 200,
+            smoothing: Smoothing::Linear,
+            corner_radius: 20.0,
+            controller: Controller::PurePursuit,
+            lookahead: 30.0,
+            stanley_gain: 1.0,
+            heading_gains: (2.0, 0.0, 0.1),
+            timestep: 0.02,
+            mpc_horizon: 10,
+            mpc_step: 0.2,
+            mpc_iterations: 20,
+            mpc_weights: MpcWeights::default(),
+            dwa_horizon: 3.0,
+            dwa_samples: 7,
+            dwa_weights: DwaWeights::default(),
+            lidar: LidarConfig::default(),
+            localise: false,
+            localisation: LocalisationConfig::default(),
+            belief_cell: 5,
+            explore_step: 10.0,
+            frontier_weight: 1.0,
+            tool_width: 50.0,
+            overlap: 0.1,
+            roadmap_samples: 10_000,
+            runs: 1,
+            threads: None,
+            anytime_rounds: 10,
+        };
+    }
+}
+
+impl PlannerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.grid_size <= 0 {
+            return Err(format!(
+                "grid_size must be positive, got {}",
+                self.grid_size
+            ));
+        }
+        if self.angle_step <= 0 || self.angle_step > 180 {
+            return Err(format!(
+                "angle_step must be between 1 and 180 degrees, got {}",
+                self.angle_step
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.goal_bias) {
+            return Err(format!(
+                "goal_bias must be between 0 and 1, got {}",
+                self.goal_bias
+            ));
+        }
+        if let Some(step_size) = self.step_size {
+            if step_size.is_nan() || step_size < self.grid_size as f64 {
+                return Err(format!(
+                    "step_size must be at least grid_size ({}), got {}",
+                    self.grid_size, step_size
+                ));
+            }
+        }
+        if self.max_iterations == Some(0) {
+            return Err("max_iterations must be positive".to_string());
+        }
+        if self.neighbours == 0 {
+            return Err("neighbours must be positive".to_string());
+        }
+        if !(self.heading_weight >= 0.0 && self.heading_weight.is_finite()) {
+            return Err(format!(
+                "heading_weight must not be negative, got {}",
+                self.heading_weight
+            ));
+        }
+        if self.robot_size.0 <= 0 || self.robot_size.1 <= 0 {
+            return Err(format!(
+                "robot_size must be positive, got {:?}",
+                self.robot_size
+            ));
+        }
+        if !(self.speed > 0.0 && self.speed.is_finite()) {
+            return Err(format!("speed must be positive, got {}", self.speed));
+        }
+        if self.optimise_iterations == 0 {
+            return Err("optimise_iterations must be positive".to_string());
+        }
+        if !(self.corner_radius > 0.0 && self.corner_radius.is_finite()) {
+            return Err(format!(
+                "corner_radius must be positive, got {}",
+                self.corner_radius
+            ));
+        }
+        if !(self.lookahead > 0.0 && self.lookahead.is_finite()) {
+            return Err(format!(
+                "lookahead must be positive, got {}",
+                self.lookahead
+            ));
+        }
+        if !(self.timestep > 0.0 && self.timestep.is_finite()) {
+            return Err(format!("timestep must be positive, got {}", self.timestep));
+        }
+        if self.mpc_horizon == 0 {
+            return Err("mpc_horizon must be positive".to_string());
+        }
+        if !(self.mpc_step > 0.0 && self.mpc_step.is_finite()) {
+            return Err(format!("mpc_step must be positive, got {}", self.mpc_step));
+        }
+        if self.mpc_iterations == 0 {
+            return Err("mpc_iterations must be positive".to_string());
+        }
+        if !(self.dwa_horizon > 0.0 && self.dwa_horizon.is_finite()) {
+            return Err(format!(
+                "dwa_horizon must be positive, got {}",
+                self.dwa_horizon
+            ));
+        }
+        if self.dwa_samples < 2 {
+            return Err(format!(
+                "dwa_samples must be at least 2, got {}",
+                self.dwa_samples
+            ));
+        }
+        let lidar = self.lidar;
+        if !(lidar.fov > 0.0 && lidar.fov <= std::f64::consts::TAU) {
+            return Err(format!(
+                "lidar.fov must be positive and at most a full circle, got {}",
+                lidar.fov
+            ));
+        }
+        if !(lidar.resolution > 0.0 && lidar.resolution.is_finite()) {
+            return Err(format!(
+                "lidar.resolution must be positive, got {}",
+                lidar.resolution
+            ));
+        }
+        if lidar.fov / lidar.resolution > MAX_LIDAR_RAYS {
+            return Err(format!(
+                "lidar.resolution must give at most {MAX_LIDAR_RAYS} rays across lidar.fov, got {}",
+                lidar.resolution
+            ));
+        }
+        if !(lidar.max_range > 0.0 && lidar.max_range <= MAX_LIDAR_RANGE) {
+            return Err(format!(
+                "lidar.max_range must be positive and at most {MAX_LIDAR_RANGE}, got {}",
+                lidar.max_range
+            ));
+        }
+        if !(lidar.noise >= 0.0 && lidar.noise.is_finite()) {
+            return Err(format!(
+                "lidar.noise must not be negative, got {}",
+                lidar.noise
+            ));
+        }
+        let localisation = self.localisation;
+        if localisation.particles == 0 {
+            return Err("localisation.particles must be positive".to_string());
+        }
+        if localisation.beams == 0 {
+            return Err("localisation.beams must be positive".to_string());
+        }
+        for (name, value) in [
+            ("localisation.sensor_noise", localisation.sensor_noise),
+            ("localisation.scan_interval", localisation.scan_interval),
+        ] {
+            if !(value > 0.0 && value.is_finite()) {
+                return Err(format!("{name} must be positive, got {value}"));
+            }
+        }
+        for (name, value) in [
+            ("localisation.odometry_noise", localisation.odometry_noise),
+            ("localisation.heading_drift", localisation.heading_drift),
+            ("localisation.initial_spread", localisation.initial_spread.0),
+            ("localisation.initial_spread", localisation.initial_spread.1),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                return Err(format!("{name} must not be negative, got {value}"));
+            }
+        }
+        if self.belief_cell <= 0 {
+            return Err(format!(
+                "belief_cell must be positive, got {}",
+                self.belief_cell
+            ));
+        }
+        if !(self.explore_step > 0.0 && self.explore_step.is_finite()) {
+            return Err(format!(
+                "explore_step must be positive, got {}",
+                self.explore_step
+            ));
+        }
+        if !(self.frontier_weight >= 0.0 && self.frontier_weight.is_finite()) {
+            return Err(format!(
+                "frontier_weight must not be negative, got {}",
+                self.frontier_weight
+            ));
+        }
+        if !(self.tool_width > 0.0 && self.tool_width.is_finite()) {
+            return Err(format!(
+                "tool_width must be positive, got {}",
+                self.tool_width
+            ));
+        }
+        if !(0.0..1.0).contains(&self.overlap) {
+            return Err(format!(
+                "overlap must be at least 0 and less than 1, got {}",
+                self.overlap
+            ));
+        }
+        let (p, i, d) = self.heading_gains;
+        let w = self.mpc_weights;
+        let dwa = self.dwa_weights;
+        for (name, gain) in [
+            ("stanley_gain", self.stanley_gain),
+            ("heading_gains", p),
+            ("heading_gains", i),
+            ("heading_gains", d),
+            ("mpc_weights", w.tracking),
+            ("mpc_weights", w.heading),
+            ("mpc_weights", w.smoothness),
+            ("mpc_weights", w.clearance),
+            ("dwa_weights", dwa.progress),
+            ("dwa_weights", dwa.clearance),
+            ("dwa_weights", dwa.speed),
+        ] {
+            if !(gain >= 0.0 && gain.is_finite()) {
+                return Err(format!("{name} must not be negative, got {gain}"));
+            }
+        }
+        let limits = [
+            ("max_angular_speed", self.max_angular_speed),
+            ("max_acceleration", self.max_acceleration),
+            ("max_angular_acceleration", self.max_angular_acceleration),
+            ("max_jerk", self.max_jerk),
+            ("max_lateral_acceleration", self.max_lateral_acceleration),
+        ];
+        for (name, limit) in limits {
+            if !(limit > 0.0 && limit.is_finite()) {
+                return Err(format!("{name} must be positive, got {limit}"));
+            }
+        }
+        if self.roadmap_samples == 0 {
+            return Err("roadmap_samples must be positive".to_string());
+        }
+        if self.runs == 0 {
+            return Err("runs must be positive".to_string());
+        }
+        if self.threads == Some(0) {
+            return Err("threads must be positive".to_string());
+        }
+        if self.anytime_rounds == 0 {
+            return Err("anytime_rounds must be positive".to_string());
+        }
+        return Ok(());
+    }
+
+    // Like validate, but also checks the settings that only make sense for a particular
+    // playground: the sampling grid has to have at least one point across it.
+    pub fn validate_for(&self, playground: &Playground) -> Result<(), String> {
+        self.validate()?;
+        let (w, h) = playground.size;
+        if self.grid_size > w.min(h) {
+            return Err(format!(
+                "grid_size must be at most the playground's size ({w}x{h}), got {}",
+                self.grid_size
+            ));
+        }
+        return Ok(());
+    }
+
+    // Reads a JSON config file. The result is validated before it is returned.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let config: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(PlannerConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate() {
+        let bad = [
+            PlannerConfig {
+                grid_size: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                angle_step: 360,
+                ..Default::default()
+            },
+            PlannerConfig {
+                goal_bias: 1.5,
+                ..Default::default()
+            },
+            PlannerConfig {
+                step_size: Some(5.0),
+                ..Default::default()
+            },
+            PlannerConfig {
+                max_iterations: Some(0),
+                ..Default::default()
+            },
+            PlannerConfig {
+                neighbours: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                heading_weight: -1.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                robot_size: (10, -1),
+                ..Default::default()
+            },
+            PlannerConfig {
+                speed: 0.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                optimise_iterations: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                corner_radius: 0.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                lookahead: 0.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                timestep: f64::NAN,
+                ..Default::default()
+            },
+            PlannerConfig {
+                heading_gains: (1.0, -0.1, 0.0),
+                ..Default::default()
+            },
+            PlannerConfig {
+                mpc_horizon: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                dwa_samples: 1,
+                ..Default::default()
+            },
+            PlannerConfig {
+                dwa_weights: DwaWeights {
+                    speed: -1.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                lidar: LidarConfig {
+                    fov: 7.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                lidar: LidarConfig {
+                    resolution: 0.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                lidar: LidarConfig {
+                    resolution: 1e-9,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                lidar: LidarConfig {
+                    max_range: 1e10,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                lidar: LidarConfig {
+                    noise: -1.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                belief_cell: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                explore_step: f64::NAN,
+                ..Default::default()
+            },
+            PlannerConfig {
+                frontier_weight: -1.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                tool_width: 0.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                overlap: 1.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                localisation: LocalisationConfig {
+                    particles: 0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                localisation: LocalisationConfig {
+                    sensor_noise: 0.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                localisation: LocalisationConfig {
+                    initial_spread: (5.0, -0.1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                mpc_weights: MpcWeights {
+                    clearance: f64::INFINITY,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PlannerConfig {
+                max_acceleration: 0.0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                max_jerk: f64::INFINITY,
+                ..Default::default()
+            },
+            PlannerConfig {
+                roadmap_samples: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                runs: 0,
+                ..Default::default()
+            },
+            PlannerConfig {
+                threads: Some(0),
+                ..Default::default()
+            },
+            PlannerConfig {
+                anytime_rounds: 0,
+                ..Default::default()
+            },
+        ];
+        for config in bad {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn validate_for() {
+        let playground = Playground::new((100, 50), (10, 10), (90, 40));
+        let config = PlannerConfig {
+            grid_size: 50,
+            step_size: Some(50.0),
+            ..Default::default()
+        };
+        assert_eq!(config.validate_for(&playground), Ok(()));
+        let config = PlannerConfig {
+            grid_size: 51,
+            step_size: Some(51.0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(config.validate_for(&playground).is_err());
+    }
+
+    #[test]
+    fn partial_file_uses_defaults() {
+        let config: PlannerConfig = serde_json::from_str(
+            r#"{ "goal_bias": 0.2, "compaction": "none", "algorithm": "prm" }"#,
+        )
+        .unwrap();
+        assert_eq!(config.goal_bias, 0.2);
+        assert_eq!(config.algorithm, Algorithm::Prm);
+        assert_eq!(config.compaction, CompactionMode::None);
+        assert_eq!(config.grid_size, PlannerConfig::default().grid_size);
+    }
+}
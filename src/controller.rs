@@ -0,0 +1,364 @@
+use crate::config::{Controller, PlannerConfig};
+use crate::dwa::Dwa;
+use crate::mpc::Mpc;
+use crate::planner::Planner;
+use crate::playground::Playground;
+use crate::pose::{angle_diff, normalize_angle, Pose2};
+use crate::robot::{Control, Robot};
+
+// Added to the speed in Stanley's cross-track term so it doesn't blow up when the robot is slow.
+const STANLEY_SOFTENING: f64 = 1.0;
+
+// Stanley never steers more than this, in radians, either way.
+const MAX_STEERING: f64 = 1.2;
+
+// The robot keeps at least this fraction of its speed while turning to face the path, so robots
+// that can't turn on the spot still get round.
+const MIN_SPEED_FRACTION: f64 = 0.25;
+
+// The nearest point on the path to the robot.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Projection {
+    pub distance: f64, // Along the path, in pixels
+    pub x: f64,
+    pub y: f64,
+    pub heading: f64, // Direction of the path at this point
+    pub error: f64,   // Cross-track error: positive if a positive turn takes the robot back
+}
+
+// A path reduced to the positions the robot has to drive through, with the distance along it to
+// each one and the way the robot faces there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackedPath {
+    points: Vec<(f64, f64)>,
+    distances: Vec<f64>,
+    thetas: Vec<f64>,
+}
+
+impl TrackedPath {
+    pub fn new(path: &[Pose2]) -> Self {
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut distances = Vec::new();
+        let mut thetas: Vec<f64> = Vec::new();
+        for p in path {
+            let distance = match points.last() {
+                None => 0.0,
+                Some(&(x, y)) => (p.x - x).hypot(p.y - y),
+            };
+            // Turning on the spot doesn't move the robot along the path, only changes the way it
+            // faces when it sets off.
+            match points.is_empty() || distance > 0.0 {
+                true => {
+                    points.push((p.x, p.y));
+                    distances.push(distances.last().unwrap_or(&0.0) + distance);
+                    thetas.push(p.theta);
+                }
+                false => *thetas.last_mut().unwrap() = p.theta,
+            }
+        }
+        return Self {
+            points,
+            distances,
+            thetas,
+        };
+    }
+
+    pub fn length(&self) -> f64 {
+        return *self.distances.last().unwrap_or(&0.0);
+    }
+
+    pub fn end(&self) -> (f64, f64) {
+        return *self.points.last().unwrap_or(&(0.0, 0.0));
+    }
+
+    // The point `distance` along the path, clamped to its ends.
+    pub fn point_at(&self, distance: f64) -> (f64, f64) {
+        let i = self.distances.partition_point(|&d| d < distance);
+        if i == 0 {
+            return self.points[0];
+        }
+        if i == self.points.len() {
+            return self.end();
+        }
+        let ((ax, ay), (bx, by)) = (self.points[i - 1], self.points[i]);
+        let s = (distance - self.distances[i - 1]) / (self.distances[i] - self.distances[i - 1]);
+        return (ax + (bx - ax) * s, ay + (by - ay) * s);
+    }
+
+    // The direction of travel `distance` along the path, clamped to its ends.
+    pub fn heading_at(&self, distance: f64) -> f64 {
+        if self.points.len() < 2 {
+            return 0.0;
+        }
+        let i = self
+            .distances
+            .partition_point(|&d| d < distance)
+            .clamp(1, self.points.len() - 1);
+        let ((ax, ay), (bx, by)) = (self.points[i - 1], self.points[i]);
+        return (by - ay).atan2(bx - ax);
+    }
+
+    // The way the robot faces `distance` along the path, which only matches the direction of
+    // travel for robots that can't move sideways.
+    pub fn theta_at(&self, distance: f64) -> f64 {
+        let i = self.distances.partition_point(|&d| d < distance);
+        if i == 0 {
+            return self.thetas[0];
+        }
+        if i == self.points.len() {
+            return *self.thetas.last().unwrap();
+        }
+        let s = (distance - self.distances[i - 1]) / (self.distances[i] - self.distances[i - 1]);
+        let turn = angle_diff(self.thetas[i - 1], self.thetas[i]);
+        return normalize_angle(self.thetas[i - 1] + turn * s);
+    }
+
+    // The nearest point to (x, y) on the part of the path between `from` and `from + window`
+    // pixels along it. Only looking a little way ahead of the robot's progress keeps it from
+    // skipping to a later part of the path that happens to pass nearby.
+    pub fn project(&self, x: f64, y: f64, from: f64, window: f64) -> Projection {
+        if self.points.len() < 2 {
+            let (px, py) = self.end();
+            return Projection {
+                distance: 0.0,
+                x: px,
+                y: py,
+                heading: 0.0,
+                error: (x - px).hypot(y - py),
+            };
+        }
+
+        let mut best = Projection::default();
+        let mut best_distance = f64::INFINITY;
+        for i in 0..self.points.len() - 1 {
+            if self.distances[i + 1] < from || self.distances[i] > from + window {
+                continue;
+            }
+            let ((ax, ay), (bx, by)) = (self.points[i], self.points[i + 1]);
+            let length = self.distances[i + 1] - self.distances[i];
+            let (tx, ty) = ((bx - ax) / length, (by - ay) / length);
+            let s = ((x - ax) * tx + (y - ay) * ty).clamp(0.0, length);
+            let (px, py) = (ax + tx * s, ay + ty * s);
+            let d = (x - px).hypot(y - py);
+            // On a tie, at a corner, take the later edge so the robot turns onto it.
+            if d <= best_distance {
+                best_distance = d;
+                best = Projection {
+                    distance: self.distances[i] + s,
+                    x: px,
+                    y: py,
+                    heading: ty.atan2(tx),
+                    // A positive turn heads towards (-ty, tx).
+                    error: match (x - px) * ty - (y - py) * tx >= 0.0 {
+                        true => d,
+                        false => -d,
+                    },
+                };
+            }
+        }
+        return best;
+    }
+}
+
+// Turns the robot's pose into velocity commands that follow a path, with config.controller
+// deciding how it steers. Keeps track of how far along the path the robot has got, and of the
+// PID controller's history, the MPC planner's plan and the DWA planner's speeds, between calls.
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    controller: Controller,
+    speed: f64,
+    lookahead: f64,
+    stanley_gain: f64,
+    heading_gains: (f64, f64, f64),
+    wheelbase: f64,
+    pub progress: f64, // Distance along the path to the robot's projection onto it
+    integral: f64,
+    last_error: Option<f64>,
+    mpc: Mpc,
+    dwa: Dwa,
+}
+
+impl Tracker {
+    // Stanley steers like a car, so it uses an Ackermann robot's wheelbase, and otherwise takes
+    // the footprint's length as the distance between the axles.
+    pub fn new(config: &PlannerConfig, robot: &Robot) -> Self {
+        let wheelbase = match robot {
+            Robot::Ackermann(m) => m.wheelbase,
+            _ => config.robot_size.1 as f64,
+        };
+        return Self {
+            controller: config.controller,
+            speed: config.speed,
+            lookahead: config.lookahead,
+            stanley_gain: config.stanley_gain,
+            heading_gains: config.heading_gains,
+            wheelbase,
+            progress: 0.0,
+            integral: 0.0,
+            last_error: None,
+            mpc: Mpc::new(config),
+            dwa: Dwa::new(config),
+        };
+    }
+
+    // The command for the next `dt` seconds, and where the robot is relative to the path. Only
+    // the MPC and DWA planners look at the obstacles.
+    pub fn command(
+        &mut self,
+        planner: &Planner,
+        playground: &Playground,
+        path: &TrackedPath,
+        pose: &Pose2,
+        dt: f64,
+    ) -> (Control, Projection) {
+        let window = 2.0 * self.lookahead + self.speed * dt;
+        let projection = path.project(pose.x, pose.y, self.progress, window);
+        self.progress = projection.distance;
+
+        // Slow down to stop at the end of the path.
+        let (ex, ey) = path.end();
+        let remaining = (path.length() - self.progress).max((ex - pose.x).hypot(ey - pose.y));
+        let speed = self.speed.min(remaining);
+
+        let (tx, ty) = path.point_at(self.progress + self.lookahead);
+        let (dx, dy) = (tx - pose.x, ty - pose.y);
+        let bearing = angle_diff(pose.theta, dy.atan2(dx));
+        let speed = speed * bearing.cos().max(MIN_SPEED_FRACTION);
+
+        let angular = match self.controller {
+            Controller::PurePursuit => {
+                // The arc through the lookahead point has curvature 2 sin(bearing) / distance.
+                let distance = dx.hypot(dy);
+                match distance > 0.0 {
+                    true => 2.0 * speed * bearing.sin() / distance,
+                    false => 0.0,
+                }
+            }
+            Controller::Stanley => {
+                // Stanley steers the front axle onto the path, so measure the error there.
+                let reach = self.wheelbase / 2.0;
+                let (fx, fy) = (
+                    pose.x + reach * pose.theta.cos(),
+                    pose.y + reach * pose.theta.sin(),
+                );
+                let front = path.project(fx, fy, self.progress, 2.0 * reach);
+                let heading_error = angle_diff(pose.theta, front.heading);
+                let correction = (self.stanley_gain * front.error).atan2(speed + STANLEY_SOFTENING);
+                let steering = (heading_error + correction).clamp(-MAX_STEERING, MAX_STEERING);
+                speed * steering.tan() / self.wheelbase
+            }
+            Controller::PidHeading => {
+                let (p, i, d) = self.heading_gains;
+                self.integral += bearing * dt;
+                let derivative = match self.last_error {
+                    Some(last) => angle_diff(last, bearing) / dt,
+                    None => 0.0,
+                };
+                self.last_error = Some(bearing);
+                p * bearing + i * self.integral + d * derivative
+            }
+            Controller::Mpc => {
+                let control = self
+                    .mpc
+                    .command(planner, playground, path, self.progress, pose, dt);
+                return (control, projection);
+            }
+            Controller::Dwa => {
+                let control = self
+                    .dwa
+                    .command(planner, playground, path, self.progress, pose, dt);
+                return (control, projection);
+            }
+        };
+
+        let control = Control {
+            linear: speed,
+            lateral: 0.0,
+            angular,
+        };
+        return (control, projection);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::Ackermann;
+
+    #[test]
+    fn project() {
+        let path = TrackedPath::new(&[
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(100.0, 0.0, 0.0),
+            Pose2::new(100.0, 0.0, 1.0),
+            Pose2::new(100.0, 100.0, 0.0),
+        ]);
+        assert_eq!(path.length(), 200.0);
+        assert_eq!(path.point_at(150.0), (100.0, 50.0));
+        assert_eq!(path.point_at(500.0), (100.0, 100.0));
+        assert_eq!(path.heading_at(150.0), std::f64::consts::FRAC_PI_2);
+        assert_eq!(path.theta_at(150.0), 0.5);
+        assert_eq!(path.theta_at(-10.0), 0.0);
+
+        // Above the path on screen, a positive (clockwise) turn takes the robot back.
+        let p = path.project(30.0, -10.0, 0.0, 500.0);
+        assert_eq!((p.distance, p.x, p.y, p.heading), (30.0, 30.0, 0.0, 0.0));
+        assert_eq!(p.error, 10.0);
+        let p = path.project(30.0, 10.0, 0.0, 500.0);
+        assert_eq!(p.error, -10.0);
+
+        // The second edge is nearer, but out of the window.
+        let p = path.project(90.0, 50.0, 0.0, 50.0);
+        assert_eq!((p.x, p.y), (90.0, 0.0));
+        let p = path.project(90.0, 50.0, 0.0, 500.0);
+        assert_eq!((p.x, p.y, p.distance), (100.0, 50.0, 150.0));
+    }
+
+    #[test]
+    fn controllers_steer_back_to_the_path() {
+        let playground = Playground::new((600, 200), (0, 100), (500, 100));
+        let planner = Planner::new(&playground);
+        let path = TrackedPath::new(&[Pose2::new(0.0, 100.0, 0.0), Pose2::new(500.0, 100.0, 0.0)]);
+        for controller in [
+            Controller::PurePursuit,
+            Controller::Stanley,
+            Controller::PidHeading,
+            Controller::Mpc,
+            Controller::Dwa,
+        ] {
+            let config = PlannerConfig {
+                controller,
+                ..Default::default()
+            };
+            let mut tracker = Tracker::new(&config, &playground.robot);
+            // Below the path on screen, it turns back anticlockwise.
+            let pose = Pose2::new(50.0, 120.0, 0.0);
+            let (control, projection) = tracker.command(&planner, &playground, &path, &pose, 0.02);
+            assert_eq!(projection.error, -20.0);
+            assert!(control.linear > 0.0, "{controller:?}");
+            assert!(control.angular < 0.0, "{controller:?}");
+
+            // On the path and facing along it, it goes straight, or as near as the MPC planner's
+            // optimisation gets.
+            let mut tracker = Tracker::new(&config, &playground.robot);
+            let pose = Pose2::new(50.0, 100.0, 0.0);
+            let (control, _) = tracker.command(&planner, &playground, &path, &pose, 0.02);
+            assert!(control.angular.abs() < 0.01, "{controller:?}");
+        }
+    }
+
+    #[test]
+    fn stanley_uses_the_wheelbase() {
+        let config = PlannerConfig::default();
+        let car = Ackermann {
+            wheelbase: 60.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            Tracker::new(&config, &Robot::Ackermann(car)).wheelbase,
+            60.0
+        );
+        let tracker = Tracker::new(&config, &Robot::default());
+        assert_eq!(tracker.wheelbase, config.robot_size.1 as f64);
+    }
+}
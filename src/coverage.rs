@@ -0,0 +1,490 @@
+use std::f64::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::PlannerConfig;
+use crate::planner::Planner;
+use crate::playground::{Playground, Rect};
+use crate::pose::Pose2;
+use crate::worker::cancel_monitor;
+
+// Side of the squares coverage is measured on, in pixels.
+const COVERAGE_CELL: i32 = 5;
+
+// One cell of a boustrophedon decomposition: a stretch of free space a sweep line crosses without
+// it splitting or merging. It is made of slabs, left to right, each between two neighbouring
+// obstacle edges, and covers where the robot's centre can be rather than the robot itself.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SweepCell {
+    pub slabs: Vec<Rect>,
+}
+
+impl SweepCell {
+    // The range of y the robot's centre can take at x, if the cell reaches that far. Where two
+    // slabs meet it has to be inside both.
+    pub fn span_at(&self, x: i32) -> Option<(i32, i32)> {
+        let mut span: Option<(i32, i32)> = None;
+        for s in &self.slabs {
+            if x < s.anchor.0 || x > s.anchor.0 + s.size.0 {
+                continue;
+            }
+            let (y0, y1) = (s.anchor.1, s.anchor.1 + s.size.1);
+            span = Some(match span {
+                None => (y0, y1),
+                Some((a, b)) => (a.max(y0), b.min(y1)),
+            });
+        }
+        return span.filter(|(y0, y1)| y0 <= y1);
+    }
+
+    // The lanes a sweep of the cell runs along, left to right: the x of each, at most `spacing`
+    // apart with the first and last on the cell's edges, and the range of y it runs over.
+    pub fn lanes(&self, spacing: f64) -> Vec<(i32, i32, i32)> {
+        let (Some(first), Some(last)) = (self.slabs.first(), self.slabs.last()) else {
+            return Vec::new();
+        };
+        let (left, right) = (first.anchor.0, last.anchor.0 + last.size.0);
+        let gaps = ((right - left) as f64 / spacing).ceil().max(1.0) as i32;
+        return (0..=gaps)
+            .map(|i| left + ((right - left) as f64 * i as f64 / gaps as f64).round() as i32)
+            .filter_map(|x| self.span_at(x).map(|(y0, y1)| (x, y0, y1)))
+            .collect();
+    }
+}
+
+// Boustrophedon cell decomposition of the playground for a robot that stays `half` pixels clear
+// of the obstacles and the edges in x and y. A vertical line swept from left to right stops at
+// every obstacle edge. Between stops the space it crosses is a set of free spans; a span that
+// carries on from exactly one span before it, which carries on into nothing else, grows that
+// cell, and any other starts a new one.
+pub fn decompose(playground: &Playground, half: (i32, i32)) -> Vec<SweepCell> {
+    let (hx, hy) = half;
+    // The robot may touch the top and left edges of the playground, but not the others.
+    let (left, right) = (hx, playground.size.0 - hx - 1);
+    let (top, bottom) = (hy, playground.size.1 - hy - 1);
+    if left > right || top > bottom {
+        return Vec::new();
+    }
+    // The obstacles grown by the robot's half size, as the ranges of x and y they rule out.
+    let obstacles: Vec<(i32, i32, i32, i32)> = playground
+        .get_obstacles()
+        .iter()
+        .map(|o| {
+            return (
+                (o.anchor.0 - hx).max(left),
+                (o.anchor.0 + o.size.0 + hx).min(right),
+                o.anchor.1 - hy,
+                o.anchor.1 + o.size.1 + hy,
+            );
+        })
+        .filter(|&(x0, x1, _, _)| x0 < x1)
+        .collect();
+    let mut stops: Vec<i32> = obstacles.iter().flat_map(|o| [o.0, o.1]).collect();
+    stops.extend([left, right]);
+    stops.sort();
+    stops.dedup();
+
+    let overlaps = |a: &(i32, i32), b: &(i32, i32)| a.0 < b.1 && b.0 < a.1;
+    let mut cells: Vec<SweepCell> = Vec::new();
+    let mut previous: Vec<((i32, i32), usize)> = Vec::new(); // The last stop's spans and cells
+    for w in stops.windows(2) {
+        let (x0, x1) = (w[0], w[1]);
+        // The free spans between these two stops. Every obstacle either covers all of the slab
+        // or none of it, since its edges are stops too.
+        let mut blocked: Vec<(i32, i32)> = obstacles
+            .iter()
+            .filter(|o| o.0 < x1 && x0 < o.1)
+            .map(|o| (o.2, o.3))
+            .collect();
+        blocked.sort();
+        let mut spans = Vec::new();
+        let mut y = top;
+        for (b0, b1) in blocked {
+            if b0 > y {
+                spans.push((y, b0.min(bottom)));
+            }
+            y = y.max(b1);
+            if y >= bottom {
+                break;
+            }
+        }
+        if y < bottom {
+            spans.push((y, bottom));
+        }
+        spans.retain(|(y0, y1)| y0 < y1);
+
+        let mut current = Vec::new();
+        for span in spans.iter().copied() {
+            let before: Vec<&((i32, i32), usize)> = previous
+                .iter()
+                .filter(|(p, _)| overlaps(p, &span))
+                .collect();
+            let carries_on = match before.as_slice() {
+                [(p, _)] => spans.iter().filter(|s| overlaps(p, s)).count() == 1,
+                _ => false,
+            };
+            let k = match carries_on {
+                true => before[0].1,
+                false => {
+                    cells.push(SweepCell::default());
+                    cells.len() - 1
+                }
+            };
+            // A slab the same height as the one before it just makes that one wider.
+            let slabs = &mut cells[k].slabs;
+            match slabs.last_mut() {
+                Some(s) if s.anchor.1 == span.0 && s.size.1 == span.1 - span.0 => {
+                    s.size.0 = x1 - s.anchor.0;
+                }
+                _ => slabs.push(Rect {
+                    anchor: (x0, span.0),
+                    size: (x1 - x0, span.1 - span.0),
+                }),
+            }
+            current.push((span, k));
+        }
+        previous = current;
+    }
+    return cells;
+}
+
+// A path that sweeps a tool over as much of the playground as the robot can reach: cell by cell
+// of the boustrophedon decomposition, up and down lanes spaced so that neighbouring strips of
+// config.tool_width overlap by config.overlap.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub cells: Vec<SweepCell>,
+    pub order: Vec<usize>,       // The cells in the order they are swept
+    pub unreachable: Vec<usize>, // Cells the planner found no way to
+    pub path: Vec<Pose2>,
+    pub tool_width: f64,
+    pub covered: f64, // Fraction of the free playground the tool passes over
+}
+
+impl Coverage {
+    // Plans a sweep from the playground's start. The robot drives the lanes facing along them,
+    // and moves from lane to lane and cell to cell straight across, round one corner or, if
+    // neither is clear, along whatever path the planner finds, seeded from `seed`.
+    pub fn plan(playground: &Playground, config: &PlannerConfig, seed: u64) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        return Self::plan_cancellable(playground, config, seed, &cancel);
+    }
+
+    // Like plan, but stops early, with the sweep so far, once `cancel` is set.
+    pub fn plan_cancellable(
+        playground: &Playground,
+        config: &PlannerConfig,
+        seed: u64,
+        cancel: &Arc<AtomicBool>,
+    ) -> Self {
+        let checker = Planner::with_config(playground, config.clone());
+        // How far the robot reaches from its centre along a lane, rounded as is_valid_pose rounds
+        // it.
+        let footprint = checker.footprint(&Pose2::new(0.0, 0.0, FRAC_PI_2));
+        let half = (-footprint.anchor.0, -footprint.anchor.1);
+        let spacing = config.tool_width * (1.0 - config.overlap);
+        let mut coverage = Self {
+            cells: decompose(playground, half),
+            tool_width: config.tool_width,
+            ..Default::default()
+        };
+        let lanes: Vec<Vec<(i32, i32, i32)>> =
+            coverage.cells.iter().map(|c| c.lanes(spacing)).collect();
+
+        let mut joiner = Joiner {
+            playground,
+            config,
+            checker: &checker,
+            seed,
+            plans: 0,
+            cancel,
+        };
+        let mut path = vec![playground.start_pose()];
+        let mut left: Vec<usize> = (0..lanes.len()).filter(|&k| !lanes[k].is_empty()).collect();
+        while !left.is_empty() && !cancel.load(Ordering::Relaxed) {
+            // Whichever way into a cell left to sweep is nearest: from either side, heading
+            // either way along the first lane.
+            let here = *path.last().unwrap();
+            let (k, sweep) = left
+                .iter()
+                .flat_map(|&k| {
+                    return [(false, false), (false, true), (true, false), (true, true)]
+                        .map(|(reverse, up)| (k, sweep(&lanes[k], reverse, up)));
+                })
+                .min_by(|(_, a), (_, b)| {
+                    return a[0].translation(&here).total_cmp(&b[0].translation(&here));
+                })
+                .unwrap();
+            left.retain(|&j| j != k);
+            let Some(way) = joiner.join(&here, &sweep[0]) else {
+                coverage.unreachable.push(k);
+                continue;
+            };
+            path.extend(&way[1..]);
+            coverage.order.push(k);
+            for w in sweep.windows(2) {
+                // Lanes in a cell can always be joined unless the cell is oddly shaped, in
+                // which case the rest of it is left unswept.
+                let Some(way) = joiner.join(&w[0], &w[1]) else {
+                    break;
+                };
+                path.extend(&way[1..]);
+            }
+        }
+        coverage.path = path;
+        coverage.covered = measure(playground, &coverage.path, config.tool_width);
+        return coverage;
+    }
+
+    pub fn length(&self) -> f64 {
+        return self.path.windows(2).map(|w| w[0].translation(&w[1])).sum();
+    }
+
+    // The figures the stats overlay and `coverage` report for the plan.
+    pub fn lines(&self) -> Vec<String> {
+        return vec![
+            format!("cells: {}", self.cells.len()),
+            format!("unreachable cells: {}", self.unreachable.len()),
+            format!("path length: {:.0}", self.length()),
+            format!("coverage: {:.1}%", 100.0 * self.covered),
+        ];
+    }
+}
+
+// The waypoints of a sweep along `lanes`, left to right or, if `reverse`, right to left, setting
+// off up the first lane (towards larger y) if `up` and down it otherwise, then turning at the end
+// of every lane.
+fn sweep(lanes: &[(i32, i32, i32)], reverse: bool, up: bool) -> Vec<Pose2> {
+    let mut order: Vec<&(i32, i32, i32)> = lanes.iter().collect();
+    if reverse {
+        order.reverse();
+    }
+    let mut poses = Vec::new();
+    let mut up = up;
+    for &&(x, y0, y1) in &order {
+        let (x, y0, y1) = (x as f64, y0 as f64, y1 as f64);
+        match up {
+            true => poses.extend([Pose2::new(x, y0, FRAC_PI_2), Pose2::new(x, y1, FRAC_PI_2)]),
+            false => poses.extend([Pose2::new(x, y1, -FRAC_PI_2), Pose2::new(x, y0, -FRAC_PI_2)]),
+        }
+        up = !up;
+    }
+    return poses;
+}
+
+// Finds ways between poses for a coverage plan, planning only when it has to.
+struct Joiner<'a> {
+    playground: &'a Playground,
+    config: &'a PlannerConfig,
+    checker: &'a Planner,
+    seed: u64,
+    plans: u64, // Plans made so far, so each gets its own seed
+    cancel: &'a Arc<AtomicBool>,
+}
+
+impl Joiner<'_> {
+    // A way from `from` to `to`, starting with `from` and ending with `to`: straight there, round
+    // a corner, or planned, whichever is the first that works.
+    fn join(&mut self, from: &Pose2, to: &Pose2) -> Option<Vec<Pose2>> {
+        let clear = |way: &[Pose2]| {
+            return way
+                .windows(2)
+                .all(|w| self.checker.is_valid_path(self.playground, &w[0], &w[1]));
+        };
+        let corners = [
+            Pose2::new(from.x, to.y, from.theta),
+            Pose2::new(to.x, from.y, from.theta),
+        ];
+        let ways = [vec![*from, *to]]
+            .into_iter()
+            .chain(corners.map(|c| vec![*from, c, *to]));
+        for way in ways {
+            if clear(&way) {
+                return Some(way);
+            }
+        }
+
+        let playground = self.playground.between(from, to);
+        let mut planner = Planner::with_config(&playground, self.config.clone());
+        planner.seed = self.seed.wrapping_add(self.plans);
+        planner.monitor = Some(cancel_monitor(self.cancel));
+        self.plans += 1;
+        planner.compute_path(&playground);
+        if planner.compact_path.is_empty() {
+            return None;
+        }
+        let mut way = vec![*from];
+        way.extend(&planner.compact_path[1..]);
+        *way.last_mut().unwrap() = *to;
+        return Some(way);
+    }
+}
+
+// The fraction of the playground's free space a tool `width` pixels across passes over along
+// `path`, measured on a grid of COVERAGE_CELL squares: each is free if its middle is clear of the
+// obstacles and covered if its middle comes within half the tool's width of the path.
+pub fn measure(playground: &Playground, path: &[Pose2], width: f64) -> f64 {
+    let cell = COVERAGE_CELL as f64;
+    let columns = (playground.size.0 + COVERAGE_CELL - 1) / COVERAGE_CELL;
+    let rows = (playground.size.1 + COVERAGE_CELL - 1) / COVERAGE_CELL;
+    let centre = |i: i32, j: i32| ((i as f64 + 0.5) * cell, (j as f64 + 0.5) * cell);
+    let mut free = vec![true; (columns * rows) as usize];
+    for o in playground.get_obstacles() {
+        let i0 = (o.anchor.0 / COVERAGE_CELL).max(0);
+        let j0 = (o.anchor.1 / COVERAGE_CELL).max(0);
+        let i1 = ((o.anchor.0 + o.size.0) / COVERAGE_CELL).min(columns - 1);
+        let j1 = ((o.anchor.1 + o.size.1) / COVERAGE_CELL).min(rows - 1);
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                let (x, y) = centre(i, j);
+                if o.contains(x, y) {
+                    free[(j * columns + i) as usize] = false;
+                }
+            }
+        }
+    }
+
+    let mut covered = vec![false; free.len()];
+    let radius = width / 2.0;
+    let mut stamp = |x: f64, y: f64| {
+        let i0 = (((x - radius) / cell).floor() as i32).max(0);
+        let j0 = (((y - radius) / cell).floor() as i32).max(0);
+        let i1 = (((x + radius) / cell).floor() as i32).min(columns - 1);
+        let j1 = (((y + radius) / cell).floor() as i32).min(rows - 1);
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                let (cx, cy) = centre(i, j);
+                if (cx - x).hypot(cy - y) <= radius {
+                    covered[(j * columns + i) as usize] = true;
+                }
+            }
+        }
+    };
+    // Stamping the tool every half a grid square along the path.
+    for w in path.windows(2) {
+        let steps = (w[0].translation(&w[1]) / (cell / 2.0)).ceil().max(1.0) as usize;
+        for s in 0..=steps {
+            let p = w[0].interpolate(&w[1], s as f64 / steps as f64);
+            stamp(p.x, p.y);
+        }
+    }
+    if let [p] = path {
+        stamp(p.x, p.y);
+    }
+
+    let total = free.iter().filter(|&&f| f).count();
+    let swept = (0..free.len()).filter(|&k| free[k] && covered[k]).count();
+    return match total {
+        0 => 1.0,
+        _ => swept as f64 / total as f64,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(anchor: (i32, i32), size: (i32, i32)) -> Rect {
+        return Rect { anchor, size };
+    }
+
+    #[test]
+    fn decompose() {
+        // A block in the middle splits the room into four: left of it, above it, below it and
+        // right of it.
+        let mut playground = Playground::new((201, 101), (0, 0), (0, 0));
+        playground.add_obstacles(rect((80, 40), (40, 20)));
+        let cells = super::decompose(&playground, (5, 5));
+        assert_eq!(
+            cells,
+            [
+                vec![rect((5, 5), (70, 90))],
+                vec![rect((75, 5), (50, 30))],
+                vec![rect((75, 65), (50, 30))],
+                vec![rect((125, 5), (70, 90))],
+            ]
+            .map(|slabs| SweepCell { slabs })
+        );
+
+        // A block against the top only narrows the cell.
+        let mut playground = Playground::new((201, 101), (0, 0), (0, 0));
+        playground.add_obstacles(rect((80, 0), (40, 20)));
+        let cells = super::decompose(&playground, (5, 5));
+        assert_eq!(cells.len(), 1);
+        assert_eq!(
+            cells[0].slabs,
+            [
+                rect((5, 5), (70, 90)),
+                rect((75, 25), (50, 70)),
+                rect((125, 5), (70, 90))
+            ]
+        );
+        // Where the slabs meet, the robot has to fit in both.
+        assert_eq!(cells[0].span_at(50), Some((5, 95)));
+        assert_eq!(cells[0].span_at(75), Some((25, 95)));
+        assert_eq!(cells[0].span_at(300), None);
+
+        // Lanes at most 30 apart, on both edges.
+        let lanes = cells[0].lanes(30.0);
+        let xs: Vec<i32> = lanes.iter().map(|l| l.0).collect();
+        assert_eq!(xs, [5, 32, 59, 86, 114, 141, 168, 195]);
+        assert_eq!(lanes[3], (86, 25, 95));
+    }
+
+    #[test]
+    fn sweeps_every_room() {
+        // Two rooms joined by a door in the wall between them.
+        let mut playground = Playground::new((300, 200), (20, 20), (0, 0));
+        playground.add_obstacles(rect((140, 0), (20, 120)));
+        playground.add_obstacles(rect((140, 160), (20, 40)));
+        playground.add_obstacles(rect((60, 80), (30, 30)));
+        let config = PlannerConfig {
+            robot_size: (10, 10),
+            tool_width: 20.0,
+            overlap: 0.25,
+            max_iterations: Some(20_000),
+            ..Default::default()
+        };
+        let checker = Planner::with_config(&playground, config.clone());
+        let coverage = Coverage::plan(&playground, &config, 1);
+        assert!(coverage.unreachable.is_empty());
+        let mut order = coverage.order.clone();
+        order.sort();
+        assert_eq!(order, (0..coverage.cells.len()).collect::<Vec<usize>>());
+        assert!(coverage.covered > 0.95, "{}", coverage.covered);
+        assert_eq!(coverage.path[0], playground.start_pose());
+        for w in coverage.path.windows(2) {
+            assert!(checker.is_valid_path(&playground, &w[0], &w[1]), "{w:?}");
+        }
+        assert!(coverage.lines()[3].starts_with("coverage: 9"));
+
+        // Less overlap sweeps fewer lanes, but narrower strips leave gaps.
+        let sparse = Coverage::plan(
+            &playground,
+            &PlannerConfig {
+                overlap: 0.0,
+                ..config.clone()
+            },
+            1,
+        );
+        assert!(sparse.length() < coverage.length());
+        let narrow = measure(&playground, &coverage.path, 10.0);
+        assert!(narrow < coverage.covered);
+
+        // A room with no way in is left out.
+        playground.add_obstacles(rect((140, 120), (20, 40)));
+        let coverage = Coverage::plan(&playground, &config, 1);
+        assert_eq!(coverage.unreachable.len(), 1);
+        assert!(coverage.covered < 0.6);
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let playground = Playground::new((200, 200), (20, 20), (0, 0));
+        let cancel = Arc::new(AtomicBool::new(true));
+        let coverage =
+            Coverage::plan_cancellable(&playground, &PlannerConfig::default(), 1, &cancel);
+        assert!(coverage.order.is_empty());
+        assert_eq!(coverage.path, vec![playground.start_pose()]);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::config::{DwaWeights, PlannerConfig};
+use crate::controller::TrackedPath;
+use crate::planner::Planner;
+use crate::playground::{Playground, Rect};
+use crate::pose::Pose2;
+use crate::robot::{Control, RobotModel};
+
+// Arcs are checked for collisions at least this often, in pixels travelled at full speed.
+const COLLISION_SPACING: f64 = 2.0;
+
+// The robot counts as hitting an obstacle once its bounding box comes this close, in pixels, which
+// covers is_valid_pose rounding the box out to whole pixels and the robot's motion between checks.
+const COLLISION_MARGIN: f64 = 2.0;
+
+// Arcs aim for the point this many horizons' drive further along the path.
+const GOAL_FRACTION: f64 = 0.5;
+
+// How much being off the path counts against getting close to that point. Well under one, so that
+// swerving round an obstacle can still pay off.
+const ERROR_WEIGHT: f64 = 0.25;
+
+// Slow arcs are checked for collisions over at most this many horizons.
+const LOOKAHEAD_LIMIT: usize = 4;
+
+// Arcs slower than this, in pixels per second, aren't checked for collisions beyond the horizon.
+const MIN_SPEED: f64 = 1.0;
+
+// Clearance beyond this, in pixels, scores no better.
+const CLEARANCE_CAP: f64 = 15.0;
+
+// The Dynamic Window Approach. Every tick it tries a grid of config.dwa_samples linear by
+// config.dwa_samples angular target speeds, and simulates the robot model speeding up or slowing
+// down to each, within config.max_acceleration and config.max_angular_acceleration, for
+// config.dwa_horizon seconds. Only the first tick of the best arc is driven, so each command is
+// within the dynamic window of speeds reachable from the last one. Speeds that would run the
+// robot into an obstacle before it could brake to a stop are ruled out, arcs that run into
+// something are only taken when they all do, and the rest are scored by config.dwa_weights for
+// how close they get to a point further along the path, how clear of obstacles they stay and how
+// fast they go. It only looks at what is around the robot now, so it dodges obstacles the path
+// was planned without, but it only drives forwards and never sideways.
+#[derive(Clone, Debug)]
+pub struct Dwa {
+    horizon: f64,
+    samples: usize,
+    weights: DwaWeights,
+    speed: f64,
+    max_angular_speed: f64,
+    max_acceleration: f64,
+    max_angular_acceleration: f64,
+    velocity: Control, // The last command
+}
+
+// What the arcs are simulated against, which stays the same for a whole tick.
+struct Surroundings<'a> {
+    planner: &'a Planner,
+    playground: &'a Playground,
+    model: &'a dyn RobotModel,
+    obstacles: Vec<Rect>, // Those within reach of the robot
+    path: &'a TrackedPath,
+    progress: f64,
+}
+
+// The outcome of simulating one command.
+struct Arc {
+    gained: f64, // How close it gets to the point it aims for, less how far off the path it is
+    clearance: f64, // The least room between the robot and an obstacle on the way, up to the cap
+    collision: Option<f64>, // Pixels travelled before it hits something, if it does
+}
+
+impl Dwa {
+    pub fn new(config: &PlannerConfig) -> Self {
+        return Self {
+            horizon: config.dwa_horizon,
+            samples: config.dwa_samples,
+            weights: config.dwa_weights,
+            speed: config.speed,
+            max_angular_speed: config.max_angular_speed,
+            max_acceleration: config.max_acceleration,
+            max_angular_acceleration: config.max_angular_acceleration,
+            velocity: Control::default(),
+        };
+    }
+
+    // The command for the next `dt` seconds, with the robot `progress` pixels along the path.
+    pub fn command(
+        &mut self,
+        planner: &Planner,
+        playground: &Playground,
+        path: &TrackedPath,
+        progress: f64,
+        pose: &Pose2,
+        dt: f64,
+    ) -> Control {
+        let reach = self.speed * self.horizon;
+        let (xs, ys) = planner.half_extents(pose.theta);
+        let surroundings = Surroundings {
+            planner,
+            playground,
+            model: playground.robot.model(),
+            obstacles: playground.obstacles_near(
+                pose.x,
+                pose.y,
+                reach + xs.hypot(ys) + CLEARANCE_CAP,
+            ),
+            path,
+            progress,
+        };
+
+        let (dv, dw) = (
+            self.max_acceleration * dt,
+            self.max_angular_acceleration * dt,
+        );
+
+        let mut best: Option<((bool, f64), Control)> = None;
+        for i in 0..self.samples {
+            for j in 0..self.samples {
+                let (a, b) = (
+                    i as f64 / (self.samples - 1) as f64,
+                    j as f64 / (self.samples - 1) as f64,
+                );
+                let target = Control {
+                    linear: self.speed * a,
+                    lateral: 0.0,
+                    angular: self.max_angular_speed * (2.0 * b - 1.0),
+                };
+                let arc = self.simulate(&surroundings, pose, &target);
+                // The dynamic window: the robot only gets as far towards the target by the next
+                // tick as it can accelerate.
+                let control =
+                    surroundings
+                        .model
+                        .limit(&self.towards(&self.velocity, &target, dv, dw));
+
+                // Only speeds the robot can still brake from before it hits something are allowed.
+                let stopping = control.linear.powi(2) / (2.0 * self.max_acceleration);
+                if arc.collision.is_some_and(|d| stopping >= d) {
+                    continue;
+                }
+
+                let score = self.weights.progress * arc.gained / reach
+                    + self.weights.clearance * arc.clearance / CLEARANCE_CAP
+                    + self.weights.speed * target.linear / self.speed;
+                // An arc that runs into something is only taken if they all do.
+                let rank = (arc.collision.is_none(), score);
+                if best.is_none_or(|(r, _)| rank > r) {
+                    best = Some((rank, control));
+                }
+            }
+        }
+
+        // With nowhere safe to go, brake as hard as possible.
+        self.velocity = match best {
+            Some((_, control)) => control,
+            None => Control {
+                linear: (self.velocity.linear - dv).max(0.0),
+                lateral: 0.0,
+                angular: 0.0,
+            },
+        };
+        return self.velocity;
+    }
+
+    // Drives the robot for self.horizon seconds, speeding up or slowing down to the target as
+    // fast as it can and then holding it, stopping short if it hits something. An arc is as good
+    // as the best point on it, so one that passes through the goal isn't marked down for carrying
+    // on past it. Slow arcs are checked for collisions further on, until they have covered as
+    // much ground as a full-speed one, so that creeping towards an obstacle doesn't look safe just
+    // because the robot won't reach it yet.
+    fn simulate(&self, surroundings: &Surroundings, pose: &Pose2, target: &Control) -> Arc {
+        let Surroundings {
+            planner,
+            playground,
+            model,
+            obstacles,
+            path,
+            progress,
+        } = surroundings;
+        let reach = self.speed * self.horizon;
+        let substeps = (reach / COLLISION_SPACING).ceil().max(1.0) as usize;
+        let dt = self.horizon / substeps as f64;
+        let (gx, gy) = path.point_at(progress + GOAL_FRACTION * reach);
+        let (dv, dw) = (
+            self.max_acceleration * dt,
+            self.max_angular_acceleration * dt,
+        );
+        let mut arc = Arc {
+            gained: f64::NEG_INFINITY,
+            clearance: CLEARANCE_CAP,
+            collision: None,
+        };
+        let mut current = *pose;
+        let mut velocity = self.velocity;
+        let mut travelled = 0.0;
+        // Nothing past the end of the path matters.
+        let lookahead = reach.min(path.length() - progress);
+        for k in 0..LOOKAHEAD_LIMIT * substeps {
+            if k >= substeps && (travelled >= lookahead || velocity.linear.abs() < MIN_SPEED) {
+                break;
+            }
+            velocity = model.limit(&self.towards(&velocity, target, dv, dw));
+            let next = model.integrate(&current, &velocity, dt);
+            let clearance = separation(planner, playground, obstacles, &next);
+            if clearance < COLLISION_MARGIN {
+                arc.collision = Some(travelled);
+                break;
+            }
+            travelled += velocity.linear.abs() * dt;
+            arc.clearance = arc.clearance.min(clearance);
+            current = next;
+            if k < substeps {
+                let projection = path.project(next.x, next.y, *progress, 2.0 * reach);
+                let gained = reach
+                    - (gx - next.x).hypot(gy - next.y)
+                    - ERROR_WEIGHT * projection.error.abs();
+                arc.gained = arc.gained.max(gained);
+            }
+        }
+        return arc;
+    }
+
+    // The velocity that gets from `from` as close to `to` as changing the linear speed by at most
+    // `dv` and the angular speed by at most `dw` allows.
+    fn towards(&self, from: &Control, to: &Control, dv: f64, dw: f64) -> Control {
+        return Control {
+            linear: to.linear.clamp(from.linear - dv, from.linear + dv),
+            lateral: 0.0,
+            angular: to.angular.clamp(from.angular - dw, from.angular + dw),
+        };
+    }
+}
+
+// How far the robot's bounding box is from the nearest obstacle or the edge of the playground,
+// or how deep it is inside one, as a negative number.
+fn separation(planner: &Planner, playground: &Playground, obstacles: &[Rect], pose: &Pose2) -> f64 {
+    let (xs, ys) = planner.half_extents(pose.theta);
+    let (left, top, right, bottom) = (pose.x - xs, pose.y - ys, pose.x + xs, pose.y + ys);
+    let (w, h) = (playground.size.0 as f64, playground.size.1 as f64);
+    let mut separation = left.min(top).min(w - right).min(h - bottom);
+    for o in obstacles {
+        let (x0, y0) = (o.anchor.0 as f64, o.anchor.1 as f64);
+        let (x1, y1) = (x0 + o.size.0 as f64, y0 + o.size.1 as f64);
+        let (gx, gy) = ((x0 - right).max(left - x1), (y0 - bottom).max(top - y1));
+        let gap = match gx > 0.0 || gy > 0.0 {
+            true => gx.max(0.0).hypot(gy.max(0.0)),
+            false => gx.max(gy),
+        };
+        separation = separation.min(gap);
+    }
+    return separation;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Controller;
+    use crate::robot::Robot;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn dodges_an_obstacle_on_the_path() {
+        // The path runs straight through an obstacle that was added after it was planned.
+        let mut playground = Playground::new((600, 400), (50, 200), (550, 200));
+        playground.add_obstacles(Rect {
+            anchor: (280, 170),
+            size: (40, 40),
+        });
+        for robot in Robot::defaults() {
+            playground.robot = robot;
+            for controller in [Controller::PurePursuit, Controller::Dwa] {
+                let mut planner = Planner::with_config(
+                    &playground,
+                    PlannerConfig {
+                        controller,
+                        robot_size: (10, 10),
+                        ..Default::default()
+                    },
+                );
+                planner.smooth_path =
+                    vec![Pose2::new(50.0, 200.0, 0.0), Pose2::new(550.0, 200.0, 0.0)];
+                let simulation = Simulation::run(&planner, &playground);
+                let name = format!("{robot:?} {controller:?}");
+                match controller {
+                    Controller::Dwa => {
+                        assert!(simulation.reached_goal, "{name}");
+                        assert_eq!(simulation.collision_time, None, "{name}");
+                    }
+                    _ => assert!(simulation.collision_time.is_some(), "{name}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn separation() {
+        let mut playground = Playground::new((500, 500), (0, 0), (0, 0));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (100, 100),
+        });
+        let planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                robot_size: (10, 20),
+                ..Default::default()
+            },
+        );
+        let obstacles = playground.obstacles_near(250.0, 250.0, 250.0);
+        let gap =
+            |x, y| super::separation(&planner, &playground, &obstacles, &Pose2::new(x, y, 0.0));
+        // Facing along x, the robot's box reaches 5 either side and 10 above and below.
+        assert_eq!(gap(185.0, 250.0), 10.0);
+        assert_eq!(gap(250.0, 185.0), 5.0);
+        assert_eq!(gap(250.0, 250.0), -55.0);
+        assert_eq!(gap(100.0, 13.0), 3.0);
+        assert!((gap(192.0, 186.0) - 5.0).abs() < 1e-9);
+    }
+}
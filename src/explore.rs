@@ -0,0 +1,908 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::config::PlannerConfig;
+use crate::lidar::{Lidar, Ray};
+use crate::planner::Planner;
+use crate::playground::{GoalRegion, Playground, Rect};
+use crate::pose::Pose2;
+use crate::worker::cancel_monitor;
+
+// Gives up on a run that takes more steps than this.
+const MAX_STEPS: usize = 10_000;
+
+// Frontiers with fewer cells than this are left alone, as they are more likely gaps between the
+// rays along the side of an obstacle than a way into somewhere new.
+const MIN_FRONTIER: usize = 3;
+
+// Rays cast round a frontier to count the unknown cells that can be seen from it.
+const GAIN_RAYS: usize = 180;
+
+// What the robot believes about a cell of the map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cell {
+    Unknown,
+    Free,
+    Occupied,
+}
+
+// An occupancy grid of config.belief_cell pixel cells over the playground, built up from range
+// readings. A cell is free once a ray has passed through it, and occupied once a ray has ended in
+// it, which wins over free. The cells under the robot are free whatever the readings say, as it
+// is standing on them. Each cell keeps the changes to what is believed about it, with the scan
+// that made them, so the map can be replayed as it was after any scan.
+#[derive(Clone, Debug)]
+pub struct BeliefMap {
+    pub size: (i32, i32), // Of the playground, in pixels
+    pub cell: i32,        // Side of a cell, in pixels
+    columns: i32,
+    rows: i32,
+    history: Vec<Vec<(usize, Cell)>>, // Each cell's changes, in order
+    scans: usize,                     // Scans taken so far
+}
+
+impl BeliefMap {
+    pub fn new(size: (i32, i32), cell: i32) -> Self {
+        let (columns, rows) = ((size.0 + cell - 1) / cell, (size.1 + cell - 1) / cell);
+        return Self {
+            size,
+            cell,
+            columns,
+            rows,
+            history: vec![Vec::new(); (columns * rows) as usize],
+            scans: 0,
+        };
+    }
+
+    // (columns, rows)
+    pub fn dimensions(&self) -> (i32, i32) {
+        return (self.columns, self.rows);
+    }
+
+    pub fn scans(&self) -> usize {
+        return self.scans;
+    }
+
+    // The cell containing the point, if it is on the map.
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<(i32, i32)> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (i, j) = ((x / self.cell as f64) as i32, (y / self.cell as f64) as i32);
+        return match i < self.columns && j < self.rows {
+            true => Some((i, j)),
+            false => None,
+        };
+    }
+
+    pub fn get(&self, i: i32, j: i32) -> Cell {
+        return self.get_after(i, j, self.scans);
+    }
+
+    // What the map said about the cell once `scans` scans had been taken.
+    pub fn get_after(&self, i: i32, j: i32, scans: usize) -> Cell {
+        let changes = &self.history[(j * self.columns + i) as usize];
+        let i = changes.partition_point(|(s, _)| *s < scans);
+        return match i {
+            0 => Cell::Unknown,
+            _ => changes[i - 1].1,
+        };
+    }
+
+    // Records what the latest scan says about a cell. Returns true if that changed it.
+    fn set(&mut self, (i, j): (i32, i32), cell: Cell) -> bool {
+        let scan = self.scans - 1;
+        let changes = &mut self.history[(j * self.columns + i) as usize];
+        if changes.last().map_or(Cell::Unknown, |c| c.1) == cell {
+            return false;
+        }
+        changes.push((scan, cell));
+        return true;
+    }
+
+    // Marks up the map with a scan taken from `pose`. Returns true if it found any new occupied
+    // cells.
+    pub fn integrate(&mut self, pose: &Pose2, rays: &[Ray]) -> bool {
+        self.scans += 1;
+        let step = self.cell as f64 / 2.0;
+        let mut found = false;
+        for ray in rays {
+            let (dx, dy) = (ray.angle.cos(), ray.angle.sin());
+            // The last cell before the reading may be part of the obstacle, so it is left alone.
+            let free = match ray.hit {
+                true => ray.range - self.cell as f64,
+                false => ray.range,
+            };
+            let mut d = 0.0;
+            while d <= free {
+                if let Some(c) = self.cell_at(pose.x + d * dx, pose.y + d * dy) {
+                    if self.get(c.0, c.1) == Cell::Unknown {
+                        self.set(c, Cell::Free);
+                    }
+                }
+                d += step;
+            }
+            // Half a cell past the reading is inside whatever the ray hit, even if noise made
+            // the reading a little short, unless it was the edge of the playground.
+            let end = ray.range + step;
+            if ray.hit {
+                if let Some(c) = self.cell_at(pose.x + end * dx, pose.y + end * dy) {
+                    found |= self.set(c, Cell::Occupied);
+                }
+            }
+        }
+        return found;
+    }
+
+    // Marks the cells the rectangle overlaps as free, as of the latest scan. Returns true if any
+    // of them were occupied.
+    pub fn clear(&mut self, r: &Rect) -> bool {
+        let mut cleared = false;
+        for (i, j) in self.cells_in(r) {
+            let occupied = self.get(i, j) == Cell::Occupied;
+            cleared |= self.set((i, j), Cell::Free) && occupied;
+        }
+        return cleared;
+    }
+
+    // How many of the cells the rectangle overlaps are still unknown.
+    pub fn unknown_in(&self, r: &Rect) -> usize {
+        return self
+            .cells_in(r)
+            .filter(|&(i, j)| self.get(i, j) == Cell::Unknown)
+            .count();
+    }
+
+    // The cells the rectangle overlaps, leaving out any beyond the edge of the map.
+    fn cells_in(&self, r: &Rect) -> impl Iterator<Item = (i32, i32)> {
+        let cell = self.cell;
+        let (i0, j0) = ((r.anchor.0 / cell).max(0), (r.anchor.1 / cell).max(0));
+        let i1 = ((r.anchor.0 + r.size.0 - 1) / cell).min(self.columns - 1);
+        let j1 = ((r.anchor.1 + r.size.1 - 1) / cell).min(self.rows - 1);
+        return (j0..=j1).flat_map(move |j| (i0..=i1).map(move |i| (i, j)));
+    }
+
+    // The fraction of the cells that are known to be free or occupied.
+    pub fn known(&self) -> f64 {
+        let known = self.history.iter().filter(|c| !c.is_empty()).count();
+        return known as f64 / self.history.len() as f64;
+    }
+
+    // The occupied cells, as rectangles in pixels. Joining neighbouring cells keeps the number of
+    // obstacles down.
+    pub fn occupied_rects(&self) -> Vec<Rect> {
+        return self.runs(|cell| cell == Cell::Occupied, self.scans);
+    }
+
+    // Rectangles covering the cells that pass `keep`, as they were after `scans` scans. Runs of
+    // such cells along each row are joined with an identical run in the row above, so the side
+    // of an obstacle becomes one tall rectangle.
+    pub fn runs(&self, keep: impl Fn(Cell) -> bool, scans: usize) -> Vec<Rect> {
+        let mut rects: Vec<Rect> = Vec::new();
+        let mut above: Vec<usize> = Vec::new(); // Rectangles reaching down to the previous row
+        for j in 0..self.rows {
+            let mut current = Vec::new();
+            let mut i = 0;
+            while i < self.columns {
+                if !keep(self.get_after(i, j, scans)) {
+                    i += 1;
+                    continue;
+                }
+                let first = i;
+                while i < self.columns && keep(self.get_after(i, j, scans)) {
+                    i += 1;
+                }
+                let (x, width) = (first * self.cell, (i - first) * self.cell);
+                let joined = above
+                    .iter()
+                    .find(|&&k| rects[k].anchor.0 == x && rects[k].size.0 == width);
+                match joined {
+                    Some(&k) => {
+                        rects[k].size.1 += self.cell;
+                        current.push(k);
+                    }
+                    None => {
+                        rects.push(Rect {
+                            anchor: (x, j * self.cell),
+                            size: (width, self.cell),
+                        });
+                        current.push(rects.len() - 1);
+                    }
+                }
+            }
+            above = current;
+        }
+        return rects;
+    }
+
+    // The middle of a cell, in pixels.
+    pub fn centre(&self, (i, j): (i32, i32)) -> (f64, f64) {
+        let half = self.cell as f64 / 2.0;
+        return ((i * self.cell) as f64 + half, (j * self.cell) as f64 + half);
+    }
+
+    // Is the cell on the frontier after `scans` scans: known to be free, with an unknown cell
+    // beside it?
+    pub fn is_frontier(&self, (i, j): (i32, i32), scans: usize) -> bool {
+        if self.get_after(i, j, scans) != Cell::Free {
+            return false;
+        }
+        return [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(di, dj)| {
+            let (ni, nj) = (i + di, j + dj);
+            return ni >= 0
+                && nj >= 0
+                && ni < self.columns
+                && nj < self.rows
+                && self.get_after(ni, nj, scans) == Cell::Unknown;
+        });
+    }
+
+    // The frontier cells after `scans` scans, grouped into frontiers of touching cells, leaving
+    // out ones too small to be worth a visit.
+    pub fn frontiers(&self, scans: usize) -> Vec<Vec<(i32, i32)>> {
+        let mut seen = vec![false; self.history.len()];
+        let mut frontiers = Vec::new();
+        for j in 0..self.rows {
+            for i in 0..self.columns {
+                if seen[(j * self.columns + i) as usize] || !self.is_frontier((i, j), scans) {
+                    continue;
+                }
+                let mut frontier = Vec::new();
+                let mut queue = VecDeque::from([(i, j)]);
+                seen[(j * self.columns + i) as usize] = true;
+                while let Some((ci, cj)) = queue.pop_front() {
+                    frontier.push((ci, cj));
+                    for (di, dj) in [
+                        (-1, -1),
+                        (0, -1),
+                        (1, -1),
+                        (-1, 0),
+                        (1, 0),
+                        (-1, 1),
+                        (0, 1),
+                        (1, 1),
+                    ] {
+                        let (ni, nj) = (ci + di, cj + dj);
+                        if ni < 0 || nj < 0 || ni >= self.columns || nj >= self.rows {
+                            continue;
+                        }
+                        let k = (nj * self.columns + ni) as usize;
+                        if !seen[k] && self.is_frontier((ni, nj), scans) {
+                            seen[k] = true;
+                            queue.push_back((ni, nj));
+                        }
+                    }
+                }
+                if frontier.len() >= MIN_FRONTIER {
+                    frontiers.push(frontier);
+                }
+            }
+        }
+        return frontiers;
+    }
+
+    // How many unknown cells a scan from (x, y) could see within `range`: rays are marched out
+    // through free and unknown cells until they reach an occupied one or the edge of the map.
+    pub fn gain(&self, x: f64, y: f64, range: f64) -> usize {
+        let mut counted = vec![false; self.history.len()];
+        let mut gain = 0;
+        let step = self.cell as f64 / 2.0;
+        for r in 0..GAIN_RAYS {
+            let angle = std::f64::consts::TAU * r as f64 / GAIN_RAYS as f64;
+            let (dx, dy) = (angle.cos(), angle.sin());
+            let mut d = 0.0;
+            while d <= range {
+                let Some((i, j)) = self.cell_at(x + d * dx, y + d * dy) else {
+                    break;
+                };
+                let k = (j * self.columns + i) as usize;
+                match self.get(i, j) {
+                    Cell::Occupied => break,
+                    Cell::Unknown if !counted[k] => {
+                        counted[k] = true;
+                        gain += 1;
+                    }
+                    _ => (),
+                }
+                d += step;
+            }
+        }
+        return gain;
+    }
+
+    // The playground as the robot believes it to be: only the obstacles it has seen, with
+    // everything it hasn't seen taken to be free.
+    pub fn to_playground(&self, truth: &Playground, start: &Pose2) -> Playground {
+        let xy = (start.x.round() as i32, start.y.round() as i32);
+        let mut playground = Playground::new(self.size, xy, truth.goal);
+        playground.start_heading = start.theta;
+        playground.goal_heading = truth.goal_heading;
+        playground.goal_region = truth.goal_region.clone();
+        playground.robot = truth.robot;
+        for r in self.occupied_rects() {
+            playground.add_obstacles(r);
+        }
+        return playground;
+    }
+}
+
+// One step of an exploration run.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExploreSample {
+    pub step: usize,
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+    pub distance: f64,  // Pixels travelled so far
+    pub known: f64,     // Fraction of the map known after the scan at this step
+    pub replans: usize, // Times the robot has had to replan so far
+}
+
+impl ExploreSample {
+    pub fn pose(&self) -> Pose2 {
+        return Pose2::new(self.x, self.y, self.theta);
+    }
+}
+
+// What an exploration run is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mission {
+    Goal,      // Get to the playground's goal
+    Frontiers, // Map as much of the playground as the robot can reach
+}
+
+// A run through a map the robot starts out knowing nothing about. It scans with its lidar,
+// plans with the obstacles it has seen, optimistically taking unknown space to be free, and walks
+// the plan config.explore_step pixels at a time, scanning after each step. When the new readings
+// put an obstacle in the way of the rest of the plan, it plans again from where it is.
+//
+// With Mission::Goal it plans to the playground's goal. With Mission::Frontiers it sets off for
+// the frontier between the free space it has seen and the space it hasn't that is most worth
+// visiting: the one a scan from which would see the most unknown cells, less
+// config.frontier_weight cells for every pixel it is away. When it gets there, or a scan on the
+// way reveals the frontier, it picks the next one, until there are none left it can reach.
+#[derive(Clone, Debug)]
+pub struct Exploration {
+    pub mission: Mission,
+    pub samples: Vec<ExploreSample>, // One per step, starting with the start pose
+    pub plans: Vec<(usize, Vec<Pose2>)>, // Each plan, and the step it was made at
+    pub targets: Vec<(usize, (i32, i32))>, // Each frontier cell set off for, and the step
+    pub unreachable: usize,          // Frontiers the planner found no way to
+    pub belief: BeliefMap,           // As it was at the end of the run
+    pub reached_goal: bool,
+    pub collided: bool, // Did the robot run into an obstacle it hadn't seen?
+}
+
+impl Exploration {
+    pub fn run(truth: &Playground, config: &PlannerConfig, mission: Mission, seed: u64) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        return Self::run_cancellable(truth, config, mission, seed, &cancel);
+    }
+
+    // Like run, but stops early, with the run so far, once `cancel` is set.
+    pub fn run_cancellable(
+        truth: &Playground,
+        config: &PlannerConfig,
+        mission: Mission,
+        seed: u64,
+        cancel: &Arc<AtomicBool>,
+    ) -> Self {
+        let lidar = Lidar::new(&config.lidar);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut exploration = Self {
+            mission,
+            samples: Vec::new(),
+            plans: Vec::new(),
+            targets: Vec::new(),
+            unreachable: 0,
+            belief: BeliefMap::new(truth.size, config.belief_cell),
+            reached_goal: false,
+            collided: false,
+        };
+        let belief = &mut exploration.belief;
+
+        let mut pose = truth.start_pose();
+        let mut distance = 0.0;
+        let mut path: Vec<Pose2> = Vec::new(); // What is left of the current plan
+        let mut target = match mission {
+            Mission::Goal => Some(truth.goal_pose()),
+            Mission::Frontiers => None,
+        };
+        let mut frontier: Vec<(i32, i32)> = Vec::new(); // The cells of the frontier being visited
+        let mut visited: Vec<(i32, i32)> = Vec::new(); // Frontier cells not to try again
+        let mut known = belief.to_playground(truth, &pose);
+        let checker = Planner::with_config(truth, config.clone());
+        for step in 0..MAX_STEPS {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let found = belief.integrate(&pose, &lidar.scan(truth, &pose, &mut rng));
+            if belief.clear(&checker.footprint(&pose)) || found {
+                known = belief.to_playground(truth, &pose);
+            }
+            if mission == Mission::Frontiers {
+                let cell = target.and_then(|t| belief.cell_at(t.x, t.y));
+                let arrived = path.len() == 1;
+                if arrived || cell.is_some_and(|c| !belief.is_frontier(c, belief.scans())) {
+                    target = None;
+                    path.clear();
+                }
+            }
+            let blocked = path
+                .windows(2)
+                .any(|w| !checker.is_valid_path(&known, &w[0], &w[1]));
+            if path.is_empty() || blocked {
+                path.clear();
+                // A cancelled run is left with no plan, so it stops here.
+                while !cancel.load(Ordering::Relaxed) {
+                    if target.is_none() {
+                        match Self::next_frontier(belief, &known, &checker, &pose, &visited) {
+                            None => break,
+                            Some((t, cells)) => {
+                                let xy = (t.x as i32, t.y as i32);
+                                visited.push(xy);
+                                exploration.targets.push((step, xy));
+                                target = Some(t);
+                                frontier = cells;
+                            }
+                        }
+                    }
+                    let goal = target.unwrap();
+                    known.goal = (goal.x.round() as i32, goal.y.round() as i32);
+                    known.goal_heading = goal.theta;
+                    if mission == Mission::Frontiers {
+                        known.goal_region = GoalRegion::default();
+                    }
+                    let round = exploration.plans.len() as u64;
+                    let seed = seed.wrapping_add(round);
+                    match Self::plan(&mut known, config, &pose, seed, cancel) {
+                        Some(plan) => {
+                            exploration.plans.push((step, plan.clone()));
+                            path = plan;
+                            break;
+                        }
+                        None if mission == Mission::Frontiers => {
+                            // Its neighbours are most likely out of reach too.
+                            exploration.unreachable += 1;
+                            visited.append(&mut frontier);
+                            target = None;
+                        }
+                        None => break,
+                    }
+                }
+            }
+            exploration.samples.push(ExploreSample {
+                step,
+                x: pose.x,
+                y: pose.y,
+                theta: pose.theta,
+                distance,
+                known: belief.known(),
+                replans: exploration.plans.len().saturating_sub(1),
+            });
+            if path.len() < 2 {
+                // At the goal, with no way there or with nowhere left to explore.
+                exploration.reached_goal = mission == Mission::Goal && !path.is_empty();
+                break;
+            }
+
+            let next = advance(&mut path, config.explore_step);
+            if !checker.is_valid_path(truth, &pose, &next) {
+                exploration.collided = true;
+                break;
+            }
+            distance += pose.translation(&next);
+            pose = next;
+        }
+        return exploration;
+    }
+
+    // The frontier most worth setting off for next, leaving out cells in `visited`: the pose to
+    // head for, and the frontier's cells so they can all be given up on if there's no way there.
+    fn next_frontier(
+        belief: &BeliefMap,
+        known: &Playground,
+        checker: &Planner,
+        pose: &Pose2,
+        visited: &[(i32, i32)],
+    ) -> Option<(Pose2, Vec<(i32, i32)>)> {
+        let config = &checker.config;
+        let mut best: Option<(Pose2, Vec<(i32, i32)>)> = None;
+        let mut best_utility = f64::NEG_INFINITY;
+        for frontier in belief.frontiers(belief.scans()) {
+            // Targets are rounded to whole pixels, as the planner's goal is.
+            let cells: Vec<(i32, i32)> = frontier
+                .iter()
+                .map(|&c| {
+                    let (x, y) = belief.centre(c);
+                    return (x.round() as i32, y.round() as i32);
+                })
+                .collect();
+            let n = cells.len() as f64;
+            let (mx, my) = cells.iter().fold((0.0, 0.0), |(x, y), &(cx, cy)| {
+                return (x + cx as f64 / n, y + cy as f64 / n);
+            });
+            // The cell nearest the middle of the frontier that the robot fits on, facing whichever
+            // way keeps most of it in space it has already seen. Unknown space is taken to be
+            // free, so a robot across a corridor would fit, but it could never turn to get there.
+            // The robot is the same both ways round, so half a turn covers every heading.
+            let headings: Vec<f64> = (0..180)
+                .step_by(config.angle_step as usize)
+                .map(|d| (d as f64).to_radians())
+                .collect();
+            let target = cells
+                .iter()
+                .filter(|t| !visited.contains(t))
+                .filter_map(|&(x, y)| {
+                    let unknown = |p: &Pose2| belief.unknown_in(&checker.footprint(p));
+                    return headings
+                        .iter()
+                        .map(|&theta| Pose2::new(x as f64, y as f64, theta))
+                        .filter(|p| checker.is_valid_pose(known, p))
+                        .min_by_key(unknown);
+                })
+                .min_by(|a, b| {
+                    let da = (a.x - mx).hypot(a.y - my);
+                    let db = (b.x - mx).hypot(b.y - my);
+                    return da.total_cmp(&db);
+                });
+            let Some(target) = target else {
+                continue;
+            };
+            let gain = belief.gain(target.x, target.y, config.lidar.max_range) as f64;
+            let utility = gain - config.frontier_weight * target.translation(pose);
+            if best.is_none() || utility > best_utility {
+                best = Some((target, cells));
+                best_utility = utility;
+            }
+        }
+        return best;
+    }
+
+    // A plan from `pose` to the known playground's goal, starting with `pose` itself, or None if
+    // the planner can't find one. Every plan gets its own seed, so a run replays exactly.
+    fn plan(
+        known: &mut Playground,
+        config: &PlannerConfig,
+        pose: &Pose2,
+        seed: u64,
+        cancel: &Arc<AtomicBool>,
+    ) -> Option<Vec<Pose2>> {
+        known.start = (pose.x.round() as i32, pose.y.round() as i32);
+        known.start_heading = pose.theta;
+        let mut planner = Planner::with_config(known, config.clone());
+        planner.seed = seed;
+        planner.monitor = Some(cancel_monitor(cancel));
+        planner.compute_path(known);
+        if planner.compact_path.is_empty() {
+            return None;
+        }
+        let mut path = vec![*pose];
+        path.extend(planner.compact_path);
+        return Some(path);
+    }
+
+    pub fn replans(&self) -> usize {
+        return self.plans.len().saturating_sub(1);
+    }
+
+    // The step the robot is at `t` seconds in, moving at `speed` pixels per second.
+    pub fn step_at(&self, t: f64, speed: f64, step: f64) -> usize {
+        let i = (t.max(0.0) * speed / step) as usize;
+        return i.min(self.samples.len().saturating_sub(1));
+    }
+
+    // The plan the robot is following at a step.
+    pub fn plan_at(&self, step: usize) -> Option<&[Pose2]> {
+        let i = self.plans.partition_point(|(s, _)| *s <= step);
+        return match i {
+            0 => None,
+            _ => Some(&self.plans[i - 1].1),
+        };
+    }
+
+    // The frontier cell the robot is making for at a step.
+    pub fn target_at(&self, step: usize) -> Option<(i32, i32)> {
+        let i = self.targets.partition_point(|(s, _)| *s <= step);
+        return match i {
+            0 => None,
+            _ => Some(self.targets[i - 1].1),
+        };
+    }
+
+    // A human-readable summary for the stats overlay and the command line.
+    pub fn lines(&self) -> Vec<String> {
+        let last = self.samples.last();
+        let mut lines = vec![
+            format!("steps: {}", self.samples.len()),
+            format!("distance: {:.0}", last.map_or(0.0, |s| s.distance)),
+            format!("replans: {}", self.replans()),
+            format!("map known: {:.1}%", 100.0 * last.map_or(0.0, |s| s.known)),
+        ];
+        match self.mission {
+            Mission::Goal => lines.push(format!("reached goal: {}", self.reached_goal)),
+            Mission::Frontiers => {
+                lines.push(format!("frontiers visited: {}", self.targets.len()));
+                lines.push(format!("unreachable frontiers: {}", self.unreachable));
+            }
+        }
+        lines.push(format!("collided: {}", self.collided));
+        return lines;
+    }
+}
+
+// Moves up to `distance` pixels down the path, dropping the waypoint it leaves, and returns the
+// new pose, which becomes the path's first point. The robot turns on the spot to the heading of
+// the waypoint it is heading for and holds it along the way, and stops at each waypoint, so every
+// step lies within the box the planner checked for that leg.
+fn advance(path: &mut Vec<Pose2>, distance: f64) -> Pose2 {
+    while path.len() >= 2 && path[0].translation(&path[1]) < 1e-9 {
+        path.remove(0);
+    }
+    if path.len() < 2 {
+        return path[0];
+    }
+    let length = path[0].translation(&path[1]);
+    match length > distance {
+        true => {
+            path[0] = Pose2 {
+                theta: path[1].theta,
+                ..path[0].interpolate(&path[1], distance / length)
+            }
+        }
+        false => {
+            path.remove(0);
+        }
+    }
+    return path[0];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LidarConfig;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn integrate() {
+        let mut belief = BeliefMap::new((100, 50), 10);
+        assert_eq!(belief.dimensions(), (10, 5));
+        assert_eq!(belief.known(), 0.0);
+
+        // One ray hitting something 37 pixels to the right, and one running out of range
+        // upwards. Half a cell further on is in the next cell along.
+        let pose = Pose2::new(5.0, 25.0, 0.0);
+        let rays = [
+            Ray {
+                angle: 0.0,
+                range: 37.0,
+                hit: true,
+            },
+            Ray {
+                angle: -PI / 2.0,
+                range: 20.0,
+                hit: false,
+            },
+        ];
+        assert!(belief.integrate(&pose, &rays));
+        assert_eq!(belief.get(0, 2), Cell::Free);
+        assert_eq!(belief.get(3, 2), Cell::Free);
+        assert_eq!(belief.get(4, 2), Cell::Occupied);
+        assert_eq!(belief.get(5, 2), Cell::Unknown);
+        assert_eq!(belief.get(0, 0), Cell::Free);
+        assert_eq!(belief.get(1, 1), Cell::Unknown);
+        assert_eq!(belief.known(), 7.0 / 50.0);
+        assert_eq!(
+            belief.occupied_rects(),
+            vec![Rect {
+                anchor: (40, 20),
+                size: (10, 10),
+            }]
+        );
+
+        // Seeing the same obstacle again finds nothing new, and it stays occupied even when a
+        // ray passes through its cell.
+        assert!(!belief.integrate(&pose, &rays[..1]));
+        let through = Ray {
+            angle: 0.0,
+            range: 60.0,
+            hit: false,
+        };
+        belief.integrate(&pose, &[through]);
+        assert_eq!(belief.get(4, 2), Cell::Occupied);
+
+        // The first scan alone knew nothing past the obstacle.
+        assert_eq!(belief.get_after(5, 2, 1), Cell::Unknown);
+        assert_eq!(belief.get_after(5, 2, 3), Cell::Free);
+        assert_eq!(belief.get_after(4, 2, 0), Cell::Unknown);
+
+        // Standing on the cell clears it, from the latest scan on.
+        let footprint = Rect {
+            anchor: (42, 22),
+            size: (6, 6),
+        };
+        assert!(belief.clear(&footprint));
+        assert!(!belief.clear(&footprint));
+        assert_eq!(belief.get(4, 2), Cell::Free);
+        assert_eq!(belief.get_after(4, 2, 2), Cell::Occupied);
+        assert!(belief.occupied_rects().is_empty());
+    }
+
+    #[test]
+    fn runs() {
+        let mut belief = BeliefMap::new((30, 30), 10);
+        let rays = [Ray {
+            angle: 0.0,
+            range: 30.0,
+            hit: false,
+        }];
+        belief.integrate(&Pose2::new(0.0, 5.0, 0.0), &rays);
+        // The two unknown rows join into one rectangle.
+        let unknown = belief.runs(|c| c == Cell::Unknown, belief.scans());
+        assert_eq!(
+            unknown,
+            vec![Rect {
+                anchor: (0, 10),
+                size: (30, 20),
+            }]
+        );
+    }
+
+    #[test]
+    fn frontiers() {
+        let mut belief = BeliefMap::new((50, 50), 10);
+        // Three free cells across the middle, and one on its own in the corner.
+        let across = Ray {
+            angle: 0.0,
+            range: 20.0,
+            hit: false,
+        };
+        belief.integrate(&Pose2::new(25.0, 25.0, 0.0), &[across]);
+        let corner = Ray {
+            range: 0.0,
+            ..across
+        };
+        belief.integrate(&Pose2::new(5.0, 45.0, 0.0), &[corner]);
+        assert!(belief.is_frontier((2, 2), 2));
+        assert!(belief.is_frontier((0, 4), 2));
+        assert!(!belief.is_frontier((1, 1), 2));
+        assert!(!belief.is_frontier((2, 2), 0));
+        assert_eq!(belief.frontiers(2), vec![vec![(2, 2), (3, 2), (4, 2)]]);
+        assert_eq!(belief.centre((3, 2)), (35.0, 25.0));
+    }
+
+    #[test]
+    fn gain() {
+        let mut belief = BeliefMap::new((100, 100), 10);
+        assert_eq!(belief.gain(5.0, 5.0, 1000.0), 100);
+        assert!(belief.gain(5.0, 5.0, 20.0) < 10);
+
+        // A wall down the middle hides the far half of the map.
+        for j in 0..10 {
+            let ray = Ray {
+                angle: 0.0,
+                range: 5.0,
+                hit: true,
+            };
+            belief.integrate(&Pose2::new(45.0, (j * 10 + 5) as f64, 0.0), &[ray]);
+        }
+        assert_eq!(belief.get(5, 3), Cell::Occupied);
+        assert_eq!(belief.gain(5.0, 5.0, 1000.0), 50);
+    }
+
+    #[test]
+    fn advance() {
+        let mut path = vec![
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(10.0, 0.0, 1.0),
+            Pose2::new(10.0, 10.0, 2.0),
+        ];
+        // Turned to the heading of the next waypoint, and stopping when it gets there.
+        assert_eq!(super::advance(&mut path, 4.0), Pose2::new(4.0, 0.0, 1.0));
+        assert_eq!(super::advance(&mut path, 10.0), Pose2::new(10.0, 0.0, 1.0));
+        assert_eq!(path.len(), 2);
+        assert_eq!(super::advance(&mut path, 4.0), Pose2::new(10.0, 4.0, 2.0));
+        assert_eq!(super::advance(&mut path, 10.0), Pose2::new(10.0, 10.0, 2.0));
+        assert_eq!(path.len(), 1);
+    }
+
+    #[test]
+    fn replans_round_an_unseen_wall() {
+        // A wall across most of the way to the goal, which the robot can't see from the start.
+        let mut playground = Playground::new((300, 300), (30, 150), (270, 150));
+        playground.add_obstacles(Rect {
+            anchor: (140, 40),
+            size: (20, 260),
+        });
+        let config = PlannerConfig {
+            robot_size: (10, 10),
+            lidar: LidarConfig {
+                max_range: 60.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let exploration = Exploration::run(&playground, &config, Mission::Goal, 1);
+        assert!(exploration.reached_goal);
+        assert!(!exploration.collided);
+        assert!(exploration.replans() >= 1);
+
+        // The first plan went straight through the wall it didn't know about.
+        let first = &exploration.plans[0].1;
+        let planner = Planner::with_config(&playground, config.clone());
+        assert!(first
+            .windows(2)
+            .any(|w| !planner.is_valid_path(&playground, &w[0], &w[1])));
+
+        // The robot saw more of the map as it went, and finished at the goal.
+        let known: Vec<f64> = exploration.samples.iter().map(|s| s.known).collect();
+        assert!(known.windows(2).all(|w| w[0] <= w[1]));
+        assert!(known[0] > 0.0 && known[known.len() - 1] < 1.0);
+        let end = exploration.samples.last().unwrap().pose();
+        assert!(end.translation(&playground.goal_pose()) < 1e-6);
+        assert_eq!(exploration.plan_at(0), Some(&first[..]));
+    }
+
+    #[test]
+    fn maps_a_room_frontier_by_frontier() {
+        // Two rooms joined by a doorway, with a pillar in the far one.
+        let mut playground = Playground::new((300, 200), (40, 100), (40, 100));
+        for wall in [
+            ((140, 0), (20, 80)),
+            ((140, 120), (20, 80)),
+            ((220, 80), (20, 40)),
+        ] {
+            playground.add_obstacles(Rect {
+                anchor: wall.0,
+                size: wall.1,
+            });
+        }
+        // Frontiers the robot can't get to cost a whole search each, so searches are kept short.
+        let config = PlannerConfig {
+            robot_size: (10, 10),
+            max_iterations: Some(5_000),
+            lidar: LidarConfig {
+                max_range: 80.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let exploration = Exploration::run(&playground, &config, Mission::Frontiers, 1);
+        assert!(!exploration.collided);
+        assert!(!exploration.reached_goal);
+        assert!(exploration.targets.len() >= 2);
+
+        // Everything but the inside of the walls has been seen, and the robot has been through
+        // the doorway to see it.
+        let belief = &exploration.belief;
+        let walls = (2 * 20 * 80 + 20 * 40) as f64 / (300 * 200) as f64;
+        let known = exploration.samples.last().unwrap().known;
+        assert!(known > 0.95 * (1.0 - walls), "{known}");
+        assert!(exploration.samples.iter().any(|s| s.x > 200.0));
+        assert!(belief.frontiers(belief.scans()).is_empty() || exploration.unreachable > 0);
+
+        // Coverage only ever grows, and so does the distance travelled.
+        for w in exploration.samples.windows(2) {
+            assert!(w[0].known <= w[1].known);
+            assert!(w[0].distance <= w[1].distance);
+        }
+        assert_eq!(exploration.target_at(0), Some(exploration.targets[0].1));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let playground = Playground::new((200, 200), (20, 100), (180, 100));
+        let cancel = Arc::new(AtomicBool::new(true));
+        let config = PlannerConfig::default();
+        let exploration =
+            Exploration::run_cancellable(&playground, &config, Mission::Goal, 1, &cancel);
+        assert!(exploration.plans.is_empty());
+        assert!(!exploration.reached_goal);
+    }
+}
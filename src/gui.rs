@@ -0,0 +1,828 @@
+extern crate glutin_window;
+extern crate graphics;
+extern crate opengl_graphics;
+extern crate piston;
+
+use glutin_window::GlutinWindow as Window;
+use opengl_graphics::{GlGraphics, OpenGL};
+use piston::event_loop::{EventSettings, Events};
+use piston::input::{Button, Key, PressEvent, RenderArgs, RenderEvent, UpdateArgs, UpdateEvent};
+use piston::window::WindowSettings;
+
+mod font;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::config::{CompactionMode, Controller, Profile, Smoothing};
+use crate::coverage::Coverage;
+use crate::explore::{Cell, Exploration, Mission};
+use crate::lidar::{Lidar, Ray};
+use crate::planner::{Planner, Progress};
+use crate::playground::Playground;
+use crate::pose::Pose2;
+use crate::simulation::Simulation;
+use crate::tour::{Order, Tour};
+use crate::worker::{Job, PlanWorker, WorkerEvent};
+
+const PURPLE: [f32; 4] = [0.5, 0.0, 0.5, 1.0];
+const ORANGE: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+const FAINT_RED: [f32; 4] = [1.0, 0.0, 0.0, 0.25];
+const FOG: [f32; 4] = [0.5, 0.5, 0.5, 0.5];
+const SENSED: [f32; 4] = [0.6, 0.0, 0.0, 1.0];
+const ESTIMATE: [f32; 4] = [0.0, 0.4, 1.0, 1.0];
+const PARTICLE: [f32; 4] = [0.0, 0.4, 1.0, 0.4];
+const SWEPT: [f32; 4] = [0.0, 0.6, 0.3, 0.2];
+const TOUR: [f32; 4] = [0.8, 0.6, 0.0, 1.0];
+
+// Seconds a message stays on the status line.
+const STATUS_TIME: f64 = 3.0;
+
+pub struct App {
+    gl: GlGraphics, // OpenGL drawing backend.
+    playground: Arc<Playground>,
+    planner: Planner,
+    worker: Option<PlanWorker>,         // The search in progress, if any
+    progress: Option<Progress>,         // Its latest progress report
+    anytime: bool,  // Start moving on the first path found and switch to better ones
+    tracking: bool, // Also drive a simulated robot along the path with a controller
+    simulation: Option<Simulation>, // Its run along the current path
+    simulator: Option<Job<Simulation>>, // That run in progress, if any
+    t: f64,
+    show_stats: bool,
+    show_lidar: bool, // Sweep the robot's surroundings with the simulated range sensor
+    scan: Option<(Pose2, Vec<Ray>)>, // The latest sweep and the pose it was taken from
+    lidar_rng: ChaCha8Rng, // Noise for the sweeps, seeded from the planner's seed
+    explorer: Option<Job<Exploration>>, // An exploration run in progress, if any
+    exploration: Option<Exploration>, // The finished run, being replayed
+    coverer: Option<Job<Coverage>>, // A coverage plan in progress, if any
+    coverage: Option<Coverage>,
+    tourer: Option<Job<Tour>>, // A tour being planned, if any
+    tour: Option<Tour>,
+    replay_t: f64,                 // Time into replaying an exploration run or a tour
+    status: Option<(String, f64)>, // A setting changed or a run failed, and seconds left to show it
+}
+
+impl App {
+    fn render(&mut self, args: &RenderArgs) {
+        // Import _everything_ for now because otherwise we get trait method errors.
+        use graphics::*;
+        self.gl.draw(args.viewport(), |c, gl| {
+            clear(color::WHITE, gl);
+
+            let scale = [
+                args.window_size[0] / (self.playground.size.0 as f64),
+                args.window_size[1] / (self.playground.size.1 as f64),
+            ];
+
+            // Render obstacles
+            for r in self.playground.get_obstacles() {
+                let [ax, ay] = math::mul([r.anchor.0 as f64, r.anchor.1 as f64], scale);
+                let [sx, sy] = math::mul([r.size.0 as f64, r.size.1 as f64], scale);
+                let r = rectangle::rectangle_by_corners(ax, ay, ax + sx, ay + sy);
+                rectangle(color::BLACK, r, c.transform, gl);
+            }
+
+            // Render path
+            let [sx, sy] = math::mul(
+                [
+                    self.playground.start.0 as f64,
+                    self.playground.start.1 as f64,
+                ],
+                scale,
+            );
+            let [gx, gy] = math::mul(
+                [self.playground.goal.0 as f64, self.playground.goal.1 as f64],
+                scale,
+            );
+            {
+                let mut lp = [sx, sy];
+                for next_pose in &self.planner.full_path {
+                    let np = math::mul([next_pose.x, next_pose.y], scale);
+                    line_from_to(
+                        color::GREEN,
+                        1.0,
+                        [lp[0], lp[1]],
+                        [np[0], np[1]],
+                        c.transform,
+                        gl,
+                    );
+                    lp = np;
+                }
+            }
+            {
+                let mut lp = [sx, sy];
+                for next_pose in &self.planner.compact_path {
+                    let np = math::mul([next_pose.x, next_pose.y], scale);
+                    line_from_to(
+                        color::RED,
+                        1.0,
+                        [lp[0], lp[1]],
+                        [np[0], np[1]],
+                        c.transform,
+                        gl,
+                    );
+                    lp = np;
+                }
+            }
+            // With linear smoothing the smoothed path is the compacted one.
+            if self.planner.config.smoothing != Smoothing::Linear {
+                for w in self.planner.smooth_path.windows(2) {
+                    line_from_to(
+                        PURPLE,
+                        1.0,
+                        math::mul([w[0].x, w[0].y], scale),
+                        math::mul([w[1].x, w[1].y], scale),
+                        c.transform,
+                        gl,
+                    );
+                }
+            }
+
+            // Render a coverage plan: the cells of the decomposition, as the space the robot's
+            // centre can reach in each, and the strip the tool sweeps along the path.
+            if let Some(coverage) = &self.coverage {
+                for slab in coverage.cells.iter().flat_map(|cell| &cell.slabs) {
+                    let [ax, ay] = math::mul([slab.anchor.0 as f64, slab.anchor.1 as f64], scale);
+                    let [sx, sy] = math::mul([slab.size.0 as f64, slab.size.1 as f64], scale);
+                    Rectangle::new_border(color::GRAY, 0.5).draw(
+                        rectangle::rectangle_by_corners(ax, ay, ax + sx, ay + sy),
+                        &c.draw_state,
+                        c.transform,
+                        gl,
+                    );
+                }
+                let radius = coverage.tool_width / 2.0 * scale[0].min(scale[1]);
+                for w in coverage.path.windows(2) {
+                    let from = math::mul([w[0].x, w[0].y], scale);
+                    let to = math::mul([w[1].x, w[1].y], scale);
+                    line_from_to(SWEPT, radius, from, to, c.transform, gl);
+                    line_from_to(color::GREEN, 0.5, from, to, c.transform, gl);
+                }
+            }
+
+            // Render start/goal
+            let r = 10.0 * (scale[0].powf(2.0) + scale[1].powf(2.0)).sqrt();
+            let start = ellipse::circle(sx, sy, r);
+            let goal = ellipse::circle(gx, gy, r);
+            ellipse(color::RED, start, c.transform, gl);
+            ellipse(color::RED, goal, c.transform, gl);
+
+            // Render the goal region: its outline, and a tick for the heading flanked by the
+            // tolerance either side unless any heading will do.
+            let region = &self.playground.goal_region;
+            if region.radius > 0.0 {
+                let radius = region.radius * scale[0].min(scale[1]);
+                Ellipse::new_border(color::RED, 1.0).draw(
+                    ellipse::circle(gx, gy, radius),
+                    &c.draw_state,
+                    c.transform,
+                    gl,
+                );
+            }
+            if region.area.len() >= 3 {
+                let corners: Vec<[f64; 2]> = region
+                    .area
+                    .iter()
+                    .map(|&(x, y)| math::mul([x as f64, y as f64], scale))
+                    .collect();
+                for (k, &corner) in corners.iter().enumerate() {
+                    let next = corners[(k + 1) % corners.len()];
+                    line_from_to(color::RED, 1.0, corner, next, c.transform, gl);
+                }
+            }
+            if region.heading_tolerance < PI {
+                let heading = self.playground.goal_heading;
+                let tolerance = region.heading_tolerance;
+                for (turn, colour) in [
+                    (0.0, color::RED),
+                    (-tolerance, FAINT_RED),
+                    (tolerance, FAINT_RED),
+                ] {
+                    let (sin, cos) = (heading + turn).sin_cos();
+                    let tip = [gx + 2.0 * r * cos, gy + 2.0 * r * sin];
+                    line_from_to(colour, 1.0, [gx, gy], tip, c.transform, gl);
+                }
+            }
+
+            // Render actor
+            let [acx, acy] = math::mul([self.planner.pose.x, self.planner.pose.y], scale);
+            let [asx, asy] = math::mul(
+                [
+                    self.planner.config.robot_size.0 as f64,
+                    self.planner.config.robot_size.1 as f64,
+                ],
+                scale,
+            );
+            let transform = c
+                .transform
+                .trans(acx, acy)
+                .rot_rad(self.planner.pose.theta)
+                .trans(asx / -2.0, asy / -2.0);
+            let r = rectangle::rectangle_by_corners(0.0, 0.0, asx, asy);
+            rectangle(color::BLUE, r, transform, gl);
+
+            // Render waypoints as rings, with a tick for the way the robot has to face there.
+            let ring = 5.0 * (scale[0].powf(2.0) + scale[1].powf(2.0)).sqrt();
+            for waypoint in &self.playground.waypoints {
+                let (x, y) = (waypoint.position.0 as f64, waypoint.position.1 as f64);
+                let [wx, wy] = math::mul([x, y], scale);
+                Ellipse::new_border(TOUR, 1.0).draw(
+                    ellipse::circle(wx, wy, ring),
+                    &c.draw_state,
+                    c.transform,
+                    gl,
+                );
+                if let Some(heading) = waypoint.heading {
+                    let (sin, cos) = heading.sin_cos();
+                    let tip = [wx + 2.0 * ring * cos, wy + 2.0 * ring * sin];
+                    line_from_to(TOUR, 1.0, [wx, wy], tip, c.transform, gl);
+                }
+            }
+
+            // Render a tour: the legs it drives and the robot as an outline, as far as the replay
+            // has got.
+            if let Some(tour) = &self.tour {
+                for w in tour.legs.iter().flat_map(|leg| leg.windows(2)) {
+                    line_from_to(
+                        TOUR,
+                        1.0,
+                        math::mul([w[0].x, w[0].y], scale),
+                        math::mul([w[1].x, w[1].y], scale),
+                        c.transform,
+                        gl,
+                    );
+                }
+                let pose = tour.trajectory.sample(self.replay_t).pose();
+                let [x, y] = math::mul([pose.x, pose.y], scale);
+                let transform = c
+                    .transform
+                    .trans(x, y)
+                    .rot_rad(pose.theta)
+                    .trans(asx / -2.0, asy / -2.0);
+                Rectangle::new_border(TOUR, 1.0).draw(
+                    rectangle::rectangle_by_corners(0.0, 0.0, asx, asy),
+                    &c.draw_state,
+                    transform,
+                    gl,
+                );
+            }
+
+            // Render the latest lidar scan from the robot, with a dot where each ray hit
+            // something.
+            if let Some((pose, rays)) = self.scan.as_ref().filter(|_| self.show_lidar) {
+                let [acx, acy] = math::mul([pose.x, pose.y], scale);
+                for ray in rays {
+                    let (x, y) = ray.end(pose.x, pose.y);
+                    let end = math::mul([x, y], scale);
+                    line_from_to(FAINT_RED, 1.0, [acx, acy], end, c.transform, gl);
+                    if ray.hit {
+                        let dot = ellipse::circle(end[0], end[1], 2.0);
+                        ellipse(color::RED, dot, c.transform, gl);
+                    }
+                }
+            }
+
+            // Render an exploration run as far as it has got: fog over the cells the robot hasn't
+            // seen yet, the obstacles it has seen, the way it has come, the plan it is following
+            // and the robot itself as an outline.
+            if let Some(exploration) = &self.exploration {
+                let config = &self.planner.config;
+                let step = exploration.step_at(self.replay_t, config.speed, config.explore_step);
+                let belief = &exploration.belief;
+                let mut cells = |keep: Cell, colour| {
+                    for r in belief.runs(|cell| cell == keep, step + 1) {
+                        let [ax, ay] = math::mul([r.anchor.0 as f64, r.anchor.1 as f64], scale);
+                        let [sx, sy] = math::mul([r.size.0 as f64, r.size.1 as f64], scale);
+                        let r = rectangle::rectangle_by_corners(ax, ay, ax + sx, ay + sy);
+                        rectangle(colour, r, c.transform, gl);
+                    }
+                };
+                cells(Cell::Unknown, FOG);
+                cells(Cell::Occupied, SENSED);
+                if exploration.mission == Mission::Frontiers {
+                    for frontier in belief.frontiers(step + 1) {
+                        for cell in frontier {
+                            let (x, y) = belief.centre(cell);
+                            let [x, y] = math::mul([x, y], scale);
+                            let dot = ellipse::circle(x, y, 1.5);
+                            ellipse(color::GREEN, dot, c.transform, gl);
+                        }
+                    }
+                    if let Some((x, y)) = exploration.target_at(step) {
+                        let [x, y] = math::mul([x as f64, y as f64], scale);
+                        let r = 10.0 * (scale[0].powf(2.0) + scale[1].powf(2.0)).sqrt();
+                        Ellipse::new_border(color::GREEN, 1.0).draw(
+                            ellipse::circle(x, y, r),
+                            &c.draw_state,
+                            c.transform,
+                            gl,
+                        );
+                    }
+                }
+                if let Some(plan) = exploration.plan_at(step) {
+                    for w in plan.windows(2) {
+                        line_from_to(
+                            PURPLE,
+                            1.0,
+                            math::mul([w[0].x, w[0].y], scale),
+                            math::mul([w[1].x, w[1].y], scale),
+                            c.transform,
+                            gl,
+                        );
+                    }
+                }
+                let trail = &exploration.samples[..=step];
+                for w in trail.windows(2) {
+                    line_from_to(
+                        ORANGE,
+                        1.0,
+                        math::mul([w[0].x, w[0].y], scale),
+                        math::mul([w[1].x, w[1].y], scale),
+                        c.transform,
+                        gl,
+                    );
+                }
+                let sample = &exploration.samples[step];
+                let [x, y] = math::mul([sample.x, sample.y], scale);
+                let transform = c
+                    .transform
+                    .trans(x, y)
+                    .rot_rad(sample.theta)
+                    .trans(asx / -2.0, asy / -2.0);
+                Rectangle::new_border(ORANGE, 1.0).draw(
+                    rectangle::rectangle_by_corners(0.0, 0.0, asx, asy),
+                    &c.draw_state,
+                    transform,
+                    gl,
+                );
+            }
+
+            // Render the simulated robot, and the way it has come, as an outline. If it is
+            // localising, the particle filter's guesses are dots and the pose it steers by is
+            // outlined too.
+            if let Some(simulation) = &self.simulation {
+                let past = simulation.samples.iter().take_while(|s| s.time <= self.t);
+                let trail: Vec<[f64; 2]> = past.map(|s| math::mul([s.x, s.y], scale)).collect();
+                for w in trail.windows(2) {
+                    line_from_to(ORANGE, 1.0, w[0], w[1], c.transform, gl);
+                }
+                for p in simulation.particles_at(self.t).unwrap_or_default() {
+                    let [x, y] = math::mul([p.x, p.y], scale);
+                    ellipse(PARTICLE, ellipse::circle(x, y, 1.5), c.transform, gl);
+                }
+                if let Some(sample) = simulation.sample(self.t) {
+                    let mut outlines = vec![(sample.pose(), ORANGE)];
+                    if !simulation.particles.is_empty() {
+                        outlines.push((sample.estimate(), ESTIMATE));
+                    }
+                    for (pose, colour) in outlines {
+                        let [x, y] = math::mul([pose.x, pose.y], scale);
+                        let transform = c
+                            .transform
+                            .trans(x, y)
+                            .rot_rad(pose.theta)
+                            .trans(asx / -2.0, asy / -2.0);
+                        Rectangle::new_border(colour, 1.0).draw(
+                            rectangle::rectangle_by_corners(0.0, 0.0, asx, asy),
+                            &c.draw_state,
+                            transform,
+                            gl,
+                        );
+                    }
+                }
+            }
+
+            if self.worker.is_some() {
+                let mut lines = match self.planner.has_plan() {
+                    true => vec!["improving...".to_string()],
+                    false => vec!["planning...".to_string()],
+                };
+                if let Some(progress) = &self.progress {
+                    lines.extend(progress.lines());
+                }
+                lines.push("x to cancel".to_string());
+                font::draw_lines(&lines, [10.0, 10.0], 2.0, color::BLACK, c.transform, gl);
+            } else if self.show_stats && self.planner.has_plan() {
+                let mut lines = self.planner.stats.lines();
+                if self.simulator.is_some() {
+                    lines.push("simulating...".to_string());
+                }
+                if let Some(simulation) = &self.simulation {
+                    lines.extend(simulation.lines());
+                }
+                if self.explorer.is_some() {
+                    lines.push("exploring...".to_string());
+                }
+                if let Some(exploration) = &self.exploration {
+                    lines.extend(exploration.lines());
+                }
+                if self.coverer.is_some() {
+                    lines.push("planning coverage...".to_string());
+                }
+                if let Some(coverage) = &self.coverage {
+                    lines.extend(coverage.lines());
+                }
+                if self.tourer.is_some() {
+                    lines.push("planning tour...".to_string());
+                }
+                if let Some(tour) = &self.tour {
+                    lines.extend(tour.lines());
+                }
+                font::draw_lines(&lines, [10.0, 10.0], 2.0, color::BLACK, c.transform, gl);
+            }
+
+            if let Some((message, _)) = &self.status {
+                let y = args.window_size[1] - (font::GLYPH_HEIGHT + 5.0) * 2.0;
+                let lines = [message.clone()];
+                font::draw_lines(&lines, [10.0, y], 2.0, color::BLACK, c.transform, gl);
+            }
+        });
+    }
+
+    fn update(&mut self, args: &UpdateArgs) {
+        if let Some((_, left)) = &mut self.status {
+            *left -= args.dt;
+            if *left <= 0.0 {
+                self.status = None;
+            }
+        }
+        // The robot is only scanned again once it has moved, so a still robot's scan holds still.
+        let pose = self.planner.pose;
+        if self.show_lidar && self.scan.as_ref().is_none_or(|(p, _)| *p != pose) {
+            let lidar = Lidar::new(&self.planner.config.lidar);
+            let rays = lidar.scan(&self.playground, &pose, &mut self.lidar_rng);
+            self.scan = Some((pose, rays));
+        }
+        if self.explorer.as_ref().is_some_and(|e| e.is_finished()) {
+            match self.explorer.take().unwrap().join() {
+                Ok(exploration) => {
+                    self.exploration = Some(exploration);
+                    self.replay_t = 0.0;
+                }
+                Err(e) => self.notify(format!("exploration failed: {e}")),
+            }
+        }
+        self.replay_t += args.dt;
+        if self.coverer.as_ref().is_some_and(|c| c.is_finished()) {
+            match self.coverer.take().unwrap().join() {
+                Ok(coverage) => self.coverage = Some(coverage),
+                Err(e) => self.notify(format!("coverage planning failed: {e}")),
+            }
+        }
+        if self.tourer.as_ref().is_some_and(|t| t.is_finished()) {
+            match self.tourer.take().unwrap().join() {
+                Ok(tour) => {
+                    self.tour = Some(tour);
+                    self.replay_t = 0.0;
+                }
+                Err(e) => self.notify(format!("tour planning failed: {e}")),
+            }
+        }
+        while let Some(event) = self.worker.as_mut().and_then(|w| w.try_recv()) {
+            match event {
+                WorkerEvent::Progress(p) => self.progress = Some(p),
+                WorkerEvent::Solution(s) => {
+                    if self.planner.switch_path(&self.playground, &s) {
+                        self.restart_simulation();
+                        let length = s.stats.compact_length;
+                        self.notify(format!("switched to a path of length {length:.1}"));
+                    }
+                }
+                WorkerEvent::Failed(e) => {
+                    self.worker = None;
+                    self.progress = None;
+                    self.notify(format!("planning failed: {e}"));
+                }
+                WorkerEvent::Finished(_) if self.anytime && self.planner.has_plan() => {
+                    // The robot is already following the best solution it could switch to.
+                    self.worker = None;
+                    self.progress = None;
+                }
+                WorkerEvent::Finished(planner) => {
+                    self.restart_simulation();
+                    // Keep settings that were changed without replanning, like the speed.
+                    let config = self.planner.config.clone();
+                    self.planner = *planner;
+                    if self.planner.config != config {
+                        self.planner.config = config;
+                        self.planner.retime();
+                    }
+                    self.worker = None;
+                    self.progress = None;
+                    if self.planner.stats.cancelled {
+                        self.notify("planning cancelled".to_string());
+                    }
+                }
+            }
+        }
+        if !self.planner.has_plan() {
+            // Don't start the animation until we have computed the path.
+            return;
+        }
+        if self.simulator.as_ref().is_some_and(|s| s.is_finished()) {
+            match self.simulator.take().unwrap().join() {
+                Ok(simulation) => self.simulation = Some(simulation),
+                Err(e) => {
+                    // Running it again would only fail again.
+                    self.tracking = false;
+                    self.notify(format!("tracking simulation failed: {e}"));
+                }
+            }
+        }
+        if self.tracking && self.simulation.is_none() && self.simulator.is_none() {
+            // The run only needs the path and the settings, not the search state.
+            let mut planner = Planner::with_config(&self.playground, self.planner.config.clone());
+            planner.seed = self.planner.seed;
+            planner.pose = self.planner.pose;
+            planner.smooth_path = self.planner.smooth_path.clone();
+            let playground = self.playground.clone();
+            self.simulator = Some(Job::spawn(move |cancel| {
+                return Simulation::run_cancellable(&planner, &playground, cancel);
+            }));
+        }
+        self.t += args.dt;
+        self.planner.update_pos(self.t);
+    }
+
+    // Throws away the tracking simulation so that it is run again along the current path. A run
+    // still in progress is for a path the robot is no longer following, so it is cancelled.
+    fn restart_simulation(&mut self) {
+        self.simulation = None;
+        self.simulator = None;
+    }
+
+    // Starts planning from scratch on a worker thread with the current config and seed,
+    // abandoning any search already in progress.
+    fn replan(&mut self) {
+        let mut planner = Planner::with_config(&self.playground, self.planner.config.clone());
+        planner.seed = self.planner.seed;
+        self.planner.reset(&self.playground);
+        let playground = self.playground.clone();
+        self.worker = Some(match self.anytime {
+            true => PlanWorker::spawn_anytime(planner, playground),
+            false => PlanWorker::spawn(planner, playground),
+        });
+        self.progress = None;
+        self.restart_simulation();
+        self.t = -1.0;
+    }
+
+    // Starts an exploration run on a background thread, or stops showing the current one. A run
+    // still in progress is cancelled.
+    fn explore(&mut self, mission: Mission) {
+        if self.explorer.is_some() || self.exploration.is_some() {
+            self.explorer = None;
+            self.exploration = None;
+            return;
+        }
+        let playground = self.playground.clone();
+        let config = self.planner.config.clone();
+        let seed = self.planner.seed;
+        self.explorer = Some(Job::spawn(move |cancel| {
+            return Exploration::run_cancellable(&playground, &config, mission, seed, cancel);
+        }));
+    }
+
+    // Plans a coverage sweep on a background thread, or stops showing the current one, like
+    // explore.
+    fn cover(&mut self) {
+        if self.coverer.is_some() || self.coverage.is_some() {
+            self.coverer = None;
+            self.coverage = None;
+            return;
+        }
+        let playground = self.playground.clone();
+        let config = self.planner.config.clone();
+        let seed = self.planner.seed;
+        self.coverer = Some(Job::spawn(move |cancel| {
+            return Coverage::plan_cancellable(&playground, &config, seed, cancel);
+        }));
+    }
+
+    // Plans a tour of the waypoints on a background thread, or stops showing the current one,
+    // like explore.
+    fn tour(&mut self, order: Order) {
+        if self.tourer.is_some() || self.tour.is_some() {
+            self.tourer = None;
+            self.tour = None;
+            return;
+        }
+        let playground = self.playground.clone();
+        let config = self.planner.config.clone();
+        let seed = self.planner.seed;
+        self.tourer = Some(Job::spawn(move |cancel| {
+            return Tour::plan_cancellable(&playground, &config, order, seed, cancel);
+        }));
+    }
+
+    // Shows `message` on the status line for a few seconds, in place of any message before it.
+    fn notify(&mut self, message: String) {
+        self.status = Some((message, STATUS_TIME));
+    }
+
+    // Keyboard controls for tuning the planner while the viewer is running.
+    //   R            replay the current run from the start
+    //   Up / Down    speed up / slow down the robot
+    //   P            cycle through the speed profiles
+    //   ] / [        coarser / finer sampling grid
+    //   G / B        raise / lower the goal bias
+    //   C            toggle path compaction
+    //   M            cycle through the smoothing modes
+    //   S            toggle the planning statistics overlay
+    //   X            cancel planning
+    //   A            toggle anytime planning
+    //   T            toggle the closed-loop tracking simulation
+    //   K            cycle through the tracking controllers
+    //   O            toggle steering the simulated robot by a particle filter's estimate
+    //   L            toggle the lidar scan
+    //   E            toggle exploring the map from scratch, seeing it only through the lidar
+    //   F            toggle exploring the whole map from scratch, frontier by frontier
+    //   V            toggle a coverage plan sweeping the whole map
+    //   W            toggle a tour calling at the waypoints in the order listed
+    //   N            toggle a tour calling at the waypoints in the cheapest order
+    // Changing a planning parameter replans from the start with the same seed.
+    fn key_press(&mut self, key: Key) {
+        let mut config = self.planner.config.clone();
+        match key {
+            Key::R => self.replay_t = 0.0,
+            Key::S => {
+                self.show_stats = !self.show_stats;
+                return;
+            }
+            Key::L => {
+                self.show_lidar = !self.show_lidar;
+                return;
+            }
+            Key::E => {
+                self.explore(Mission::Goal);
+                return;
+            }
+            Key::F => {
+                self.explore(Mission::Frontiers);
+                return;
+            }
+            Key::V => {
+                self.cover();
+                return;
+            }
+            Key::W => {
+                self.tour(Order::Listed);
+                return;
+            }
+            Key::N => {
+                self.tour(Order::Any);
+                return;
+            }
+            Key::T => {
+                self.tracking = !self.tracking;
+                self.restart_simulation();
+                self.notify(format!("tracking simulation: {}", self.tracking));
+                return;
+            }
+            Key::K => {
+                config.controller = match config.controller {
+                    Controller::PurePursuit => Controller::Stanley,
+                    Controller::Stanley => Controller::PidHeading,
+                    Controller::PidHeading => Controller::Mpc,
+                    Controller::Mpc => Controller::Dwa,
+                    Controller::Dwa => Controller::PurePursuit,
+                };
+            }
+            Key::O => config.localise = !config.localise,
+            Key::A => {
+                self.anytime = !self.anytime;
+                self.notify(format!("anytime planning: {}", self.anytime));
+            }
+            Key::X => {
+                if let Some(worker) = &self.worker {
+                    worker.cancel();
+                }
+                return;
+            }
+            Key::Up => config.speed *= 1.25,
+            Key::Down => config.speed /= 1.25,
+            Key::P => {
+                config.profile = match config.profile {
+                    Profile::Constant => Profile::Trapezoidal,
+                    Profile::Trapezoidal => Profile::SCurve,
+                    Profile::SCurve => Profile::Constant,
+                };
+            }
+            Key::RightBracket => {
+                let (w, h) = self.playground.size;
+                config.grid_size = (config.grid_size + 5).min(w.min(h));
+            }
+            Key::LeftBracket => config.grid_size = (config.grid_size - 5).max(1),
+            Key::G => config.goal_bias += 0.01,
+            Key::B => config.goal_bias -= 0.01,
+            Key::C => {
+                config.compaction = match config.compaction {
+                    CompactionMode::None => CompactionMode::Greedy,
+                    CompactionMode::Greedy => CompactionMode::None,
+                }
+            }
+            Key::M => {
+                config.smoothing = match config.smoothing {
+                    Smoothing::Linear => Smoothing::CatmullRom,
+                    Smoothing::CatmullRom => Smoothing::Hermite,
+                    Smoothing::Hermite => Smoothing::BSpline,
+                    Smoothing::BSpline => Smoothing::Arcs,
+                    Smoothing::Arcs => Smoothing::Linear,
+                }
+            }
+            _ => return,
+        }
+        if let Err(e) = config.validate_for(&self.playground) {
+            self.notify(format!("ignoring change: {e}"));
+            return;
+        }
+        let setting = match key {
+            Key::Up | Key::Down => Some(format!("speed: {:.1}", config.speed)),
+            Key::P => Some(format!("speed profile: {:?}", config.profile)),
+            Key::K => Some(format!("controller: {:?}", config.controller)),
+            Key::O => Some(format!("localisation: {}", config.localise)),
+            Key::RightBracket | Key::LeftBracket => {
+                Some(format!("grid size: {}", config.grid_size))
+            }
+            Key::G | Key::B => Some(format!("goal bias: {:.2}", config.goal_bias)),
+            Key::C => Some(format!("compaction: {:?}", config.compaction)),
+            Key::M => Some(format!("smoothing: {:?}", config.smoothing)),
+            _ => None,
+        };
+        if let Some(setting) = setting {
+            self.notify(setting);
+        }
+
+        // Motion settings only re-time the rest of the path, from wherever the robot is now.
+        // Either way the tracking simulation starts again.
+        self.restart_simulation();
+        let retime = matches!(key, Key::Up | Key::Down | Key::P);
+        if matches!(key, Key::K | Key::O) {
+            self.planner.config = config;
+        } else if retime {
+            self.planner.config = config;
+            self.planner.retime();
+        } else {
+            self.planner.config = config;
+            self.replan();
+        }
+    }
+}
+
+// Opens a window sized to the playground and animates the planner until the window is closed.
+// With `anytime`, the robot sets off on the first path found and switches to better ones as the
+// search finds them.
+pub fn run(playground: Playground, planner: Planner, anytime: bool) {
+    let opengl_version = OpenGL::V3_2;
+    let initial_size = (playground.size.0 as u32, playground.size.1 as u32);
+    let mut window: Window = WindowSettings::new("playground", initial_size)
+        .graphics_api(opengl_version)
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
+
+    let lidar_rng = ChaCha8Rng::seed_from_u64(planner.seed);
+    let mut app = App {
+        gl: GlGraphics::new(opengl_version),
+        playground: Arc::new(playground),
+        planner,
+        worker: None,
+        progress: None,
+        anytime,
+        tracking: false,
+        simulation: None,
+        simulator: None,
+        t: -1.0,
+        show_stats: true,
+        show_lidar: false,
+        scan: None,
+        lidar_rng,
+        explorer: None,
+        exploration: None,
+        coverer: None,
+        coverage: None,
+        tourer: None,
+        tour: None,
+        replay_t: 0.0,
+        status: None,
+    };
+    app.replan();
+
+    let mut events = Events::new(EventSettings::new());
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.render_args() {
+            app.render(&args);
+        }
+
+        if let Some(args) = e.update_args() {
+            app.update(&args);
+        }
+
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            app.key_press(key);
+        }
+    }
+}
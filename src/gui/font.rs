@@ -0,0 +1,104 @@
+// A tiny 3x5 bitmap font, so the viewer can draw overlays without shipping a font file.
+// Letters are drawn in upper case; unknown characters are left blank.
+
+use graphics::{rectangle, types::Color, Graphics, Transformed};
+
+pub const GLYPH_WIDTH: f64 = 4.0; // Including one column of spacing
+pub const GLYPH_HEIGHT: f64 = 6.0; // Including one row of spacing
+
+// Each row is a 3 bit mask, most significant bit on the left.
+fn glyph(c: char) -> [u8; 5] {
+    return match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        _ => [0; 5],
+    };
+}
+
+// Draws `lines` top to bottom starting at `pos`, with each font pixel `scale` screen pixels wide,
+// on a translucent white backing box so the text stays readable over obstacles.
+pub fn draw_lines<G: Graphics>(
+    lines: &[String],
+    pos: [f64; 2],
+    scale: f64,
+    color: Color,
+    transform: [[f64; 3]; 2],
+    g: &mut G,
+) {
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64;
+    let backing = [
+        -scale,
+        -scale,
+        (width * GLYPH_WIDTH + 1.0) * scale,
+        (lines.len() as f64 * GLYPH_HEIGHT + 1.0) * scale,
+    ];
+    let transform = transform.trans(pos[0], pos[1]);
+    rectangle([1.0, 1.0, 1.0, 0.8], backing, transform, g);
+
+    for (row, line) in lines.iter().enumerate() {
+        for (col, c) in line.chars().enumerate() {
+            let origin = [
+                col as f64 * GLYPH_WIDTH * scale,
+                row as f64 * GLYPH_HEIGHT * scale,
+            ];
+            for (y, bits) in glyph(c).iter().enumerate() {
+                for x in 0..3 {
+                    if bits & (0b100 >> x) == 0 {
+                        continue;
+                    }
+                    let px = [
+                        origin[0] + x as f64 * scale,
+                        origin[1] + y as f64 * scale,
+                        scale,
+                        scale,
+                    ];
+                    rectangle(color, px, transform, g);
+                }
+            }
+        }
+    }
+}
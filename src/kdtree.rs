@@ -0,0 +1,371 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+// A subtree of n nodes is rebuilt once an insert makes it more than log(n) / log(1 / BALANCE)
+// levels tall, as in a scapegoat tree.
+const BALANCE: f64 = 0.75;
+
+// An incrementally built k-d tree over K-dimensional points with Euclidean distance.
+// Points are never removed, so nodes live in a flat Vec and refer to their children by index.
+// Points often arrive in order, as RRT grows its tree outwards from the start, which on its own
+// would leave a long chain. So whenever an insert lands deeper than a balanced tree would put it,
+// the lopsided subtree above it is rebuilt around its medians.
+pub struct KdTree<const K: usize, T> {
+    nodes: Vec<Node<K, T>>,
+    root: usize,
+}
+
+struct Node<const K: usize, T> {
+    point: [f64; K],
+    item: T,
+    children: [Option<usize>; 2], // At or below, and at or above, the split value
+    size: usize,                  // Nodes in the subtree rooted here, this one included
+}
+
+// A candidate in a k-nearest query. Ordered by distance so the heap keeps the worst on top; ties
+// go to the node inserted first so queries are deterministic.
+struct Candidate {
+    dist_sq: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .dist_sq
+            .total_cmp(&other.dist_sq)
+            .then(self.index.cmp(&other.index));
+    }
+}
+
+impl<const K: usize, T> Default for KdTree<K, T> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<const K: usize, T> KdTree<K, T> {
+    pub fn new() -> Self {
+        return Self {
+            nodes: Vec::new(),
+            root: 0,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.nodes.is_empty();
+    }
+
+    pub fn insert(&mut self, point: [f64; K], item: T) {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            point,
+            item,
+            children: [None, None],
+            size: 1,
+        });
+        if index == 0 {
+            return;
+        }
+
+        // The nodes from the root down to the new one.
+        let mut path = vec![self.root];
+        loop {
+            let current = *path.last().unwrap();
+            let axis = (path.len() - 1) % K;
+            self.nodes[current].size += 1;
+            let side = (point[axis] >= self.nodes[current].point[axis]) as usize;
+            match self.nodes[current].children[side] {
+                Some(next) => path.push(next),
+                None => {
+                    self.nodes[current].children[side] = Some(index);
+                    break;
+                }
+            }
+        }
+        path.push(index);
+
+        // Too deep means some node on the way down roots a subtree too tall for its size.
+        // Rebuilding the lowest such node is enough to bring the depth back down.
+        let max_height = |size: usize| (size as f64).ln() / (1.0 / BALANCE).ln();
+        if (path.len() - 1) as f64 <= max_height(self.nodes.len()) {
+            return;
+        }
+        for depth in (0..path.len() - 1).rev() {
+            let node = path[depth];
+            if (path.len() - 1 - depth) as f64 > max_height(self.nodes[node].size) {
+                let root = self.rebuild(node, depth);
+                match depth {
+                    0 => self.root = root,
+                    _ => {
+                        let parent = &mut self.nodes[path[depth - 1]];
+                        let side = (parent.children[1] == Some(node)) as usize;
+                        parent.children[side] = Some(root);
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    // Rebuilds the subtree rooted at `subtree`, `depth` levels down, splitting each piece of it
+    // at its median. Returns the subtree's new root.
+    fn rebuild(&mut self, subtree: usize, depth: usize) -> usize {
+        let mut indices = Vec::with_capacity(self.nodes[subtree].size);
+        let mut stack = vec![subtree];
+        while let Some(index) = stack.pop() {
+            indices.push(index);
+            stack.extend(self.nodes[index].children.iter().flatten());
+            self.nodes[index].children = [None, None];
+        }
+
+        // Each piece of `indices` still to build: its range, its depth and the slot it hangs from.
+        let mut root = subtree;
+        let mut pieces = vec![(0, indices.len(), depth, None::<(usize, usize)>)];
+        while let Some((start, end, depth, slot)) = pieces.pop() {
+            if start == end {
+                continue;
+            }
+            let axis = depth % K;
+            let nodes = &self.nodes;
+            let middle = (start + end) / 2;
+            indices[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+                let (a_value, b_value) = (nodes[a].point[axis], nodes[b].point[axis]);
+                return a_value.total_cmp(&b_value).then(a.cmp(&b));
+            });
+            let node = indices[middle];
+            self.nodes[node].size = end - start;
+            match slot {
+                None => root = node,
+                Some((parent, side)) => self.nodes[parent].children[side] = Some(node),
+            }
+            pieces.push((start, middle, depth + 1, Some((node, 0))));
+            pieces.push((middle + 1, end, depth + 1, Some((node, 1))));
+        }
+        return root;
+    }
+
+    // The closest item and its distance, or None if the tree is empty.
+    pub fn nearest(&self, point: &[f64; K]) -> Option<(&T, f64)> {
+        return self.k_nearest(point, 1).into_iter().next();
+    }
+
+    // Up to k items ordered from nearest to furthest, with their distances.
+    pub fn k_nearest(&self, point: &[f64; K], k: usize) -> Vec<(&T, f64)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 && !self.nodes.is_empty() {
+            self.search_k(point, k, &mut heap);
+        }
+        return heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (&self.nodes[c.index].item, c.dist_sq.sqrt()))
+            .collect();
+    }
+
+    // Every item within `radius` of the point, ordered from nearest to furthest.
+    pub fn within_radius(&self, point: &[f64; K], radius: f64) -> Vec<(&T, f64)> {
+        let mut acc = Vec::new();
+        if !self.nodes.is_empty() {
+            self.search_radius(point, radius * radius, &mut acc);
+        }
+        acc.sort();
+        return acc
+            .into_iter()
+            .map(|c| (&self.nodes[c.index].item, c.dist_sq.sqrt()))
+            .collect();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[f64; K], &T)> {
+        return self.nodes.iter().map(|n| (&n.point, &n.item));
+    }
+
+    // Searches iteratively, as the tree can be deep before it is rebuilt.
+    fn search_k(&self, point: &[f64; K], k: usize, heap: &mut BinaryHeap<Candidate>) {
+        // Subtrees still to visit, with their depth and how far the point is from them at least.
+        let mut stack = vec![(self.root, 0, 0.0)];
+        while let Some((index, depth, bound)) = stack.pop() {
+            // Points in the subtree can tie with the current worst candidate, so visit it unless
+            // it is strictly further away.
+            if heap.len() == k && bound > heap.peek().unwrap().dist_sq {
+                continue;
+            }
+            let node = &self.nodes[index];
+            let candidate = Candidate {
+                dist_sq: dist_sq(&node.point, point),
+                index,
+            };
+            if heap.len() < k {
+                heap.push(candidate);
+            } else if candidate < *heap.peek().unwrap() {
+                heap.pop();
+                heap.push(candidate);
+            }
+
+            let axis = depth % K;
+            let diff = point[axis] - node.point[axis];
+            let (near, far) = match diff >= 0.0 {
+                true => (node.children[1], node.children[0]),
+                false => (node.children[0], node.children[1]),
+            };
+            // The near side goes on top, so it is searched first and tightens the bound.
+            if let Some(far) = far {
+                stack.push((far, depth + 1, diff * diff));
+            }
+            if let Some(near) = near {
+                stack.push((near, depth + 1, bound));
+            }
+        }
+    }
+
+    fn search_radius(&self, point: &[f64; K], radius_sq: f64, acc: &mut Vec<Candidate>) {
+        let mut stack = vec![(self.root, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let d = dist_sq(&node.point, point);
+            if d <= radius_sq {
+                acc.push(Candidate { dist_sq: d, index });
+            }
+
+            let axis = depth % K;
+            let diff = point[axis] - node.point[axis];
+            if let Some(below) = node.children[0] {
+                if diff < 0.0 || diff * diff <= radius_sq {
+                    stack.push((below, depth + 1));
+                }
+            }
+            if let Some(above) = node.children[1] {
+                if diff >= 0.0 || diff * diff <= radius_sq {
+                    stack.push((above, depth + 1));
+                }
+            }
+        }
+    }
+}
+
+fn dist_sq<const K: usize>(a: &[f64; K], b: &[f64; K]) -> f64 {
+    return a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn brute_force(points: &[[f64; 3]], query: &[f64; 3]) -> Vec<(usize, f64)> {
+        let mut acc: Vec<(usize, f64)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, dist_sq(p, query).sqrt()))
+            .collect();
+        acc.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        return acc;
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let points: Vec<[f64; 3]> = (0..500)
+            .map(|_| [rng.gen(), rng.gen(), rng.gen()])
+            .collect();
+        let mut tree = KdTree::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(*p, i);
+        }
+        assert_eq!(tree.len(), 500);
+
+        for _ in 0..50 {
+            let query = [rng.gen(), rng.gen(), rng.gen()];
+            let expected = brute_force(&points, &query);
+
+            let nearest = tree.nearest(&query).unwrap();
+            assert_eq!(*nearest.0, expected[0].0);
+
+            let k: Vec<usize> = tree.k_nearest(&query, 5).iter().map(|n| *n.0).collect();
+            let expected_k: Vec<usize> = expected[..5].iter().map(|n| n.0).collect();
+            assert_eq!(k, expected_k);
+
+            let radius: Vec<usize> = tree
+                .within_radius(&query, 0.2)
+                .iter()
+                .map(|n| *n.0)
+                .collect();
+            let expected_radius: Vec<usize> = expected
+                .iter()
+                .filter(|n| n.1 <= 0.2)
+                .map(|n| n.0)
+                .collect();
+            assert_eq!(radius, expected_radius);
+        }
+    }
+
+    #[test]
+    fn sorted_inserts() {
+        // In order along x, with a few rows of y that repeat, like lattice-snapped RRT nodes.
+        let points: Vec<[f64; 2]> = (0..200_000)
+            .map(|i| [(i / 4) as f64, (i % 4) as f64])
+            .collect();
+        let mut tree = KdTree::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(*p, i);
+        }
+        assert_eq!(tree.len(), 200_000);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        for _ in 0..5 {
+            let query = [rng.gen_range(-10.0..50_010.0), rng.gen_range(-1.0..4.0)];
+            let mut expected: Vec<(usize, f64)> = points
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (i, dist_sq(p, &query).sqrt()))
+                .collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+            assert_eq!(*tree.nearest(&query).unwrap().0, expected[0].0);
+            let k: Vec<usize> = tree.k_nearest(&query, 10).iter().map(|n| *n.0).collect();
+            let expected_k: Vec<usize> = expected[..10].iter().map(|n| n.0).collect();
+            assert_eq!(k, expected_k);
+        }
+    }
+
+    #[test]
+    fn ties_prefer_first_inserted() {
+        let mut tree = KdTree::new();
+        tree.insert([1.0, 0.0], "right");
+        tree.insert([-1.0, 0.0], "left");
+        tree.insert([0.0, 1.0], "up");
+        assert_eq!(tree.nearest(&[0.0, 0.0]), Some((&"right", 1.0)));
+        let all: Vec<&str> = tree
+            .k_nearest(&[0.0, 0.0], 5)
+            .iter()
+            .map(|n| *n.0)
+            .collect();
+        assert_eq!(all, vec!["right", "left", "up"]);
+    }
+
+    #[test]
+    fn empty() {
+        let tree: KdTree<2, ()> = KdTree::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.nearest(&[0.0, 0.0]), None);
+        assert!(tree.within_radius(&[0.0, 0.0], 10.0).is_empty());
+    }
+}
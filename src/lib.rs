@@ -0,0 +1,42 @@
+// Core path planning library. The simulation types have no windowing dependencies, so they can be
+// embedded in other services; the piston viewer lives behind the `gui` feature.
+#![allow(clippy::needless_return)]
+
+pub mod config;
+pub mod controller;
+pub mod coverage;
+pub mod dwa;
+pub mod explore;
+pub mod kdtree;
+pub mod lidar;
+pub mod localise;
+pub mod mpc;
+pub mod optimise;
+pub mod parallel;
+pub mod planner;
+pub mod playground;
+pub mod pose;
+pub mod render;
+pub mod roadmap;
+pub mod robot;
+pub mod scenario;
+pub mod simulation;
+pub mod smoothing;
+pub mod stats;
+pub mod tour;
+pub mod trajectory;
+pub mod worker;
+
+#[cfg(feature = "gui")]
+pub mod gui;
+
+pub use config::{
+    Algorithm, CompactionMode, Controller, DistanceMetric, Optimiser, PlannerConfig, Profile,
+    Smoothing,
+};
+pub use planner::Planner;
+pub use playground::{Playground, Rect, Waypoint};
+pub use pose::{Pose, Pose2};
+pub use robot::{Robot, RobotModel};
+pub use scenario::Scenario;
+pub use stats::PlanStats;
@@ -0,0 +1,248 @@
+use rand::Rng;
+
+use crate::config::LidarConfig;
+use crate::playground::{Playground, Rect};
+use crate::pose::{normalize_angle, Pose2};
+
+// One reading of the range sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub angle: f64, // Direction of the ray in the playground's frame, in radians
+    pub range: f64, // Measured distance, noise included, up to the maximum range
+    pub hit: bool,  // Did the ray hit something within range?
+}
+
+impl Ray {
+    // Where the ray ends, starting from (x, y).
+    pub fn end(&self, x: f64, y: f64) -> (f64, f64) {
+        return (
+            x + self.range * self.angle.cos(),
+            y + self.range * self.angle.sin(),
+        );
+    }
+}
+
+// A 2D lidar: a fan of rays spread over config.fov around the robot's heading, config.resolution
+// apart, each reporting the distance to the first obstacle or edge of the playground it meets
+// within config.max_range, give or take Gaussian noise of config.noise pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Lidar {
+    config: LidarConfig,
+}
+
+impl Lidar {
+    pub fn new(config: &LidarConfig) -> Self {
+        return Self { config: *config };
+    }
+
+    pub fn max_range(&self) -> f64 {
+        return self.config.max_range;
+    }
+
+    // The directions of the rays relative to the robot's heading, from one side to the other. A
+    // full circle doesn't cast the same ray twice.
+    pub fn angles(&self) -> Vec<f64> {
+        let LidarConfig {
+            fov, resolution, ..
+        } = self.config;
+        let full = fov >= std::f64::consts::TAU - 1e-9;
+        let gaps = ((fov / resolution).round() as usize).max(1);
+        let rays = match full {
+            true => gaps,
+            false => gaps + 1,
+        };
+        let step = fov / gaps as f64;
+        return (0..rays).map(|i| -fov / 2.0 + step * i as f64).collect();
+    }
+
+    // Takes a reading from `pose`. The quadtree only hands back the obstacles within range, so
+    // each ray is only tested against those.
+    pub fn scan<R: Rng>(&self, playground: &Playground, pose: &Pose2, rng: &mut R) -> Vec<Ray> {
+        let max_range = self.config.max_range;
+        let obstacles = playground.obstacles_near(pose.x, pose.y, max_range);
+        let mut rays = Vec::new();
+        for angle in self.angles() {
+            let angle = normalize_angle(pose.theta + angle);
+            let range = cast(playground, &obstacles, pose.x, pose.y, angle, max_range);
+            rays.push(match range {
+                Some(range) => Ray {
+                    angle,
+                    range: (range + self.config.noise * gaussian(rng)).clamp(0.0, max_range),
+                    hit: true,
+                },
+                None => Ray {
+                    angle,
+                    range: max_range,
+                    hit: false,
+                },
+            });
+        }
+        return rays;
+    }
+}
+
+// The distance from (x, y) along `angle` to the first of `obstacles` or the edge of the
+// playground, if it is within `max_range`. A ray starting inside an obstacle hits it straight
+// away.
+pub fn cast(
+    playground: &Playground,
+    obstacles: &[Rect],
+    x: f64,
+    y: f64,
+    angle: f64,
+    max_range: f64,
+) -> Option<f64> {
+    let (dx, dy) = (angle.cos(), angle.sin());
+    let (w, h) = (playground.size.0 as f64, playground.size.1 as f64);
+
+    // The edges of the playground are walls, seen from the inside.
+    let mut nearest = f64::INFINITY;
+    for (distance, speed) in [(x, -dx), (y, -dy), (w - x, dx), (h - y, dy)] {
+        if speed > 0.0 {
+            nearest = nearest.min((distance / speed).max(0.0));
+        }
+    }
+
+    for o in obstacles {
+        let (x0, y0) = (o.anchor.0 as f64, o.anchor.1 as f64);
+        let (x1, y1) = (x0 + o.size.0 as f64, y0 + o.size.1 as f64);
+        if let Some(t) = slab(x, dx, x0, x1).and_then(|a| overlap(a, slab(y, dy, y0, y1)?)) {
+            nearest = nearest.min(t.0.max(0.0));
+        }
+    }
+    return match nearest <= max_range {
+        true => Some(nearest),
+        false => None,
+    };
+}
+
+// The stretch of a ray, from `origin` moving at `speed`, that lies between `lo` and `hi`.
+fn slab(origin: f64, speed: f64, lo: f64, hi: f64) -> Option<(f64, f64)> {
+    if speed == 0.0 {
+        return match origin >= lo && origin <= hi {
+            true => Some((f64::NEG_INFINITY, f64::INFINITY)),
+            false => None,
+        };
+    }
+    let (a, b) = ((lo - origin) / speed, (hi - origin) / speed);
+    return Some((a.min(b), a.max(b)));
+}
+
+// Where two stretches of a ray overlap ahead of its origin, if they do.
+fn overlap(a: (f64, f64), b: (f64, f64)) -> Option<(f64, f64)> {
+    let (enter, exit) = (a.0.max(b.0), a.1.min(b.1));
+    return match enter <= exit && exit >= 0.0 {
+        true => Some((enter, exit)),
+        false => None,
+    };
+}
+
+// A standard normal sample, by the Box-Muller transform.
+pub(crate) fn gaussian<R: Rng>(rng: &mut R) -> f64 {
+    let u: f64 = 1.0 - rng.gen::<f64>(); // In (0, 1], so the log is finite
+    let v: f64 = rng.gen();
+    return (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn playground() -> Playground {
+        let mut playground = Playground::new((500, 500), (0, 0), (0, 0));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (100, 100),
+        });
+        return playground;
+    }
+
+    #[test]
+    fn cast() {
+        let playground = playground();
+        let obstacles = playground.get_obstacles();
+        let cast = |x, y, angle| super::cast(&playground, &obstacles, x, y, angle, 400.0);
+        assert_eq!(cast(150.0, 250.0, 0.0), Some(50.0));
+        assert_eq!(cast(250.0, 350.0, -FRAC_PI_2), Some(50.0));
+        assert_eq!(cast(250.0, 250.0, 0.0), Some(0.0));
+        // Past the obstacle, to the edge of the playground.
+        assert_eq!(cast(150.0, 250.0, PI), Some(150.0));
+        assert_eq!(cast(150.0, 150.0, 0.0), Some(350.0));
+        // Clipping the corner.
+        let d = cast(150.0, 150.0, PI / 4.0).unwrap();
+        assert!((d - 50.0 * 2f64.sqrt()).abs() < 1e-9);
+        // Out of range.
+        assert_eq!(
+            super::cast(&playground, &obstacles, 150.0, 150.0, 0.0, 100.0),
+            None
+        );
+    }
+
+    #[test]
+    fn angles() {
+        let lidar = |fov, resolution| {
+            Lidar::new(&LidarConfig {
+                fov,
+                resolution,
+                ..Default::default()
+            })
+            .angles()
+        };
+        assert_eq!(lidar(PI, FRAC_PI_2), vec![-FRAC_PI_2, 0.0, FRAC_PI_2]);
+        assert_eq!(
+            lidar(2.0 * PI, FRAC_PI_2),
+            vec![-PI, -FRAC_PI_2, 0.0, FRAC_PI_2]
+        );
+        assert_eq!(lidar(0.1, 1.0), vec![-0.05, 0.05]);
+    }
+
+    #[test]
+    fn scan() {
+        let playground = playground();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let lidar = Lidar::new(&LidarConfig {
+            fov: PI,
+            resolution: FRAC_PI_2,
+            max_range: 200.0,
+            noise: 0.0,
+        });
+        // Facing the obstacle from the left, with the top and bottom edges out of range.
+        let rays = lidar.scan(&playground, &Pose2::new(150.0, 250.0, 0.0), &mut rng);
+        let readings: Vec<(f64, bool)> = rays.iter().map(|r| (r.range, r.hit)).collect();
+        assert_eq!(readings, vec![(200.0, false), (50.0, true), (200.0, false)]);
+        assert_eq!(rays[0].angle, -FRAC_PI_2);
+        let (x, y) = rays[1].end(150.0, 250.0);
+        assert!((x - 200.0).abs() < 1e-9 && (y - 250.0).abs() < 1e-9);
+
+        // Turned round, the middle ray reaches the left edge.
+        let rays = lidar.scan(&playground, &Pose2::new(150.0, 250.0, PI), &mut rng);
+        assert_eq!(rays[1].range, 150.0);
+        assert_eq!(rays[1].angle, PI);
+    }
+
+    #[test]
+    fn noise() {
+        let playground = playground();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let lidar = Lidar::new(&LidarConfig {
+            fov: 0.1,
+            resolution: 1.0,
+            max_range: 200.0,
+            noise: 2.0,
+        });
+        let pose = Pose2::new(150.0, 250.0, 0.0);
+        let readings: Vec<f64> = (0..2000)
+            .map(|_| lidar.scan(&playground, &pose, &mut rng)[0].range)
+            .collect();
+        let mean = readings.iter().sum::<f64>() / readings.len() as f64;
+        let variance =
+            readings.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / readings.len() as f64;
+        // The rays are 0.05 radians either side of straight ahead.
+        let truth = 50.0 / 0.05f64.cos();
+        assert!((mean - truth).abs() < 0.2, "{mean}");
+        assert!((variance.sqrt() - 2.0).abs() < 0.2, "{variance}");
+    }
+}
@@ -0,0 +1,317 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::config::{LocalisationConfig, PlannerConfig};
+use crate::lidar::{cast, gaussian, Lidar, Ray};
+use crate::playground::Playground;
+use crate::pose::{angle_diff, normalize_angle, Pose2};
+
+// How likely a reading is to have nothing to do with the map, relative to one that matches it
+// exactly. Without it a single stray reading could rule out the right guess.
+const STRAY_READING: f64 = 0.05;
+
+// How far each guess is nudged after resampling, in pixels and radians, so that the copies of a
+// good guess spread out around it and the cloud can keep closing in on the robot.
+const ROUGHENING: (f64, f64) = (1.0, 0.01);
+
+// A move as the robot measures it: in its own frame at the start of the move, in pixels and
+// radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Odometry {
+    pub forward: f64,
+    pub lateral: f64, // To the left of the heading
+    pub turn: f64,
+}
+
+impl Odometry {
+    // The move that takes the robot from one pose to the other.
+    pub fn between(from: &Pose2, to: &Pose2) -> Self {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let (sin, cos) = from.theta.sin_cos();
+        return Self {
+            forward: dx * cos + dy * sin,
+            lateral: dy * cos - dx * sin,
+            turn: angle_diff(from.theta, to.theta),
+        };
+    }
+
+    // Where the move takes the robot from `pose`.
+    pub fn apply(&self, pose: &Pose2) -> Pose2 {
+        let (sin, cos) = pose.theta.sin_cos();
+        return Pose2::new(
+            pose.x + self.forward * cos - self.lateral * sin,
+            pose.y + self.forward * sin + self.lateral * cos,
+            pose.theta + self.turn,
+        );
+    }
+
+    // The move give or take the errors odometry makes: each part off by a random fraction of
+    // itself, and the heading drifting a little with every pixel travelled.
+    pub fn perturb<R: Rng>(&self, config: &LocalisationConfig, rng: &mut R) -> Self {
+        let noise = config.odometry_noise;
+        let distance = self.forward.hypot(self.lateral);
+        return Self {
+            forward: self.forward * (1.0 + noise * gaussian(rng)),
+            lateral: self.lateral * (1.0 + noise * gaussian(rng)),
+            turn: self.turn * (1.0 + noise * gaussian(rng))
+                + config.heading_drift * distance * gaussian(rng),
+        };
+    }
+}
+
+// Monte Carlo localisation: a cloud of guesses at the robot's pose. Every move shifts them all by
+// what odometry measured, each with its own made-up error, and every scan keeps the ones that
+// would have seen what the lidar saw, drawing a fresh cloud with the likelier guesses repeated.
+#[derive(Clone, Debug)]
+pub struct ParticleFilter {
+    config: LocalisationConfig,
+    lidar: Lidar,
+    pub particles: Vec<Pose2>,
+}
+
+impl ParticleFilter {
+    // Guesses scattered around `start`, where the robot is thought to begin.
+    pub fn new<R: Rng>(config: &PlannerConfig, start: &Pose2, rng: &mut R) -> Self {
+        let localisation = config.localisation;
+        let (spread, turn) = localisation.initial_spread;
+        let particles = (0..localisation.particles)
+            .map(|_| {
+                return Pose2::new(
+                    start.x + spread * gaussian(rng),
+                    start.y + spread * gaussian(rng),
+                    start.theta + turn * gaussian(rng),
+                );
+            })
+            .collect();
+        return Self {
+            config: localisation,
+            lidar: Lidar::new(&config.lidar),
+            particles,
+        };
+    }
+
+    // Moves every guess by the measured move, with errors like the odometry's own.
+    pub fn predict<R: Rng>(&mut self, odometry: &Odometry, rng: &mut R) {
+        for p in self.particles.iter_mut() {
+            *p = odometry.perturb(&self.config, rng).apply(p);
+        }
+    }
+
+    // Weighs every guess by how well the ranges it would have measured match `rays`, a scan from
+    // the robot's lidar, and draws a fresh cloud by those weights. Only config.beams of the rays
+    // are compared, to keep it quick. If no guess could have taken the scan the cloud is left as
+    // it is.
+    pub fn update<R: Rng>(&mut self, playground: &Playground, rays: &[Ray], rng: &mut R) {
+        let angles = self.lidar.angles();
+        let stride = (rays.len() / self.config.beams).max(1);
+        let beams: Vec<usize> = (0..rays.len()).step_by(stride).collect();
+
+        // One lookup covers every guess: the obstacles within range of the estimate, or of
+        // whichever guess is furthest from it.
+        let max_range = self.lidar.max_range();
+        let centre = self.estimate();
+        let furthest = self
+            .particles
+            .iter()
+            .map(|p| p.translation(&centre))
+            .fold(0.0, f64::max);
+        let obstacles = playground.obstacles_near(centre.x, centre.y, max_range + furthest);
+
+        let (w, h) = (playground.size.0 as f64, playground.size.1 as f64);
+        let sigma = self.config.sensor_noise;
+        let log_weights: Vec<f64> = self
+            .particles
+            .iter()
+            .map(|p| {
+                let outside = p.x < 0.0 || p.y < 0.0 || p.x > w || p.y > h;
+                if outside || obstacles.iter().any(|o| o.contains(p.x, p.y)) {
+                    return f64::NEG_INFINITY;
+                }
+                return beams
+                    .iter()
+                    .map(|&i| {
+                        let angle = normalize_angle(p.theta + angles[i]);
+                        let expected = cast(playground, &obstacles, p.x, p.y, angle, max_range)
+                            .unwrap_or(max_range);
+                        let error = (rays[i].range - expected) / sigma;
+                        return ((-0.5 * error * error).exp() + STRAY_READING).ln();
+                    })
+                    .sum();
+            })
+            .collect();
+        let best = log_weights
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if best == f64::NEG_INFINITY {
+            return;
+        }
+        // Relative to the best, so the likeliest guesses don't all round to nothing.
+        let weights: Vec<f64> = log_weights.iter().map(|l| (l - best).exp()).collect();
+
+        // Low-variance resampling: one random offset, then evenly spaced picks along the running
+        // total of the weights.
+        let n = self.particles.len();
+        let step = weights.iter().sum::<f64>() / n as f64;
+        let mut pick = rng.gen::<f64>() * step;
+        let mut total = weights[0];
+        let mut i = 0;
+        let mut particles = Vec::with_capacity(n);
+        for _ in 0..n {
+            while total < pick && i < n - 1 {
+                i += 1;
+                total += weights[i];
+            }
+            let p = self.particles[i];
+            particles.push(Pose2::new(
+                p.x + ROUGHENING.0 * gaussian(rng),
+                p.y + ROUGHENING.0 * gaussian(rng),
+                p.theta + ROUGHENING.1 * gaussian(rng),
+            ));
+            pick += step;
+        }
+        self.particles = particles;
+    }
+
+    // The average of the guesses, averaging the headings round the circle.
+    pub fn estimate(&self) -> Pose2 {
+        let n = self.particles.len() as f64;
+        let (mut x, mut y, mut sin, mut cos) = (0.0, 0.0, 0.0, 0.0);
+        for p in &self.particles {
+            x += p.x / n;
+            y += p.y / n;
+            sin += p.theta.sin();
+            cos += p.theta.cos();
+        }
+        return Pose2::new(x, y, sin.atan2(cos));
+    }
+}
+
+// What the simulated robot believes about where it is. It reads odometry every step and scans
+// every config.localisation.scan_interval seconds, feeding both to a particle filter, and keeps a
+// dead-reckoned pose from the odometry alone to show how far that would have drifted.
+#[derive(Clone, Debug)]
+pub struct Localiser {
+    pub filter: ParticleFilter,
+    pub estimate: Pose2,       // The filter's estimate as of the last step
+    pub dead_reckoning: Pose2, // Odometry alone
+    lidar: Lidar,
+    scan_interval: f64,
+    since_scan: f64, // Seconds since the last scan
+    rng: ChaCha8Rng,
+}
+
+impl Localiser {
+    pub fn new(config: &PlannerConfig, start: &Pose2, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let filter = ParticleFilter::new(config, start, &mut rng);
+        return Self {
+            estimate: filter.estimate(),
+            filter,
+            dead_reckoning: *start,
+            lidar: Lidar::new(&config.lidar),
+            scan_interval: config.localisation.scan_interval,
+            since_scan: 0.0,
+            rng,
+        };
+    }
+
+    // Catches up with a move the robot made from `from` to `to` in `dt` seconds. Returns true if
+    // it scanned, and so drew a fresh cloud.
+    pub fn step(&mut self, playground: &Playground, from: &Pose2, to: &Pose2, dt: f64) -> bool {
+        let odometry = Odometry::between(from, to).perturb(&self.filter.config, &mut self.rng);
+        self.dead_reckoning = odometry.apply(&self.dead_reckoning);
+        self.filter.predict(&odometry, &mut self.rng);
+        self.since_scan += dt;
+        // Allowing for rounding, so a whole number of steps per scan stays that way.
+        let scanned = self.since_scan >= self.scan_interval - 1e-9;
+        if scanned {
+            let rays = self.lidar.scan(playground, to, &mut self.rng);
+            self.filter.update(playground, &rays, &mut self.rng);
+            self.since_scan = 0.0;
+        }
+        self.estimate = self.filter.estimate();
+        return scanned;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::Rect;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn assert_near(a: &Pose2, b: &Pose2, distance: f64, turn: f64) {
+        assert!(
+            a.translation(b) < distance && a.rotation(b) < turn,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn odometry() {
+        let from = Pose2::new(10.0, 20.0, FRAC_PI_2);
+        let to = Pose2::new(5.0, 30.0, 0.0);
+        let odometry = Odometry::between(&from, &to);
+        // Facing down the y axis, the move is 10 forward and 5 to the right.
+        assert!((odometry.forward - 10.0).abs() < 1e-9);
+        assert!((odometry.lateral - 5.0).abs() < 1e-9);
+        assert!((odometry.turn + FRAC_PI_2).abs() < 1e-9);
+        assert_near(&odometry.apply(&from), &to, 1e-9, 1e-9);
+
+        // Without noise, odometry is exact.
+        let exact = LocalisationConfig {
+            odometry_noise: 0.0,
+            heading_drift: 0.0,
+            ..Default::default()
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert_eq!(odometry.perturb(&exact, &mut rng), odometry);
+        assert_ne!(
+            odometry.perturb(&LocalisationConfig::default(), &mut rng),
+            odometry
+        );
+    }
+
+    #[test]
+    fn finds_the_robot_from_its_scans() {
+        // An L-shaped room, so every pose in it looks different.
+        let mut playground = Playground::new((300, 300), (0, 0), (0, 0));
+        playground.add_obstacles(Rect {
+            anchor: (150, 150),
+            size: (150, 150),
+        });
+        playground.add_obstacles(Rect {
+            anchor: (60, 40),
+            size: (20, 30),
+        });
+        let config = PlannerConfig {
+            localisation: LocalisationConfig {
+                initial_spread: (20.0, 0.3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let truth = Pose2::new(100.0, 100.0, 0.3);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut filter = ParticleFilter::new(&config, &Pose2::new(115.0, 90.0, 0.0), &mut rng);
+        assert_eq!(filter.particles.len(), 300);
+        let lidar = Lidar::new(&config.lidar);
+        for _ in 0..20 {
+            let rays = lidar.scan(&playground, &truth, &mut rng);
+            filter.update(&playground, &rays, &mut rng);
+        }
+        assert_near(&filter.estimate(), &truth, 3.0, 0.05);
+
+        // Nothing in the cloud could have taken a scan if it's all inside an obstacle, so the
+        // cloud stays as it is.
+        let rays = lidar.scan(&playground, &truth, &mut rng);
+        let before = filter.particles.clone();
+        playground.add_obstacles(Rect {
+            anchor: (0, 0),
+            size: (150, 150),
+        });
+        filter.update(&playground, &rays, &mut rng);
+        assert_eq!(filter.particles, before);
+    }
+}
@@ -1,208 +1,738 @@
-extern crate glutin_window;
-extern crate graphics;
-extern crate opengl_graphics;
-extern crate piston;
-
-use glutin_window::GlutinWindow as Window;
-use opengl_graphics::{GlGraphics, OpenGL};
-use piston::event_loop::{EventSettings, Events};
-use piston::input::{RenderArgs, RenderEvent, UpdateArgs, UpdateEvent};
-use piston::window::WindowSettings;
-
-pub mod playground;
-use playground::{Playground, Rect};
-
-pub mod planner;
-use planner::Planner;
-
-pub struct App {
-    gl: GlGraphics, // OpenGL drawing backend.
-    playground: Playground,
-    planner: Planner,
-    t: f64,
-}
-
-impl App {
-    fn render(&mut self, args: &RenderArgs) {
-        // Import _everything_ for now because otherwise we get trait method errors.
-        use graphics::*;
-        self.gl.draw(args.viewport(), |c, gl| {
-            clear(color::WHITE, gl);
-
-            let scale = [
-                args.window_size[0] / (self.playground.size.0 as f64),
-                args.window_size[1] / (self.playground.size.1 as f64),
-            ];
-
-            // Render obstacles
-            for r in self.playground.get_obstacles() {
-                let [ax, ay] = math::mul([r.anchor.0 as f64, r.anchor.1 as f64], scale);
-                let [sx, sy] = math::mul([r.size.0 as f64, r.size.1 as f64], scale);
-                let r = rectangle::rectangle_by_corners(ax, ay, ax + sx, ay + sy);
-                rectangle(color::BLACK, r, c.transform, gl);
-            }
+#![allow(clippy::needless_return)]
 
-            // Render path
-            let [sx, sy] = math::mul(
-                [
-                    self.playground.start.0 as f64,
-                    self.playground.start.1 as f64,
-                ],
-                scale,
-            );
-            let [gx, gy] = math::mul(
-                [self.playground.goal.0 as f64, self.playground.goal.1 as f64],
-                scale,
-            );
-            {
-                let mut lp = [sx, sy];
-                for next_pose in &self.planner.full_path {
-                    let np = math::mul([next_pose.x as f64, next_pose.y as f64], scale);
-                    line_from_to(
-                        color::GREEN,
-                        1.0,
-                        [lp[0], lp[1]],
-                        [np[0], np[1]],
-                        c.transform,
-                        gl,
-                    );
-                    lp = np;
-                }
-            }
-            {
-                let mut lp = [sx, sy];
-                for next_pose in &self.planner.compact_path {
-                    let np = math::mul([next_pose.x as f64, next_pose.y as f64], scale);
-                    line_from_to(
-                        color::RED,
-                        1.0,
-                        [lp[0], lp[1]],
-                        [np[0], np[1]],
-                        c.transform,
-                        gl,
-                    );
-                    lp = np;
-                }
-            }
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use path_planner::{
+    coverage::Coverage,
+    explore::{Exploration, Mission},
+    planner::Progress,
+    playground, render,
+    simulation::Simulation,
+    tour::{Order, Tour},
+    Algorithm, CompactionMode, Controller, DistanceMetric, Optimiser, PlanStats, Planner,
+    PlannerConfig, Playground, Profile, Scenario, Smoothing,
+};
+
+// Generated maps smaller than this on either side leave no room for the start and goal.
+const MIN_GENERATE_SIZE: i32 = 16;
+
+#[derive(Parser)]
+#[command(version, about = "A toy 2D robot path planner")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-            // Render start/goal
-            let r = 10.0 * (scale[0].powf(2.0) + scale[1].powf(2.0)).sqrt();
-            let start = ellipse::circle(sx, sy, r);
-            let goal = ellipse::circle(gx, gy, r);
-            ellipse(color::RED, start, c.transform, gl);
-            ellipse(color::RED, goal, c.transform, gl);
-
-            // Render actor
-            let [acx, acy] = math::mul(
-                [self.planner.pose.x as f64, self.planner.pose.y as f64],
-                scale,
-            );
-            let [asx, asy] = math::mul(
-                [self.planner.size.0 as f64, self.planner.size.1 as f64],
-                scale,
-            );
-            let transform = c
-                .transform
-                .trans(acx, acy)
-                .rot_deg(self.planner.pose.t as f64)
-                .trans(asx / -2.0, asy / -2.0);
-            let r = rectangle::rectangle_by_corners(0.0, 0.0, asx, asy);
-            rectangle(color::BLUE, r, transform, gl);
-        });
+#[derive(Subcommand)]
+enum Command {
+    /// Plan a path through a scenario and print it
+    Plan {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Print the raw RRT path instead of the compacted one
+        #[arg(long)]
+        full: bool,
+        /// Print the smoothed path, sampled every few pixels, instead of the compacted one
+        #[arg(long, conflicts_with = "full")]
+        smoothed: bool,
+        /// Print the timed trajectory, with velocities and accelerations, instead of the path
+        #[arg(long, conflicts_with_all = ["full", "smoothed"])]
+        trajectory: bool,
+        /// Print planning statistics to stderr
+        #[arg(long)]
+        stats: bool,
+        /// Print search progress to stderr while planning
+        #[arg(long)]
+        progress: bool,
+        /// Give up after this many seconds
+        #[arg(long)]
+        timeout: Option<f64>,
+        /// Keep searching for shorter paths after the first, reporting each to stderr
+        #[arg(long)]
+        anytime: bool,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Plan a path, then drive the robot along it with a path-tracking controller and print
+    /// each step of the simulation
+    Simulate {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        /// Steer by a particle filter's estimate of the pose, from noisy odometry and lidar scans,
+        /// instead of the true pose
+        #[arg(long)]
+        localise: bool,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Drive the robot to the goal knowing only the obstacles its lidar has seen, replanning as
+    /// new ones turn up, and print each step of the run
+    Explore {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        /// Instead of heading for the goal, map as much of the playground as the robot can reach
+        /// by visiting the frontiers between the space it has seen and the space it hasn't
+        #[arg(long)]
+        frontiers: bool,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Plan a path that sweeps a tool over every part of the playground the robot can reach, and
+    /// print its waypoints
+    Coverage {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Plan a mission from the start to the goal calling at every waypoint in the scenario, and
+    /// print its timed trajectory
+    Tour {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        /// Call at the waypoints in whichever order makes the cheapest tour, rather than as listed
+        #[arg(long)]
+        any_order: bool,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Plan a path and write the result as an SVG image
+    Render {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Plan repeatedly and report timing and path statistics
+    Bench {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        trials: usize,
+        /// Repeat the benchmark with 1, 2, 4, ... threads up to one per core and report the
+        /// speedup over a single thread. Needs --runs above 1 or --planner prm
+        #[arg(long)]
+        scaling: bool,
+    },
+    /// Generate a random scenario
+    Generate {
+        #[arg(long, default_value_t = 800)]
+        width: i32,
+        #[arg(long, default_value_t = 800)]
+        height: i32,
+        #[arg(long, default_value_t = 20)]
+        obstacles: usize,
+        /// Free space to keep around the start and goal
+        #[arg(long, default_value_t = 40)]
+        clearance: i32,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Open the interactive viewer
+    #[cfg(feature = "gui")]
+    View {
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        planner: PlannerArgs,
+        /// Set off on the first path found and switch to shorter ones as they are found
+        #[arg(long)]
+        anytime: bool,
+    },
+}
+
+#[derive(Args)]
+struct ScenarioArgs {
+    /// Scenario JSON file. Defaults to the built-in demo map.
+    #[arg(short, long)]
+    scenario: Option<PathBuf>,
+    /// Heading the robot has to finish facing along, in radians
+    #[arg(long, allow_negative_numbers = true)]
+    goal_heading: Option<f64>,
+    /// Finish anywhere within this many pixels of the goal
+    #[arg(long)]
+    goal_radius: Option<f64>,
+    /// Finish facing within this many radians of the goal heading either way. π or more allows
+    /// any heading.
+    #[arg(long)]
+    heading_tolerance: Option<f64>,
+}
+
+impl ScenarioArgs {
+    fn load(&self) -> Result<Playground, Box<dyn Error>> {
+        let mut playground = match &self.scenario {
+            None => playground::demo((800, 800)),
+            Some(path) => Scenario::load(path)?.to_playground(),
+        };
+        playground.goal_heading = self.goal_heading.unwrap_or(playground.goal_heading);
+        let region = &mut playground.goal_region;
+        region.radius = self.goal_radius.unwrap_or(region.radius);
+        region.heading_tolerance = self.heading_tolerance.unwrap_or(region.heading_tolerance);
+        region.validate()?;
+        if !playground.goal_heading.is_finite() {
+            return Err(format!(
+                "goal heading must be finite, got {}",
+                playground.goal_heading
+            )
+            .into());
+        }
+        return Ok(playground);
     }
+}
+
+#[derive(Args, Clone)]
+struct PlannerArgs {
+    #[arg(long, value_enum)]
+    planner: Option<Algorithm>,
+    /// JSON planner config file. Flags below override individual fields.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Position sampling resolution, in pixels
+    #[arg(long)]
+    grid_size: Option<i32>,
+    /// Heading sampling resolution, in degrees
+    #[arg(long)]
+    angle_step: Option<i32>,
+    /// Probability of sampling the goal directly
+    #[arg(long)]
+    goal_bias: Option<f64>,
+    /// Longest edge added to the tree
+    #[arg(long)]
+    step_size: Option<f64>,
+    /// Samples to draw before giving up
+    #[arg(long)]
+    max_iterations: Option<usize>,
+    /// Robot speed along the path, in metric units per second: pixels, plus the config's
+    /// heading_weight for each radian turned
+    #[arg(long)]
+    speed: Option<f64>,
+    /// How the robot speeds up and slows down
+    #[arg(long, value_enum)]
+    profile: Option<Profile>,
+    /// Acceleration limit for the robot's centre, in pixels per second squared
+    #[arg(long)]
+    max_acceleration: Option<f64>,
+    /// Jerk limit for the S-curve profile, in pixels per second cubed, setting how long it takes
+    /// to reach max-acceleration
+    #[arg(long)]
+    max_jerk: Option<f64>,
+    #[arg(long, value_enum)]
+    compaction: Option<CompactionMode>,
+    /// Optimisers to run on the compacted path, in order, e.g. shortcut,elastic-band
+    #[arg(long, value_enum, value_delimiter = ',')]
+    optimise: Option<Vec<Optimiser>>,
+    /// Attempts each optimiser makes
+    #[arg(long)]
+    optimise_iterations: Option<usize>,
+    /// How the compacted path is smoothed before the robot follows it
+    #[arg(long, value_enum)]
+    smoothing: Option<Smoothing>,
+    /// Arc radius for arc smoothing, in pixels
+    #[arg(long)]
+    corner_radius: Option<f64>,
+    /// How the simulated robot steers to follow the path
+    #[arg(long, value_enum)]
+    controller: Option<Controller>,
+    /// Pixels down the path that pure pursuit and PID heading control aim for
+    #[arg(long)]
+    lookahead: Option<f64>,
+    /// Steps the MPC controller looks ahead
+    #[arg(long)]
+    mpc_horizon: Option<usize>,
+    /// Seconds per MPC step, which is also how often the MPC controller re-plans
+    #[arg(long)]
+    mpc_step: Option<f64>,
+    /// Seconds ahead the DWA controller simulates each command
+    #[arg(long)]
+    dwa_horizon: Option<f64>,
+    /// Linear and angular speeds the DWA controller tries each tick, in each direction
+    #[arg(long)]
+    dwa_samples: Option<usize>,
+    /// Side of a cell of the map built while exploring, in pixels
+    #[arg(long)]
+    belief_cell: Option<i32>,
+    /// Pixels the exploring robot moves between lidar scans
+    #[arg(long)]
+    explore_step: Option<f64>,
+    /// Unknown cells a frontier must promise for every pixel it is away to be worth the trip
+    #[arg(long)]
+    frontier_weight: Option<f64>,
+    /// Width of the strip a coverage sweep covers, in pixels
+    #[arg(long)]
+    tool_width: Option<f64>,
+    /// Fraction of the tool's width that neighbouring coverage sweeps share
+    #[arg(long)]
+    overlap: Option<f64>,
+    /// Guesses at the pose the particle filter keeps when localising
+    #[arg(long)]
+    particles: Option<usize>,
+    /// How path costs are measured
+    #[arg(long, value_enum)]
+    metric: Option<DistanceMetric>,
+    /// Poses sampled for the PRM roadmap
+    #[arg(long)]
+    roadmap_samples: Option<usize>,
+    /// Independently seeded searches to run in parallel, keeping the shortest path
+    #[arg(long)]
+    runs: Option<usize>,
+    /// Worker threads. Defaults to one per core.
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Searches an anytime plan runs while looking for shorter paths
+    #[arg(long)]
+    anytime_rounds: Option<usize>,
+    /// Seed for the sampler. Reusing the seed printed by a previous run replays it exactly.
+    #[arg(long)]
+    seed: Option<u64>,
+}
 
-    fn update(&mut self, args: &UpdateArgs) {
-        if !self.planner.compute_path(&self.playground) {
-            // Don't start the animation until we have computed the path.
-            return;
+impl PlannerArgs {
+    fn config(&self) -> Result<PlannerConfig, Box<dyn Error>> {
+        let mut config = match &self.config {
+            None => PlannerConfig::default(),
+            Some(path) => PlannerConfig::load(path)?,
+        };
+        config.algorithm = self.planner.unwrap_or(config.algorithm);
+        config.grid_size = self.grid_size.unwrap_or(config.grid_size);
+        config.angle_step = self.angle_step.unwrap_or(config.angle_step);
+        config.goal_bias = self.goal_bias.unwrap_or(config.goal_bias);
+        config.step_size = self.step_size.or(config.step_size);
+        config.max_iterations = self.max_iterations.or(config.max_iterations);
+        config.speed = self.speed.unwrap_or(config.speed);
+        config.profile = self.profile.unwrap_or(config.profile);
+        config.max_acceleration = self.max_acceleration.unwrap_or(config.max_acceleration);
+        config.max_jerk = self.max_jerk.unwrap_or(config.max_jerk);
+        config.compaction = self.compaction.unwrap_or(config.compaction);
+        config.optimisers = self.optimise.clone().unwrap_or(config.optimisers);
+        config.optimise_iterations = self
+            .optimise_iterations
+            .unwrap_or(config.optimise_iterations);
+        config.smoothing = self.smoothing.unwrap_or(config.smoothing);
+        config.corner_radius = self.corner_radius.unwrap_or(config.corner_radius);
+        config.controller = self.controller.unwrap_or(config.controller);
+        config.lookahead = self.lookahead.unwrap_or(config.lookahead);
+        config.mpc_horizon = self.mpc_horizon.unwrap_or(config.mpc_horizon);
+        config.mpc_step = self.mpc_step.unwrap_or(config.mpc_step);
+        config.dwa_horizon = self.dwa_horizon.unwrap_or(config.dwa_horizon);
+        config.dwa_samples = self.dwa_samples.unwrap_or(config.dwa_samples);
+        config.belief_cell = self.belief_cell.unwrap_or(config.belief_cell);
+        config.explore_step = self.explore_step.unwrap_or(config.explore_step);
+        config.frontier_weight = self.frontier_weight.unwrap_or(config.frontier_weight);
+        config.tool_width = self.tool_width.unwrap_or(config.tool_width);
+        config.overlap = self.overlap.unwrap_or(config.overlap);
+        config.localisation.particles = self.particles.unwrap_or(config.localisation.particles);
+        config.metric = self.metric.unwrap_or(config.metric);
+        config.roadmap_samples = self.roadmap_samples.unwrap_or(config.roadmap_samples);
+        config.runs = self.runs.unwrap_or(config.runs);
+        config.threads = self.threads.or(config.threads);
+        config.anytime_rounds = self.anytime_rounds.unwrap_or(config.anytime_rounds);
+        config.validate()?;
+        return Ok(config);
+    }
+
+    fn build(&self, playground: &Playground) -> Result<Planner, Box<dyn Error>> {
+        let config = self.config()?;
+        config.validate_for(playground)?;
+        let mut planner = Planner::with_config(playground, config);
+        if let Some(seed) = self.seed {
+            planner.seed = seed;
         }
-        self.t += args.dt;
-        self.planner.update_pos(self.t);
+        return Ok(planner);
     }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+}
+
 fn main() {
-    let opengl_version = OpenGL::V3_2;
-    let initial_size = (800, 800);
-    let mut window: Window = WindowSettings::new("playground", initial_size)
-        .graphics_api(opengl_version)
-        .exit_on_esc(true)
-        .build()
-        .unwrap();
-
-    let playground = setup_playground(initial_size);
-    let planner = Planner::new(&playground);
-    let mut app = App {
-        gl: GlGraphics::new(opengl_version),
-        playground,
-        planner,
-        t: -1.0,
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Plan {
+            scenario,
+            planner,
+            format,
+            full,
+            smoothed,
+            trajectory,
+            stats,
+            progress,
+            timeout,
+            anytime,
+            output,
+        } => {
+            if let Some(t) = timeout.filter(|t| !(*t >= 0.0 && t.is_finite())) {
+                return Err(format!(
+                    "timeout must be a finite number of seconds, at least 0, got {t}"
+                )
+                .into());
+            }
+            let playground = scenario.load()?;
+            let mut planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            if progress || timeout.is_some() {
+                let deadline = timeout.map(|t| Instant::now() + Duration::from_secs_f64(t));
+                planner.monitor = Some(Arc::new(move |p: &Progress| {
+                    if progress {
+                        eprintln!("{}", p.lines().join(", "));
+                    }
+                    return deadline.is_none_or(|d| Instant::now() < d);
+                }));
+            }
+            match anytime {
+                true => {
+                    planner.plan_anytime(&playground, |s| {
+                        eprintln!("round {}: length {:.1}", s.round, s.stats.compact_length);
+                        return true;
+                    });
+                }
+                false => {
+                    planner.compute_path(&playground);
+                }
+            }
+            if stats {
+                eprint!("{}", planner.stats);
+            }
+            if planner.stats.cancelled && (!anytime || planner.full_path.is_empty()) {
+                return Err("timed out".into());
+            }
+            if planner.full_path.is_empty() {
+                return Err("no path found".into());
+            }
+            let mut out = open_output(output.as_deref())?;
+            match (full, smoothed, trajectory) {
+                (true, _, _) => write_rows(&mut out, &planner.full_path, format)?,
+                (_, true, _) => write_rows(&mut out, &planner.smooth_path, format)?,
+                (_, _, true) => {
+                    let points = &planner.trajectory().unwrap().points;
+                    write_rows(&mut out, points, format)?
+                }
+                _ => write_rows(&mut out, &planner.compact_path, format)?,
+            }
+        }
+        Command::Simulate {
+            scenario,
+            planner,
+            localise,
+            format,
+            output,
+        } => {
+            let playground = scenario.load()?;
+            let mut planner = planner.build(&playground)?;
+            planner.config.localise |= localise;
+            eprintln!("seed: {}", planner.seed);
+            planner.compute_path(&playground);
+            if planner.full_path.is_empty() {
+                return Err("no path found".into());
+            }
+            let simulation = Simulation::run(&planner, &playground);
+            eprintln!("{}", simulation.lines().join("\n"));
+            write_rows(
+                &mut open_output(output.as_deref())?,
+                &simulation.samples,
+                format,
+            )?;
+        }
+        Command::Explore {
+            scenario,
+            planner,
+            frontiers,
+            format,
+            output,
+        } => {
+            let playground = scenario.load()?;
+            let planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            let mission = match frontiers {
+                true => Mission::Frontiers,
+                false => Mission::Goal,
+            };
+            let exploration = Exploration::run(&playground, &planner.config, mission, planner.seed);
+            eprintln!("{}", exploration.lines().join("\n"));
+            write_rows(
+                &mut open_output(output.as_deref())?,
+                &exploration.samples,
+                format,
+            )?;
+        }
+        Command::Coverage {
+            scenario,
+            planner,
+            format,
+            output,
+        } => {
+            let playground = scenario.load()?;
+            let planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            let coverage = Coverage::plan(&playground, &planner.config, planner.seed);
+            eprintln!("{}", coverage.lines().join("\n"));
+            write_rows(&mut open_output(output.as_deref())?, &coverage.path, format)?;
+        }
+        Command::Tour {
+            scenario,
+            planner,
+            any_order,
+            format,
+            output,
+        } => {
+            let playground = scenario.load()?;
+            let planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            let order = match any_order {
+                true => Order::Any,
+                false => Order::Listed,
+            };
+            let tour = Tour::plan(&playground, &planner.config, order, planner.seed);
+            eprintln!("{}", tour.lines().join("\n"));
+            write_rows(
+                &mut open_output(output.as_deref())?,
+                &tour.trajectory.points,
+                format,
+            )?;
+        }
+        Command::Render {
+            scenario,
+            planner,
+            output,
+        } => {
+            let playground = scenario.load()?;
+            let mut planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            planner.compute_path(&playground);
+            fs::write(output, render::to_svg(&playground, &planner))?;
+        }
+        Command::Bench {
+            scenario,
+            planner,
+            trials,
+            scaling,
+        } => {
+            let playground = scenario.load()?;
+            match scaling {
+                true => bench_scaling(&playground, &planner, trials)?,
+                false => print_bench(&bench(&playground, &planner, trials)?),
+            }
+        }
+        Command::Generate {
+            width,
+            height,
+            obstacles,
+            clearance,
+            seed,
+            output,
+        } => {
+            if width < MIN_GENERATE_SIZE || height < MIN_GENERATE_SIZE {
+                return Err(format!(
+                    "width and height must be at least {MIN_GENERATE_SIZE}, got {width}x{height}"
+                )
+                .into());
+            }
+            // The start and goal sit `clearance` in from opposite corners, so it has to leave
+            // room between them.
+            if clearance < 0 || 2 * clearance >= width.min(height) {
+                return Err(format!(
+                    "clearance must be at least 0 and less than half the width and height, got \
+                     {clearance}"
+                )
+                .into());
+            }
+            let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+            eprintln!("seed: {seed}");
+            let scenario = Scenario::generate(
+                (width, height),
+                obstacles,
+                clearance,
+                &mut ChaCha8Rng::seed_from_u64(seed),
+            )?;
+            let mut out = open_output(output.as_deref())?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&scenario)?)?;
+        }
+        #[cfg(feature = "gui")]
+        Command::View {
+            scenario,
+            planner,
+            anytime,
+        } => {
+            let playground = scenario.load()?;
+            let planner = planner.build(&playground)?;
+            eprintln!("seed: {}", planner.seed);
+            path_planner::gui::run(playground, planner, anytime);
+        }
+    }
+    return Ok(());
+}
+
+fn open_output(path: Option<&Path>) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    return match path {
+        None => Ok(Box::new(io::stdout())),
+        Some(path) => Ok(Box::new(fs::File::create(path)?)),
     };
+}
 
-    let mut events = Events::new(EventSettings::new());
-    while let Some(e) = events.next(&mut window) {
-        if let Some(args) = e.render_args() {
-            app.render(&args);
+fn write_rows<T: Serialize>(
+    out: &mut dyn Write,
+    rows: &[T],
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(rows)?)?,
+        Format::Csv => {
+            let mut w = csv::Writer::from_writer(out);
+            for row in rows {
+                w.serialize(row)?;
+            }
+            w.flush()?;
         }
+    }
+    return Ok(());
+}
 
-        if let Some(args) = e.update_args() {
-            app.update(&args);
+fn bench(
+    playground: &Playground,
+    args: &PlannerArgs,
+    trials: usize,
+) -> Result<Vec<PlanStats>, Box<dyn Error>> {
+    let mut runs = Vec::new();
+    for i in 0..trials {
+        let mut planner = args.build(playground)?;
+        // A fixed seed makes the whole benchmark reproducible rather than every trial identical.
+        if let Some(seed) = args.seed {
+            planner.seed = seed.wrapping_add(i as u64);
         }
+        planner.compute_path(playground);
+        runs.push(planner.stats);
     }
+    return Ok(runs);
 }
 
-fn setup_playground(initial_size: (u32, u32)) -> Playground {
-    let start = (50, 50);
-    let goal = (750, 50);
-    let mut playground =
-        Playground::new((initial_size.0 as i32, initial_size.1 as i32), start, goal);
-
-    let obstacles = [
-        // vertical barrier 1
-        Rect {
-            anchor: (200, 0),
-            size: (100, 650),
-        },
-        Rect {
-            anchor: (200, 700),
-            size: (100, 100),
-        },
-        // vertical barrier 2
-        Rect {
-            anchor: (650, 0),
-            size: (50, 100),
-        },
-        Rect {
-            anchor: (650, 200),
-            size: (50, 100),
-        },
-        // vertical barrier 3
-        Rect {
-            anchor: (500, 50),
-            size: (50, 200),
-        },
-        // horizontal barrier 1
-        Rect {
-            anchor: (300, 500),
-            size: (400, 100),
-        },
-        Rect {
-            anchor: (750, 500),
-            size: (300, 100),
-        },
-        // horizontal barrier 2
-        Rect {
-            anchor: (300, 300),
-            size: (50, 100),
-        },
-        Rect {
-            anchor: (450, 300),
-            size: (400, 100),
-        },
-    ];
-    for o in obstacles {
-        playground.add_obstacles(o);
+fn print_bench(runs: &[PlanStats]) {
+    let seeds: Vec<u64> = runs.iter().map(|s| s.seed).collect();
+    println!("trials: {}", runs.len());
+    println!("seeds: {seeds:?}");
+    println!("failures: {}", runs.iter().filter(|s| !s.success).count());
+    let field = |f: &dyn Fn(&PlanStats) -> f64| runs.iter().map(f).collect::<Vec<f64>>();
+    print_stats("total (ms)", &field(&|s| ms(s.total_time())));
+    print_stats("search (ms)", &field(&|s| ms(s.search_time)));
+    print_stats("compact (ms)", &field(&|s| ms(s.compaction_time)));
+    print_stats("optimise (ms)", &field(&|s| ms(s.optimisation_time)));
+    print_stats("spline (ms)", &field(&|s| ms(s.spline_time)));
+    print_stats("iterations", &field(&|s| s.iterations as f64));
+    print_stats("tree size", &field(&|s| s.tree_size as f64));
+    print_stats("pose checks", &field(&|s| s.pose_checks as f64));
+    print_stats("path checks", &field(&|s| s.path_checks as f64));
+
+    // Path quality only means something for the runs that found a path.
+    let found: Vec<&PlanStats> = runs.iter().filter(|s| s.success).collect();
+    let field = |f: &dyn Fn(&PlanStats) -> f64| found.iter().map(|s| f(s)).collect::<Vec<f64>>();
+    print_stats("raw length", &field(&|s| s.full_length));
+    print_stats("path length", &field(&|s| s.compact_length));
+    print_stats("waypoints", &field(&|s| s.compact_waypoints as f64));
+}
+
+fn bench_scaling(
+    playground: &Playground,
+    args: &PlannerArgs,
+    trials: usize,
+) -> Result<(), Box<dyn Error>> {
+    if trials == 0 {
+        return Err("--scaling needs at least 1 trial".into());
+    }
+    // A single RRT search runs on one thread, so only best-of runs and PRM have anything to
+    // spread across more.
+    let config = args.build(playground)?.config;
+    if config.runs <= 1 && config.algorithm != Algorithm::Prm {
+        return Err("--scaling needs --runs above 1 or --planner prm".into());
+    }
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = vec![1];
+    while threads.last().unwrap() * 2 <= cores {
+        threads.push(threads.last().unwrap() * 2);
+    }
+    if *threads.last().unwrap() != cores {
+        threads.push(cores);
+    }
+
+    // Every thread count plans the same searches, so the speedup only measures the threads.
+    let seed = args.seed.unwrap_or_else(rand::random);
+    eprintln!("seed: {seed}");
+    let mut baseline = None;
+    println!("{:>8} {:>12} {:>8}", "threads", "mean (ms)", "speedup");
+    for n in threads {
+        let args = PlannerArgs {
+            threads: Some(n),
+            seed: Some(seed),
+            ..args.clone()
+        };
+        let runs = bench(playground, &args, trials)?;
+        let mean = runs.iter().map(|s| ms(s.total_time())).sum::<f64>() / trials as f64;
+        let baseline = *baseline.get_or_insert(mean);
+        println!("{n:>8} {mean:>12.2} {:>7.2}x", baseline / mean);
     }
+    return Ok(());
+}
+
+fn ms(d: Duration) -> f64 {
+    return d.as_secs_f64() * 1000.0;
+}
 
-    return playground;
+fn print_stats(name: &str, samples: &[f64]) {
+    if samples.is_empty() {
+        return;
+    }
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let stddev = (samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let min = samples.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    println!("{name:>12}: mean {mean:.2} stddev {stddev:.2} min {min:.2} max {max:.2}");
 }
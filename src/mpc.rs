@@ -0,0 +1,358 @@
+use crate::config::{MpcWeights, PlannerConfig};
+use crate::controller::TrackedPath;
+use crate::planner::Planner;
+use crate::playground::{Playground, Rect};
+use crate::pose::{angle_diff, Pose2};
+use crate::robot::{Control, RobotModel};
+
+// Added to the cost of every step in which the robot hits an obstacle or leaves the playground,
+// so no amount of tracking makes a collision worth it.
+const COLLISION_COST: f64 = 1e6;
+
+// The robot is charged for coming closer than this to an obstacle, in pixels.
+const CLEARANCE_MARGIN: f64 = 10.0;
+
+// Rollouts check for collisions at least this often, in pixels travelled at full speed.
+const COLLISION_SPACING: f64 = 0.5;
+
+// Nudge given to each control when estimating the gradient by finite differences.
+const GRADIENT_DELTA: f64 = 1e-3;
+
+// The first step of each line search, in units of the normalised controls, and how many times
+// it is halved before the optimiser gives up improving the plan.
+const INITIAL_STEP: f64 = 0.5;
+const LINE_SEARCH_HALVINGS: usize = 8;
+
+// A model predictive controller. Every config.mpc_step seconds it rolls the robot model forwards
+// over the next config.mpc_horizon steps and optimises the commands for those steps against a
+// cost: keeping up with a reference pose moving down the path at config.speed, changing the
+// commands smoothly, and keeping clear of obstacles. Only the first command of the plan is
+// carried out before it re-plans from wherever the robot has got to.
+//
+// The controls are optimised as fractions of config.speed and config.max_angular_speed, by
+// gradient descent with a backtracking line search. That finds a local optimum, which is all a
+// controller re-planning this often needs.
+#[derive(Clone, Debug)]
+pub struct Mpc {
+    horizon: usize,
+    step: f64,
+    iterations: usize,
+    weights: MpcWeights,
+    speed: f64,
+    max_angular_speed: f64,
+    plan: Vec<[f64; 3]>, // Linear, lateral and angular speed per step, as fractions of their limits
+    last: [f64; 3],      // The command being carried out, in the same units
+    held: f64,           // Seconds since the plan was last optimised
+}
+
+impl Mpc {
+    pub fn new(config: &PlannerConfig) -> Self {
+        return Self {
+            horizon: config.mpc_horizon,
+            step: config.mpc_step,
+            iterations: config.mpc_iterations,
+            weights: config.mpc_weights,
+            speed: config.speed,
+            max_angular_speed: config.max_angular_speed,
+            plan: vec![[0.0; 3]; config.mpc_horizon],
+            last: [0.0; 3],
+            held: f64::INFINITY,
+        };
+    }
+
+    // The command for the next `dt` seconds, with the robot `progress` pixels along the path.
+    pub fn command(
+        &mut self,
+        planner: &Planner,
+        playground: &Playground,
+        path: &TrackedPath,
+        progress: f64,
+        pose: &Pose2,
+        dt: f64,
+    ) -> Control {
+        if self.held >= self.step - 1e-9 {
+            // Warm start from the rest of the last plan, holding its final command.
+            self.plan.remove(0);
+            let held = *self.plan.last().unwrap_or(&self.last);
+            self.plan.push(held);
+            self.plan = self.optimise(planner, playground, path, progress, pose);
+            self.last = self.plan[0];
+            self.held = 0.0;
+        }
+        self.held += dt;
+        return self.control(&self.last);
+    }
+
+    fn control(&self, u: &[f64; 3]) -> Control {
+        return Control {
+            linear: u[0] * self.speed,
+            lateral: u[1] * self.speed,
+            angular: u[2] * self.max_angular_speed,
+        };
+    }
+
+    // The plan after self.iterations steps of gradient descent from the current one.
+    fn optimise(
+        &self,
+        planner: &Planner,
+        playground: &Playground,
+        path: &TrackedPath,
+        progress: f64,
+        pose: &Pose2,
+    ) -> Vec<[f64; 3]> {
+        let model = playground.robot.model();
+        let (xs, ys) = planner.half_extents(pose.theta);
+        let reach = self.speed * self.step * self.horizon as f64 + xs.hypot(ys) + CLEARANCE_MARGIN;
+        let sideways = model
+            .limit(&Control {
+                lateral: 1.0,
+                ..Default::default()
+            })
+            .lateral
+            != 0.0;
+        let rollout = Rollout {
+            mpc: self,
+            planner,
+            playground,
+            model,
+            path,
+            progress,
+            start: *pose,
+            sideways,
+            obstacles: playground.obstacles_near(pose.x, pose.y, reach),
+        };
+        // Only robots that can move sideways get a say in the lateral speed.
+        let controls = match sideways {
+            true => vec![0, 1, 2],
+            false => vec![0, 2],
+        };
+
+        // Gradient descent only finds the optimum nearest where it starts, so start from whichever
+        // is cheapest of the last plan and a few simple manoeuvres: stopping, and driving
+        // forwards or backwards while turning either way, not at all, or one way then the other.
+        let mut plan = self.plan.clone();
+        let mut cost = rollout.cost(&plan);
+        for linear in [0.0, 1.0, -1.0] {
+            for angular in [0.0, 1.0, -1.0] {
+                for swerve in [false, true] {
+                    let mut candidate = vec![[linear, 0.0, angular]; self.horizon];
+                    if swerve {
+                        for u in &mut candidate[self.horizon / 2..] {
+                            u[2] = -angular;
+                        }
+                    }
+                    let candidate_cost = rollout.cost(&candidate);
+                    if candidate_cost < cost {
+                        (plan, cost) = (candidate, candidate_cost);
+                    }
+                }
+            }
+        }
+
+        let mut step = INITIAL_STEP;
+        for _ in 0..self.iterations {
+            // Estimate the gradient by nudging each control in turn.
+            let mut gradient = vec![[0.0; 3]; plan.len()];
+            for k in 0..plan.len() {
+                for &i in &controls {
+                    let mut nudged = plan.clone();
+                    nudged[k][i] += GRADIENT_DELTA;
+                    gradient[k][i] = (rollout.cost(&nudged) - cost) / GRADIENT_DELTA;
+                }
+            }
+            let norm = gradient.iter().flatten().map(|g| g * g).sum::<f64>().sqrt();
+            if !(norm > 0.0 && norm.is_finite()) {
+                break;
+            }
+
+            // Step downhill, halving the step until the cost goes down.
+            let mut improved = false;
+            for _ in 0..LINE_SEARCH_HALVINGS {
+                let mut candidate = plan.clone();
+                for (u, g) in candidate.iter_mut().zip(&gradient) {
+                    for i in 0..3 {
+                        u[i] = (u[i] - step * g[i] / norm).clamp(-1.0, 1.0);
+                    }
+                }
+                let candidate_cost = rollout.cost(&candidate);
+                if candidate_cost < cost {
+                    (plan, cost) = (candidate, candidate_cost);
+                    improved = true;
+                    break;
+                }
+                step /= 2.0;
+            }
+            if !improved {
+                break;
+            }
+            // Try a longer step next time, in case the last one was too cautious.
+            step = (step * 2.0).min(INITIAL_STEP);
+        }
+        return plan;
+    }
+}
+
+// Everything the cost of a plan depends on besides the plan itself.
+struct Rollout<'a> {
+    mpc: &'a Mpc,
+    planner: &'a Planner,
+    playground: &'a Playground,
+    model: &'a dyn RobotModel,
+    path: &'a TrackedPath,
+    progress: f64,
+    start: Pose2,
+    sideways: bool, // Can the robot move sideways, and so face the way the path has it face?
+    obstacles: Vec<Rect>, // Every obstacle the robot could reach within the horizon
+}
+
+impl Rollout<'_> {
+    fn cost(&self, plan: &[[f64; 3]]) -> f64 {
+        let weights = &self.mpc.weights;
+        let mut pose = self.start;
+        let mut previous = self.mpc.last;
+        let mut cost = 0.0;
+        let substeps = (self.mpc.speed * self.mpc.step / COLLISION_SPACING)
+            .ceil()
+            .max(1.0);
+        for (k, u) in plan.iter().enumerate() {
+            // Check for collisions along the way, not just where each step ends.
+            let mut collides = false;
+            for _ in 0..substeps as usize {
+                let dt = self.mpc.step / substeps;
+                pose = self.model.integrate(&pose, &self.mpc.control(u), dt);
+                collides |= self.collides(&pose);
+            }
+            if collides {
+                cost += COLLISION_COST;
+            }
+
+            // The reference pose moves down the path at full speed, stopping at the end.
+            let distance = self.progress + self.mpc.speed * self.mpc.step * (k + 1) as f64;
+            let (rx, ry) = self.path.point_at(distance);
+            let heading = match self.sideways {
+                true => self.path.theta_at(distance),
+                false => self.path.heading_at(distance),
+            };
+            cost += weights.tracking * ((pose.x - rx).powi(2) + (pose.y - ry).powi(2));
+            cost += weights.heading * angle_diff(pose.theta, heading).powi(2);
+            let change: f64 = (0..3).map(|i| (u[i] - previous[i]).powi(2)).sum();
+            cost += weights.smoothness * change;
+            previous = *u;
+
+            cost += weights.clearance * self.crowding(&pose);
+        }
+        return cost;
+    }
+
+    // The same check as is_valid_pose, against the obstacles looked up for this rollout.
+    fn collides(&self, pose: &Pose2) -> bool {
+        let footprint = self.planner.footprint(pose);
+        return !self.playground.contains(&footprint)
+            || self.obstacles.iter().any(|o| o.intersects(&footprint));
+    }
+
+    // How much of the robot's bounding box, in square pixels, is within CLEARANCE_MARGIN of an
+    // obstacle or the edge of the playground. Measuring an area rather than a distance means sliding
+    // sideways past an obstacle lowers the cost too, so the optimiser can find its way round one
+    // that sits across the path.
+    fn crowding(&self, pose: &Pose2) -> f64 {
+        let (xs, ys) = self.planner.half_extents(pose.theta);
+        let (left, top, right, bottom) = (pose.x - xs, pose.y - ys, pose.x + xs, pose.y + ys);
+        let (w, h) = (self.playground.size.0 as f64, self.playground.size.1 as f64);
+        let m = CLEARANCE_MARGIN;
+        let inside = overlap(left, right, m, w - m) * overlap(top, bottom, m, h - m);
+        let mut crowding = 4.0 * xs * ys - inside;
+        for o in &self.obstacles {
+            let (x0, y0) = (o.anchor.0 as f64, o.anchor.1 as f64);
+            let (x1, y1) = (x0 + o.size.0 as f64, y0 + o.size.1 as f64);
+            crowding += overlap(left, right, x0 - m, x1 + m) * overlap(top, bottom, y0 - m, y1 + m);
+        }
+        return crowding;
+    }
+}
+
+// The length of the overlap between the intervals [a0, a1] and [b0, b1].
+fn overlap(a0: f64, a1: f64, b0: f64, b1: f64) -> f64 {
+    return (a1.min(b1) - a0.max(b0)).max(0.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Controller;
+    use crate::robot::Robot;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn keeps_clear_of_an_obstacle_beside_the_path() {
+        // The path passes a few pixels too close to an obstacle added after it was planned.
+        let mut playground = Playground::new((600, 400), (50, 200), (550, 200));
+        playground.add_obstacles(Rect {
+            anchor: (280, 162),
+            size: (40, 40),
+        });
+        for robot in Robot::defaults() {
+            playground.robot = robot;
+            for controller in [Controller::PurePursuit, Controller::Mpc] {
+                let mut planner = Planner::with_config(
+                    &playground,
+                    PlannerConfig {
+                        controller,
+                        robot_size: (10, 10),
+                        ..Default::default()
+                    },
+                );
+                planner.smooth_path =
+                    vec![Pose2::new(50.0, 200.0, 0.0), Pose2::new(550.0, 200.0, 0.0)];
+                let simulation = Simulation::run(&planner, &playground);
+                let name = format!("{robot:?} {controller:?}");
+                assert!(simulation.reached_goal, "{name}");
+                match controller {
+                    Controller::Mpc => assert_eq!(simulation.collision_time, None, "{name}"),
+                    _ => assert!(simulation.collision_time.is_some(), "{name}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crowding() {
+        let mut playground = Playground::new((500, 500), (0, 0), (0, 0));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (100, 100),
+        });
+        let planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                robot_size: (10, 20),
+                ..Default::default()
+            },
+        );
+        let mpc = Mpc::new(&planner.config);
+        let rollout = Rollout {
+            mpc: &mpc,
+            planner: &planner,
+            playground: &playground,
+            model: playground.robot.model(),
+            path: &TrackedPath::new(&[]),
+            progress: 0.0,
+            start: Pose2::new(0.0, 0.0, 0.0),
+            sideways: true,
+            obstacles: playground.obstacles_near(250.0, 250.0, 250.0),
+        };
+        // Facing along x, the robot's box reaches 5 either side and 10 above and below.
+        assert_eq!(rollout.crowding(&Pose2::new(250.0, 250.0, 0.0)), 200.0);
+        assert!(rollout.collides(&Pose2::new(250.0, 250.0, 0.0)));
+        assert_eq!(rollout.crowding(&Pose2::new(100.0, 100.0, 0.0)), 0.0);
+        // Half the box is within the margin to the left of the obstacle.
+        assert_eq!(rollout.crowding(&Pose2::new(190.0, 250.0, 0.0)), 100.0);
+        assert!(!rollout.collides(&Pose2::new(195.0, 250.0, 0.0)));
+        assert!(rollout.collides(&Pose2::new(195.1, 250.0, 0.0)));
+        // A 5 by 5 corner is within the margin of the top left corner of the obstacle.
+        assert_eq!(rollout.crowding(&Pose2::new(190.0, 185.0, 0.0)), 25.0);
+        // A strip 3 pixels high is within the margin of the top of the playground.
+        assert_eq!(rollout.crowding(&Pose2::new(100.0, 17.0, 0.0)), 30.0);
+        assert!(rollout.collides(&Pose2::new(100.0, 9.9, 0.0)));
+    }
+}
@@ -0,0 +1,353 @@
+use rand::Rng;
+
+use crate::config::Optimiser;
+use crate::planner::Planner;
+use crate::playground::Playground;
+use crate::pose::{angle_diff, Pose2};
+
+// Spacing between the points of an elastic band, in pixels. The band can only bend at its points.
+const BAND_SPACING: f64 = 20.0;
+
+// Clearance beyond the robot's footprint at which the elastic band stops pushing away from
+// obstacles, in pixels.
+const BAND_INFLUENCE: f64 = 20.0;
+
+// Fraction of the way each band point moves towards the midpoint of its neighbours per iteration.
+const BAND_CONTRACTION: f64 = 0.5;
+
+// Pixels the band moves per pixel of clearance it lacks.
+const BAND_REPULSION: f64 = 0.5;
+
+// Moves smaller than this, in pixels or radians, are skipped. The band stops once every point
+// has settled.
+const BAND_TOLERANCE: f64 = 0.01;
+
+// Runs config.optimisers in order on a path and returns the result.
+//
+// Every optimiser only accepts a change once is_valid_path holds for each new edge, so the result
+// is as collision free as the input. The shortcutting optimisers also only accept changes that
+// make the path cheaper under the distance metric. The elastic band trades some cost for clearance
+// and smoothness, so it can make the path slightly more expensive.
+pub fn optimise<R: Rng>(
+    planner: &Planner,
+    playground: &Playground,
+    path: &[Pose2],
+    rng: &mut R,
+) -> Vec<Pose2> {
+    let mut path = path.to_vec();
+    if path.len() < 2 {
+        return path;
+    }
+    for optimiser in &planner.config.optimisers {
+        path = match optimiser {
+            Optimiser::Shortcut => shortcut(planner, playground, &path, rng),
+            Optimiser::PartialShortcut => partial_shortcut(planner, playground, &path, rng),
+            Optimiser::ElasticBand => elastic_band(planner, playground, &path),
+        };
+    }
+    return path;
+}
+
+// Random shortcutting. Picks two points anywhere along the path, not just at waypoints, and
+// replaces everything between them with a straight edge if that is valid and cheaper.
+fn shortcut<R: Rng>(
+    planner: &Planner,
+    playground: &Playground,
+    path: &[Pose2],
+    rng: &mut R,
+) -> Vec<Pose2> {
+    let mut path = path.to_vec();
+    for _ in 0..planner.config.optimise_iterations {
+        let Some((from, to)) = pick_span(planner, &path, rng) else {
+            break;
+        };
+        if from.index == to.index {
+            continue;
+        }
+        if planner.distance(&from.pose, &to.pose) >= to.distance - from.distance {
+            continue;
+        }
+        let mut candidate = path[..=from.index].to_vec();
+        candidate.extend([from.pose, to.pose]);
+        candidate.extend_from_slice(&path[to.index + 1..]);
+        let edges = from.index..from.index + 3;
+        if all_valid(planner, playground, &candidate, edges) {
+            path = dedup(candidate);
+        }
+    }
+    return path;
+}
+
+// Partial shortcutting. Like shortcutting, but interpolates only one of x, y and heading between
+// the two points and leaves the other two as they were. That straightens paths that have to
+// follow an obstacle in one dimension but wander needlessly in another, which a full shortcut
+// would cut straight through.
+fn partial_shortcut<R: Rng>(
+    planner: &Planner,
+    playground: &Playground,
+    path: &[Pose2],
+    rng: &mut R,
+) -> Vec<Pose2> {
+    let mut path = path.to_vec();
+    for _ in 0..planner.config.optimise_iterations {
+        let Some((from, to)) = pick_span(planner, &path, rng) else {
+            break;
+        };
+        if from.index == to.index {
+            continue;
+        }
+        let dimension = rng.gen_range(0..3);
+
+        // The span from `from` to `to`, with each point's distance along it as a fraction.
+        let mut span = vec![(from.pose, 0.0)];
+        let mut along = from.distance;
+        let mut prev = from.pose;
+        for p in &path[from.index + 1..=to.index] {
+            along += planner.distance(&prev, p);
+            span.push((*p, (along - from.distance) / (to.distance - from.distance)));
+            prev = *p;
+        }
+        span.push((to.pose, 1.0));
+
+        let turn = angle_diff(from.pose.theta, to.pose.theta);
+        let straightened = span.iter().map(|(p, s)| match dimension {
+            0 => Pose2::new(from.pose.x + (to.pose.x - from.pose.x) * s, p.y, p.theta),
+            1 => Pose2::new(p.x, from.pose.y + (to.pose.y - from.pose.y) * s, p.theta),
+            _ => Pose2::new(p.x, p.y, from.pose.theta + turn * s),
+        });
+        let mut candidate = path[..=from.index].to_vec();
+        candidate.extend(straightened);
+        candidate.extend_from_slice(&path[to.index + 1..]);
+
+        let edges = from.index..from.index + span.len() + 1;
+        let old = planner.path_cost(&path);
+        if planner.path_cost(&candidate) < old && all_valid(planner, playground, &candidate, edges)
+        {
+            path = dedup(candidate);
+        }
+    }
+    return path;
+}
+
+// Elastic band smoothing. Splits the path into points BAND_SPACING apart, then repeatedly pulls
+// each point towards the midpoint of its neighbours, which shortens and smooths the path, and
+// pushes it down the clearance gradient wherever it passes closer than BAND_INFLUENCE to an
+// obstacle. The ends stay put, and a point only moves if both of its edges stay valid.
+fn elastic_band(planner: &Planner, playground: &Playground, path: &[Pose2]) -> Vec<Pose2> {
+    let mut band = vec![path[0]];
+    for w in path.windows(2) {
+        let n = (w[0].translation(&w[1]) / BAND_SPACING).ceil().max(1.0) as usize;
+        band.extend((1..=n).map(|k| w[0].interpolate(&w[1], k as f64 / n as f64)));
+    }
+
+    let (width, length) = planner.config.robot_size;
+    let footprint = (width as f64).hypot(length as f64) / 2.0;
+    let reach = footprint + BAND_INFLUENCE;
+    for _ in 0..planner.config.optimise_iterations {
+        let mut settled = true;
+        for k in 1..band.len() - 1 {
+            let (prev, p, next) = (band[k - 1], band[k], band[k + 1]);
+            let mut dx = BAND_CONTRACTION * ((prev.x + next.x) / 2.0 - p.x);
+            let mut dy = BAND_CONTRACTION * ((prev.y + next.y) / 2.0 - p.y);
+            let mid_turn = angle_diff(prev.theta, next.theta) / 2.0;
+            let dt = BAND_CONTRACTION * angle_diff(p.theta, prev.theta + mid_turn);
+
+            // The clearance gradient points directly away from the nearest obstacle.
+            if let Some((nx, ny)) = playground.nearest_obstacle(p.x, p.y, reach) {
+                let distance = (p.x - nx).hypot(p.y - ny);
+                if distance > 0.0 {
+                    let push = BAND_REPULSION * (reach - distance) / distance;
+                    dx += push * (p.x - nx);
+                    dy += push * (p.y - ny);
+                }
+            }
+
+            if dx.hypot(dy) < BAND_TOLERANCE && dt.abs() < BAND_TOLERANCE {
+                continue;
+            }
+            let moved = Pose2::new(p.x + dx, p.y + dy, p.theta + dt);
+            if planner.is_valid_path(playground, &prev, &moved)
+                && planner.is_valid_path(playground, &moved, &next)
+            {
+                band[k] = moved;
+                settled = false;
+            }
+        }
+        if settled {
+            break;
+        }
+    }
+    return dedup(band);
+}
+
+// A point part way along a path: the pose, the index of the waypoint starting its edge and the
+// distance to it along the path under the planner's metric.
+struct PathPoint {
+    pose: Pose2,
+    index: usize,
+    distance: f64,
+}
+
+// Two random points along the path, in order, or None if the path is too short to shortcut.
+fn pick_span<R: Rng>(
+    planner: &Planner,
+    path: &[Pose2],
+    rng: &mut R,
+) -> Option<(PathPoint, PathPoint)> {
+    let total = planner.path_cost(path);
+    if path.len() < 3 || total <= 0.0 {
+        return None;
+    }
+    let a = point_at(planner, path, rng.gen_range(0.0..total));
+    let b = point_at(planner, path, rng.gen_range(0.0..total));
+    let (from, to) = match a.distance <= b.distance {
+        true => (a, b),
+        false => (b, a),
+    };
+    return Some((from, to));
+}
+
+fn point_at(planner: &Planner, path: &[Pose2], distance: f64) -> PathPoint {
+    let mut start = 0.0;
+    for (i, w) in path.windows(2).enumerate() {
+        let length = planner.distance(&w[0], &w[1]);
+        if start + length >= distance || i == path.len() - 2 {
+            let s = match length > 0.0 {
+                true => ((distance - start) / length).clamp(0.0, 1.0),
+                false => 0.0,
+            };
+            return PathPoint {
+                pose: w[0].interpolate(&w[1], s),
+                index: i,
+                distance,
+            };
+        }
+        start += length;
+    }
+    unreachable!("paths have at least two waypoints");
+}
+
+// Are the candidate's edges starting at the given indices valid?
+fn all_valid(
+    planner: &Planner,
+    playground: &Playground,
+    path: &[Pose2],
+    edges: std::ops::Range<usize>,
+) -> bool {
+    return edges
+        .filter(|&i| i + 1 < path.len())
+        .all(|i| planner.is_valid_path(playground, &path[i], &path[i + 1]));
+}
+
+// Drops repeated waypoints, which splitting a path at its waypoints leaves behind.
+fn dedup(mut path: Vec<Pose2>) -> Vec<Pose2> {
+    path.dedup_by(|b, a| a.translation(b) < 1e-9 && a.rotation(b) < 1e-9);
+    return path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlannerConfig;
+    use crate::playground::Rect;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // A path that zigzags across an open field, with a wall to steer around at the end.
+    fn zigzag() -> (Playground, Vec<Pose2>) {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (200, 250),
+            size: (200, 20),
+        });
+        let path = vec![
+            Pose2::new(50.0, 50.0, 0.0),
+            Pose2::new(150.0, 60.0, 1.0),
+            Pose2::new(60.0, 150.0, -1.0),
+            Pose2::new(180.0, 200.0, 2.0),
+            Pose2::new(150.0, 300.0, 0.5),
+            Pose2::new(350.0, 350.0, 0.0),
+        ];
+        return (playground, path);
+    }
+
+    fn planner(playground: &Playground, optimisers: Vec<Optimiser>) -> Planner {
+        return Planner::with_config(
+            playground,
+            PlannerConfig {
+                optimisers,
+                optimise_iterations: 50,
+                robot_size: (10, 10),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn assert_valid(planner: &Planner, playground: &Playground, path: &[Pose2]) {
+        for w in path.windows(2) {
+            assert!(planner.is_valid_path(playground, &w[0], &w[1]), "{w:?}");
+        }
+    }
+
+    #[test]
+    fn optimisers_keep_paths_valid() {
+        let (playground, path) = zigzag();
+        let before = planner(&playground, vec![]);
+        assert_valid(&before, &playground, &path);
+        let cost = before.path_cost(&path);
+
+        for optimiser in [
+            Optimiser::Shortcut,
+            Optimiser::PartialShortcut,
+            Optimiser::ElasticBand,
+        ] {
+            let planner = planner(&playground, vec![optimiser]);
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            let optimised = optimise(&planner, &playground, &path, &mut rng);
+            assert_valid(&planner, &playground, &optimised);
+            assert_eq!(optimised.first(), path.first());
+            assert_eq!(optimised.last(), path.last());
+            let after = planner.path_cost(&optimised);
+            assert!(after < cost, "{optimiser:?}: {cost} -> {after}");
+        }
+    }
+
+    #[test]
+    fn plan_reports_cost_before_and_after() {
+        let (playground, _) = zigzag();
+        let mut planner = planner(&playground, vec![Optimiser::Shortcut]);
+        planner.seed = 3;
+        planner.compute_path(&playground);
+        let stats = &planner.stats;
+        assert!(stats.success);
+        assert!(stats.cost <= stats.unoptimised_cost);
+        assert_eq!(stats.cost, planner.path_cost(&planner.compact_path));
+        assert_valid(&planner, &playground, &planner.compact_path);
+    }
+
+    #[test]
+    fn elastic_band_keeps_clear_of_obstacles() {
+        // A straight path that grazes a wall gets pushed away from it, though its ends stay put.
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 20),
+        });
+        let planner = planner(&playground, vec![Optimiser::ElasticBand]);
+        let path = vec![Pose2::new(50.0, 130.0, 0.0), Pose2::new(350.0, 130.0, 0.0)];
+        let band = optimise(
+            &planner,
+            &playground,
+            &path,
+            &mut ChaCha8Rng::seed_from_u64(1),
+        );
+        assert_valid(&planner, &playground, &band);
+        assert_eq!(band.first(), path.first());
+        assert_eq!(band.last(), path.last());
+        let middle = band.iter().find(|p| p.x >= 200.0).unwrap();
+        assert!(
+            playground.clearance(middle.x, middle.y, 100.0) > 15.0,
+            "{middle:?}"
+        );
+    }
+}
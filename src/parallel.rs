@@ -0,0 +1,189 @@
+extern crate rayon;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::sync::{Arc, Mutex};
+
+use crate::config::PlannerConfig;
+use crate::planner::{Monitor, Planner, Progress};
+use crate::playground::Playground;
+use crate::pose::Pose2;
+
+// Runs `f` on a pool of `threads` workers, or on rayon's global pool (one thread per core) if unset.
+pub fn with_threads<T: Send>(threads: Option<usize>, f: impl FnOnce() -> T + Send) -> T {
+    return match threads {
+        None => f(),
+        Some(n) => ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .expect("failed to start planner threads")
+            .install(f),
+    };
+}
+
+// The seeds for each of `runs` searches, derived from one parent seed so that a batch of runs is
+// as reproducible as a single one.
+pub fn run_seeds(seed: u64, runs: usize) -> Vec<u64> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    return (0..runs).map(|_| rng.gen()).collect();
+}
+
+// Plans config.runs independently seeded searches concurrently and returns the planner with the
+// cheapest compacted path under the distance metric. Failed runs only win if every run failed.
+// Ties go to the earlier run, so the result doesn't depend on thread scheduling.
+// Every run reports to the same monitor, so cancelling stops them all.
+pub fn best_of(
+    playground: &Playground,
+    config: &PlannerConfig,
+    seed: u64,
+    monitor: Option<Monitor>,
+) -> Planner {
+    // The runs share whichever pool this is called on rather than each starting its own.
+    let single = PlannerConfig {
+        runs: 1,
+        threads: None,
+        ..config.clone()
+    };
+    // Runs that are still searching report the best path the finished ones have found.
+    let best_cost: Arc<Mutex<Option<f64>>> = Arc::default();
+    let planners: Vec<Planner> = run_seeds(seed, config.runs)
+        .into_par_iter()
+        .map(|seed| {
+            let mut planner = Planner::with_config(playground, single.clone());
+            planner.seed = seed;
+            if let Some(monitor) = &monitor {
+                let monitor = monitor.clone();
+                let best_cost = best_cost.clone();
+                planner.monitor = Some(Arc::new(move |progress: &Progress| {
+                    let progress = Progress {
+                        best_cost: *best_cost.lock().unwrap(),
+                        ..progress.clone()
+                    };
+                    return monitor(&progress);
+                }));
+            }
+            planner.compute_path(playground);
+            if planner.stats.success {
+                let mut best = best_cost.lock().unwrap();
+                let cost = planner.stats.cost;
+                *best = Some(best.map_or(cost, |b| b.min(cost)));
+            }
+            planner.monitor = monitor.clone();
+            planner
+        })
+        .collect();
+
+    let cost = |p: &Planner| match p.stats.success {
+        true => p.stats.cost,
+        false => f64::INFINITY,
+    };
+    return planners
+        .into_iter()
+        .reduce(|best, p| match cost(&p) < cost(&best) {
+            true => p,
+            false => best,
+        })
+        .unwrap();
+}
+
+// Collision checks a batch of poses in parallel. The results are in the same order as the input.
+pub fn check_poses(planner: &Planner, playground: &Playground, poses: &[Pose2]) -> Vec<bool> {
+    return poses
+        .par_iter()
+        .map(|p| planner.is_valid_pose(playground, p))
+        .collect();
+}
+
+// Collision checks a batch of straight-line edges in parallel, in the same order as the input.
+pub fn check_edges(
+    planner: &Planner,
+    playground: &Playground,
+    edges: &[(Pose2, Pose2)],
+) -> Vec<bool> {
+    return edges
+        .par_iter()
+        .map(|(f, t)| planner.is_valid_path(playground, f, t))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::Rect;
+
+    fn playground() -> Playground {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        return playground;
+    }
+
+    #[test]
+    fn best_of() {
+        let playground = playground();
+        let config = PlannerConfig {
+            runs: 6,
+            threads: Some(3),
+            ..Default::default()
+        };
+        let best = with_threads(config.threads, || {
+            super::best_of(&playground, &config, 11, None)
+        });
+        assert!(best.stats.success);
+
+        // The winner costs no more than any individual run, and replaying its seed reproduces it.
+        for seed in run_seeds(11, 6) {
+            let mut planner = Planner::new(&playground);
+            planner.seed = seed;
+            planner.compute_path(&playground);
+            assert!(best.stats.cost <= planner.stats.cost);
+            if seed == best.stats.seed {
+                assert_eq!(planner.full_path, best.full_path);
+            }
+        }
+        assert!(run_seeds(11, 6).contains(&best.stats.seed));
+    }
+
+    #[test]
+    fn compute_path_best_of_is_deterministic() {
+        let playground = playground();
+        let config = PlannerConfig {
+            runs: 4,
+            threads: Some(2),
+            ..Default::default()
+        };
+        let mut a = Planner::with_config(&playground, config.clone());
+        let mut b = Planner::with_config(&playground, config);
+        a.seed = 5;
+        b.seed = 5;
+        a.compute_path(&playground);
+        b.compute_path(&playground);
+        assert_eq!(a.full_path, b.full_path);
+        assert_eq!(a.stats.seed, b.stats.seed);
+    }
+
+    #[test]
+    fn batch_checks_match_sequential() {
+        let playground = playground();
+        let planner = Planner::new(&playground);
+        let poses: Vec<Pose2> = (0..40)
+            .map(|i| Pose2::new(i as f64 * 10.0, 200.0, (i as f64 * 20.0).to_radians()))
+            .collect();
+        let expected: Vec<bool> = poses
+            .iter()
+            .map(|p| planner.is_valid_pose(&playground, p))
+            .collect();
+        assert_eq!(check_poses(&planner, &playground, &poses), expected);
+
+        let edges: Vec<(Pose2, Pose2)> = poses.windows(2).map(|w| (w[0], w[1])).collect();
+        let expected: Vec<bool> = edges
+            .iter()
+            .map(|(f, t)| planner.is_valid_path(&playground, f, t))
+            .collect();
+        assert_eq!(check_edges(&planner, &playground, &edges), expected);
+    }
+}
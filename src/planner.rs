@@ -1,86 +1,397 @@
 extern crate splines;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use splines::{Interpolation, Key, Spline};
-use std::{cmp, collections::HashMap};
+use std::{collections::HashMap, f64::consts::FRAC_PI_2, sync::Arc, time::Instant};
 
+use crate::config::{Algorithm, CompactionMode, DistanceMetric, PlannerConfig};
+use crate::kdtree::KdTree;
+use crate::optimise;
+use crate::parallel;
 use crate::playground::{Playground, Rect};
+use crate::pose::{angle_diff, Pose, Pose2};
+use crate::roadmap;
+use crate::smoothing;
+use crate::stats::{CheckCounter, PlanStats};
+use crate::trajectory::{MotionState, Trajectory};
 
-// pose values are stored as integers to (hopefully) simplify graph search
-// i.e. we basically have a search grid size of 1
-// Otherwise we'd likely need an oct-tree.
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Pose {
-    pub x: i32, // Cartesian coordinates in playground frame
-    pub y: i32,
-    pub t: i32, // degrees
+type PoseSplines = (Spline<f64, f64>, Spline<f64, f64>, Spline<f64, f64>);
+
+// A snapshot of a search in progress.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    pub iterations: usize,
+    pub tree_size: usize,
+    pub closest_to_goal: f64, // Distance from the tree to the goal region, ignoring heading
+    pub best_cost: Option<f64>, // Cost of the best path found so far, if any
 }
 
+impl Progress {
+    // One "name: value" line per field, like PlanStats::lines.
+    pub fn lines(&self) -> Vec<String> {
+        return vec![
+            format!("iterations: {}", self.iterations),
+            format!("tree size: {}", self.tree_size),
+            format!("distance to goal: {:.1}", self.closest_to_goal),
+            match self.best_cost {
+                None => "best cost: none".to_string(),
+                Some(c) => format!("best cost: {c:.1}"),
+            },
+        ];
+    }
+}
+
+// A path found by plan_anytime, shorter than every one found before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub round: usize,
+    pub full_path: Vec<Pose2>,
+    pub compact_path: Vec<Pose2>,
+    pub stats: PlanStats, // Of the round that found it
+}
+
+// Called with progress every few hundred iterations. Returning false cancels the search.
+pub type Monitor = Arc<dyn Fn(&Progress) -> bool + Send + Sync>;
+
+// How many samples the search draws between progress reports.
+const PROGRESS_INTERVAL: usize = 256;
+
 pub struct Planner {
-    pub pose: Pose,
-    pub size: (i32, i32),
-    pub full_path: Vec<Pose>,
-    pub compact_path: Vec<Pose>,
-    splines: Option<(Spline<f64, f64>, Spline<f64, f64>, Spline<f64, f64>)>,
+    pub pose: Pose2,
+    pub config: PlannerConfig,
+    pub full_path: Vec<Pose2>,
+    pub compact_path: Vec<Pose2>,
+    pub smooth_path: Vec<Pose2>, // The compact path after smoothing, which the robot follows
+    pub seed: u64,               // Seeds the sampler, so replaying a seed reproduces the same path
+    pub stats: PlanStats,        // Instrumentation from the last compute_path call
+    pub monitor: Option<Monitor>,
+    splines: Option<PoseSplines>,
+    trajectory: Option<Trajectory>, // When the robot reaches each distance along the splines
+    time: f64,                      // At the last update_pos call
+    travelled: f64,                 // Distance along the splines at the last update_pos call
+    cost_bound: Option<f64>,        // Only look for paths shorter than this
+    checks: CheckCounter,
 }
 
 impl Planner {
     pub fn new(playground: &Playground) -> Self {
-        const SIZE: (i32, i32) = (10, 50);
+        return Self::with_config(playground, PlannerConfig::default());
+    }
+
+    pub fn with_config(playground: &Playground, config: PlannerConfig) -> Self {
         return Self {
-            pose: Pose {
-                x: playground.start.0,
-                y: playground.start.1,
-                t: 0,
-            },
-            size: SIZE,
+            pose: playground.start_pose(),
+            config,
             full_path: vec![],
             compact_path: vec![],
+            smooth_path: vec![],
+            seed: rand::thread_rng().gen(),
+            stats: PlanStats::default(),
+            monitor: None,
             splines: None,
+            trajectory: None,
+            time: 0.0,
+            travelled: 0.0,
+            cost_bound: None,
+            checks: CheckCounter::default(),
         };
     }
 
+    // Plans on the first call and returns false; returns true once a plan is ready to follow.
+    // If the search gives up, both paths are left empty and the robot stays at the start.
     pub fn compute_path(&mut self, playground: &Playground) -> bool {
         if self.splines.is_none() {
-            self.full_path = self.rrt_to_goal(playground);
-            self.compact_path = self.compact_path(playground, &self.full_path);
-            self.splines = Some(Self::build_spline(&self.compact_path));
+            let threads = self.config.threads;
+            parallel::with_threads(threads, || match self.config.runs {
+                1 => self.plan(playground),
+                _ => self.plan_best_of(playground),
+            });
             return false;
         }
         return true;
     }
 
+    // A single search seeded with self.seed.
+    fn plan(&mut self, playground: &Playground) {
+        let mut stats = PlanStats {
+            seed: self.seed,
+            ..Default::default()
+        };
+        let checks_before = self.checks.get();
+
+        let begin = Instant::now();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let path = match self.config.algorithm {
+            Algorithm::Rrt => self.rrt_search(playground, &mut rng, &mut stats),
+            Algorithm::Prm => roadmap::prm_search(self, playground, &mut rng, &mut stats),
+        };
+        stats.success = path.is_some();
+        self.full_path = path.unwrap_or_default();
+        stats.search_time = begin.elapsed();
+
+        let begin = Instant::now();
+        self.compact_path = match self.full_path.is_empty() {
+            true => vec![],
+            false => self.compact_path(playground, &self.full_path),
+        };
+        stats.compaction_time = begin.elapsed();
+
+        let begin = Instant::now();
+        stats.unoptimised_cost = self.path_cost(&self.compact_path);
+        self.compact_path = optimise::optimise(self, playground, &self.compact_path, &mut rng);
+        stats.optimisation_time = begin.elapsed();
+
+        let begin = Instant::now();
+        let start = MotionState::default();
+        match self.compact_path.is_empty() {
+            true => self.drive(&[self.pose], start),
+            false => self.follow(playground, &self.compact_path.clone(), start),
+        };
+        stats.spline_time = begin.elapsed();
+        stats.travel_time = self.trajectory.as_ref().unwrap().duration();
+        stats.infeasible = playground.robot.model().check_path(&self.smooth_path).err();
+
+        let checks = self.checks.get();
+        stats.pose_checks = checks.0 - checks_before.0;
+        stats.path_checks = checks.1 - checks_before.1;
+        stats.full_length = Self::path_length(&self.full_path);
+        stats.compact_length = Self::path_length(&self.compact_path);
+        stats.cost = self.path_cost(&self.compact_path);
+        stats.full_waypoints = self.full_path.len();
+        stats.compact_waypoints = self.compact_path.len();
+        self.stats = stats;
+    }
+
+    // Runs config.runs searches concurrently and keeps the cheapest path. The stats are those of
+    // the winning run, whose seed replays it on its own, except that the search time covers the
+    // whole batch.
+    fn plan_best_of(&mut self, playground: &Playground) {
+        let begin = Instant::now();
+        let best = parallel::best_of(playground, &self.config, self.seed, self.monitor.clone());
+        self.full_path = best.full_path;
+        self.compact_path = best.compact_path;
+        self.smooth_path = best.smooth_path;
+        self.splines = best.splines;
+        self.trajectory = best.trajectory;
+        self.stats = best.stats;
+        self.stats.search_time = begin.elapsed()
+            - self.stats.compaction_time
+            - self.stats.optimisation_time
+            - self.stats.spline_time;
+    }
+
+    // Anytime planning. Runs up to config.anytime_rounds searches, each only looking for paths
+    // shorter than the best so far, and passes every improvement to `on_solution` as soon as it is
+    // found. The first round is seeded with self.seed, so its solution is the one compute_path
+    // would find. Stops early once the path is a straight line, if `on_solution` returns false or
+    // if the monitor cancels the search. The planner is then left holding the best solution, as
+    // if compute_path had found it. Returns true if any round found a path.
+    pub fn plan_anytime(
+        &mut self,
+        playground: &Playground,
+        mut on_solution: impl FnMut(&Solution) -> bool,
+    ) -> bool {
+        let straight = playground.goal_distance(&playground.start_pose());
+        let mut seeds = vec![self.seed];
+        seeds.extend(parallel::run_seeds(
+            self.seed,
+            self.config.anytime_rounds - 1,
+        ));
+
+        let mut best: Option<f64> = None;
+        for (round, seed) in seeds.into_iter().enumerate() {
+            let mut planner = Planner::with_config(playground, self.config.clone());
+            planner.seed = seed;
+            planner.cost_bound = best;
+            if let Some(monitor) = self.monitor.clone() {
+                planner.monitor = Some(Arc::new(move |p: &Progress| {
+                    return monitor(&Progress {
+                        best_cost: best,
+                        ..p.clone()
+                    });
+                }));
+            }
+            planner.compute_path(playground);
+
+            let cancelled = planner.stats.cancelled;
+            let cost = planner.stats.cost;
+            if planner.stats.success && best.is_none_or(|b| cost < b) {
+                best = Some(cost);
+                let solution = Solution {
+                    round,
+                    full_path: planner.full_path,
+                    compact_path: planner.compact_path,
+                    stats: planner.stats,
+                };
+                self.full_path = solution.full_path.clone();
+                self.compact_path = solution.compact_path.clone();
+                self.smooth_path = planner.smooth_path;
+                self.splines = planner.splines;
+                self.trajectory = planner.trajectory;
+                self.stats = solution.stats.clone();
+                if !on_solution(&solution) || cost <= straight + f64::EPSILON {
+                    break;
+                }
+            } else if best.is_none() {
+                self.full_path = planner.full_path;
+                self.compact_path = planner.compact_path;
+                self.smooth_path = planner.smooth_path;
+                self.splines = planner.splines;
+                self.trajectory = planner.trajectory;
+                self.stats = planner.stats;
+            }
+            if cancelled {
+                self.stats.cancelled = true;
+                break;
+            }
+        }
+        return best.is_some();
+    }
+
+    // Moves the robot onto a solution's path from wherever it is now, if that gets it to the goal
+    // sooner than carrying on along its current path. The robot heads straight for whichever
+    // waypoint leaves it the shortest way to go, provided it can reach that waypoint without a
+    // collision, so it never jumps. Returns true if the robot switched.
+    pub fn switch_path(&mut self, playground: &Playground, solution: &Solution) -> bool {
+        let remaining = match (&self.splines, self.compact_path.is_empty()) {
+            (Some((sx, _, _)), false) => (sx.keys().last().unwrap().t - self.travelled).max(0.0),
+            _ => f64::INFINITY,
+        };
+
+        // Walk back from the goal, tracking the cost of the rest of the path.
+        let path = &solution.compact_path;
+        let mut to_goal = 0.0;
+        let mut join: Option<(usize, f64)> = None;
+        for j in (0..path.len()).rev() {
+            if j + 1 < path.len() {
+                to_goal += self.distance(&path[j], &path[j + 1]);
+            }
+            let cost = self.distance(&self.pose, &path[j]) + to_goal;
+            if join.is_none_or(|(_, c)| cost <= c)
+                && self.is_valid_path(playground, &self.pose, &path[j])
+            {
+                join = Some((j, cost));
+            }
+        }
+        let j = match join {
+            Some((j, cost)) if cost < remaining => j,
+            _ => return false,
+        };
+
+        let mut waypoints = vec![self.pose];
+        waypoints.extend(path[j..].iter().skip_while(|p| **p == self.pose));
+        self.follow(playground, &waypoints, self.motion_state());
+        self.full_path = solution.full_path.clone();
+        self.compact_path = waypoints;
+        self.stats = solution.stats.clone();
+        return true;
+    }
+
+    // Has compute_path run since the planner was created or last reset?
+    pub fn has_plan(&self) -> bool {
+        return self.splines.is_some();
+    }
+
+    // Forgets the computed path and returns the robot to the start. The next compute_path call
+    // replans with the same seed, so it reproduces the previous run exactly.
+    pub fn reset(&mut self, playground: &Playground) {
+        self.pose = playground.start_pose();
+        self.full_path.clear();
+        self.compact_path.clear();
+        self.smooth_path.clear();
+        self.splines = None;
+        self.trajectory = None;
+        self.time = 0.0;
+        self.travelled = 0.0;
+    }
+
+    // Moves the robot to where its trajectory has it at time `t`, in seconds since planning.
     pub fn update_pos(&mut self, t: f64) {
-        const PIXELS_PER_SEC: f64 = 25.0;
-        let t = t * PIXELS_PER_SEC;
-        match &self.splines {
-            None => (),
-            Some((sx, sy, st)) => {
-                self.pose = Pose {
-                    x: sx.clamped_sample(t).unwrap() as i32,
-                    y: sy.clamped_sample(t).unwrap() as i32,
-                    t: st.clamped_sample(t).unwrap() as i32,
+        self.time = t;
+        if let (Some((sx, sy, st)), Some(trajectory)) = (&self.splines, &self.trajectory) {
+            let d = trajectory.sample(t).distance;
+            self.travelled = d;
+            self.pose = Pose2::new(
+                sx.clamped_sample(d).unwrap(),
+                sy.clamped_sample(d).unwrap(),
+                st.clamped_sample(d).unwrap(),
+            );
+        }
+    }
+
+    // The timed motion along the path the robot is following, if it has one.
+    pub fn trajectory(&self) -> Option<&Trajectory> {
+        return self.trajectory.as_ref();
+    }
+
+    // Re-times the rest of the path from where the robot is now, at its current speed, after the
+    // speed limits or profile in the config have changed.
+    pub fn retime(&mut self) {
+        let keys = match &self.splines {
+            Some((sx, _, _)) if !self.smooth_path.is_empty() => sx.keys(),
+            _ => return,
+        };
+        let mut path = vec![self.pose];
+        path.extend(
+            self.smooth_path
+                .iter()
+                .zip(keys)
+                .filter(|(_, k)| k.t > self.travelled)
+                .map(|(p, _)| *p),
+        );
+        let start = self.motion_state();
+        self.smooth_path = path.clone();
+        self.drive(&path, start);
+    }
+
+    // Where the robot is along its trajectory, for starting a new one without a jolt.
+    fn motion_state(&self) -> MotionState {
+        return match &self.trajectory {
+            None => MotionState::default(),
+            Some(trajectory) => {
+                let point = trajectory.sample(self.time);
+                MotionState {
+                    time: self.time.max(point.time),
+                    distance: point.distance,
+                    speed: point.speed,
                 }
             }
         };
     }
 
-    fn build_spline(path: &Vec<Pose>) -> (Spline<f64, f64>, Spline<f64, f64>, Spline<f64, f64>) {
-        let mut last: Option<Pose> = None;
-        let mut dist = 0.0;
+    // Smooths `path` with config.smoothing and sets the robot following it from `start`.
+    fn follow(&mut self, playground: &Playground, path: &[Pose2], start: MotionState) {
+        self.smooth_path = smoothing::smooth(self, playground, path);
+        self.drive(&self.smooth_path.clone(), start);
+    }
+
+    // Sets the robot following `path` as it is, timed with config.profile.
+    fn drive(&mut self, path: &[Pose2], start: MotionState) {
+        self.splines = Some(self.build_spline(path, start.distance));
+        self.trajectory = Some(Trajectory::new(self, path, start));
+    }
+
+    // Splines through the path, parameterised by the distance metric starting from `start`, so
+    // turning takes time as well as driving. The heading is unwrapped so that interpolating
+    // between keys turns the short way round.
+    fn build_spline(&self, path: &[Pose2], start: f64) -> PoseSplines {
+        let mut last: Option<Pose2> = None;
+        let mut dist = start;
+        let mut heading = 0.0;
         let mut keys = (Vec::new(), Vec::new(), Vec::new());
         for pose in path {
             match last {
-                None => (),
-                Some(prev) => dist += Self::euclid_dist(&prev, pose),
+                None => heading = pose.theta,
+                Some(prev) => {
+                    dist += self.distance(&prev, pose);
+                    heading += angle_diff(prev.theta, pose.theta);
+                }
             }
-            last = Some(pose.clone());
-            keys.0
-                .push(Key::new(dist, pose.x as f64, Interpolation::Linear));
-            keys.1
-                .push(Key::new(dist, pose.y as f64, Interpolation::Linear));
-            keys.2
-                .push(Key::new(dist, pose.t as f64, Interpolation::Linear));
+            last = Some(*pose);
+            keys.0.push(Key::new(dist, pose.x, Interpolation::Linear));
+            keys.1.push(Key::new(dist, pose.y, Interpolation::Linear));
+            keys.2.push(Key::new(dist, heading, Interpolation::Linear));
         }
         return (
             Spline::from_vec(keys.0),
@@ -89,129 +400,279 @@ impl Planner {
         );
     }
 
-    fn compact_path(&self, playground: &Playground, path: &Vec<Pose>) -> Vec<Pose> {
-        let mut acc: Vec<Pose> = Vec::new();
+    pub fn compact_path(&self, playground: &Playground, path: &[Pose2]) -> Vec<Pose2> {
+        if self.config.compaction == CompactionMode::None {
+            return path.to_vec();
+        }
+        let mut acc: Vec<Pose2> = Vec::new();
         acc.push(path[0]);
         for i in 2..path.len() {
             if !self.is_valid_path(playground, acc.last().unwrap(), &path[i]) {
                 acc.push(path[i - 1]);
             }
         }
-        acc.push(path.last().unwrap().clone());
+        acc.push(*path.last().unwrap());
         return acc;
     }
 
-    // Rapid Random Tree pathfinder
-    fn rrt_to_goal(&self, playground: &Playground) -> Vec<Pose> {
-        const GRID_SIZE: i32 = 10;
-        const GOAL_SELECT: f64 = 0.01;
-        let mut rng = rand::thread_rng();
+    // Rapid Random Tree pathfinder. Returns None if no path is found within max_iterations.
+    pub fn rrt_to_goal<R: Rng>(&self, playground: &Playground, rng: &mut R) -> Option<Vec<Pose2>> {
+        return self.rrt_search(playground, rng, &mut PlanStats::default());
+    }
+
+    // Nodes are keyed by their nearest whole pixel and degree, so a sample that lands on an
+    // existing node is rejected rather than added twice. The search ends at the first node added
+    // in the goal region, and goal-biased samples are drawn from the region.
+    fn rrt_search<R: Rng>(
+        &self,
+        playground: &Playground,
+        rng: &mut R,
+        stats: &mut PlanStats,
+    ) -> Option<Vec<Pose2>> {
+        let start = playground.start_pose();
 
-        let start = Pose {
-            x: playground.start.0,
-            y: playground.start.1,
-            t: 0,
-        };
-        let goal = Pose {
-            x: playground.goal.0,
-            y: playground.goal.1,
-            t: 0,
-        };
+        let mut visited_to_parent: HashMap<Pose, Pose2> = HashMap::new();
+        visited_to_parent.insert(start.key(), start);
+        let mut index = KdTree::new();
+        index.insert(self.se2_point(&start), start);
+        let mut closest_to_goal = playground.goal_distance(&start);
+        let end = loop {
+            stats.tree_size = visited_to_parent.len();
+            if stats.iterations.is_multiple_of(PROGRESS_INTERVAL) {
+                let progress = Progress {
+                    iterations: stats.iterations,
+                    tree_size: stats.tree_size,
+                    closest_to_goal,
+                    best_cost: None,
+                };
+                if !self.report(&progress) {
+                    stats.cancelled = true;
+                    return None;
+                }
+            }
+            if self
+                .config
+                .max_iterations
+                .is_some_and(|max| stats.iterations >= max)
+            {
+                return None;
+            }
+            stats.iterations += 1;
 
-        let mut visited_to_parent: HashMap<Pose, Pose> = HashMap::new();
-        visited_to_parent.insert(start, start);
-        loop {
-            let rpose = match rng.gen_bool(GOAL_SELECT) {
-                true => goal,
-                false => Pose {
-                    x: rng.gen_range(0..playground.size.0 / GRID_SIZE) * GRID_SIZE,
-                    y: rng.gen_range(0..playground.size.1 / GRID_SIZE) * GRID_SIZE,
-                    // Only rotate around half the circle to avoid interpolation issues.
-                    t: rng.gen_range(0..180 / GRID_SIZE) * GRID_SIZE,
-                },
+            let rpose = match rng.gen_bool(self.config.goal_bias) {
+                true => playground.sample_goal(rng),
+                false => self.sample_pose(playground, rng),
             };
-            if visited_to_parent.contains_key(&rpose) || !self.is_valid_pose(playground, &rpose) {
+            if visited_to_parent.contains_key(&rpose.key())
+                || self.beyond_bound(playground, &rpose)
+                || !self.is_valid_pose(playground, &rpose)
+            {
+                stats.rejected_samples += 1;
                 continue;
             }
 
-            let mut nearest: Option<Pose> = None;
-            let mut nearest_dist = (playground.size.0 + playground.size.1) as f64;
-            for c in visited_to_parent.keys() {
-                let dist = Self::euclid_dist(&rpose, &c);
-                if dist >= nearest_dist || !self.is_valid_path(playground, &rpose, &c) {
+            // Connect to the closest of the nearest few tree nodes with a collision-free edge. The
+            // k-d tree measures turns along the chord, so re-rank the candidates by the metric.
+            let mut candidates: Vec<Pose2> = index
+                .k_nearest(&self.se2_point(&rpose), self.config.neighbours)
+                .into_iter()
+                .map(|(c, _)| *c)
+                .collect();
+            candidates.sort_by(|a, b| {
+                self.distance(a, &rpose)
+                    .total_cmp(&self.distance(b, &rpose))
+            });
+            let nearest = candidates
+                .into_iter()
+                .find(|c| self.is_valid_path(playground, &rpose, c));
+            let n = match nearest {
+                None => {
+                    stats.unconnected_samples += 1;
                     continue;
                 }
-
-                nearest = Some(c.clone());
-                nearest_dist = dist;
-            }
-
-            match nearest {
-                None => continue,
-                Some(n) => {
-                    visited_to_parent.insert(rpose, n);
-                    if rpose == goal {
-                        break;
+                Some(n) => n,
+            };
+            let edge_length = Self::euclid_dist(&n, &rpose);
+            let rpose = match self.config.step_size {
+                Some(step) if edge_length > step => {
+                    // Only extend part of the way towards the sample. The shorter edge lies within
+                    // the bounding box of the full one, but snapping back onto the grid may not.
+                    let p = Pose2 {
+                        theta: rpose.theta,
+                        ..n.interpolate(&rpose, step / edge_length)
+                    };
+                    let p = p
+                        .to_lattice(self.config.grid_size, self.config.angle_step)
+                        .to_pose2();
+                    if visited_to_parent.contains_key(&p.key())
+                        || !self.is_valid_path(playground, &n, &p)
+                    {
+                        stats.rejected_samples += 1;
+                        continue;
                     }
+                    p
                 }
+                _ => rpose,
+            };
+            visited_to_parent.insert(rpose.key(), n);
+            index.insert(self.se2_point(&rpose), rpose);
+            closest_to_goal = closest_to_goal.min(playground.goal_distance(&rpose));
+            if playground.in_goal(&rpose) {
+                stats.tree_size = visited_to_parent.len();
+                break rpose;
             }
-        }
+        };
 
-        let mut ret = Vec::new();
-        let mut x = goal;
-        while x != visited_to_parent[&x] {
+        let mut ret = vec![end];
+        let mut x = end;
+        while x.key() != start.key() {
+            x = visited_to_parent[&x.key()];
             ret.push(x);
-            x = visited_to_parent[&x];
         }
-        ret.push(x);
         ret.reverse();
-        // print!("{:?}\n", ret);
-        return ret;
+        return Some(ret);
     }
 
-    fn euclid_dist(from: &Pose, to: &Pose) -> f64 {
-        let dx = (from.x - to.x) as f64;
-        let dy = (from.y - to.y) as f64;
-        return (dx.powf(2.0) + dy.powf(2.0)).sqrt();
+    // Total distance travelled along the path, ignoring rotation.
+    pub fn path_length(path: &[Pose2]) -> f64 {
+        return path
+            .windows(2)
+            .fold(0.0, |acc, w| acc + Self::euclid_dist(&w[0], &w[1]));
     }
 
-    fn is_valid_path(&self, playground: &Playground, f: &Pose, t: &Pose) -> bool {
-        let ft = (f.t as f64).to_radians();
-        let tt = (t.t as f64).to_radians();
+    // Distance equivalent of turning one radian under the configured metric. For the swept area,
+    // the corners of the footprint sweep about r² per radian, where r is half its diagonal, and
+    // driving straight ahead sweeps its width per pixel.
+    pub fn turn_weight(&self) -> f64 {
+        let (w, l) = (
+            self.config.robot_size.0 as f64,
+            self.config.robot_size.1 as f64,
+        );
+        return match self.config.metric {
+            DistanceMetric::Euclidean => 0.0,
+            DistanceMetric::Se2 => self.config.heading_weight,
+            DistanceMetric::SweptArea => (w * w + l * l) / 4.0 / w,
+        };
+    }
+
+    // Cost of moving between two poses under the configured metric.
+    pub fn distance(&self, from: &Pose2, to: &Pose2) -> f64 {
+        return from.translation(to) + self.turn_weight() * from.rotation(to);
+    }
 
-        let xs = cmp::max(
-            (((self.size.0 as f64) * ft.cos()).abs() + ((self.size.1 as f64) * ft.sin()).abs())
-                as i32,
-            (((self.size.0 as f64) * tt.cos()).abs() + ((self.size.1 as f64) * tt.sin()).abs())
-                as i32,
+    // Total cost of following the path under the configured metric.
+    pub fn path_cost(&self, path: &[Pose2]) -> f64 {
+        return path
+            .windows(2)
+            .fold(0.0, |acc, w| acc + self.distance(&w[0], &w[1]));
+    }
+
+    // Passes progress to the monitor, if there is one. Returns false if the search should stop.
+    pub(crate) fn report(&self, progress: &Progress) -> bool {
+        return match &self.monitor {
+            None => true,
+            Some(monitor) => monitor(progress),
+        };
+    }
+
+    // Is every path from the start to the goal region through this pose at least as long as the
+    // cost bound? Rotation only adds to the cost, so the straight-line distance is a lower bound
+    // under either metric.
+    pub(crate) fn beyond_bound(&self, playground: &Playground, pose: &Pose2) -> bool {
+        return self.cost_bound.is_some_and(|bound| {
+            Self::euclid_dist(&playground.start_pose(), pose) + playground.goal_distance(pose)
+                >= bound
+        });
+    }
+
+    // A uniformly random pose on the sampling lattice.
+    pub(crate) fn sample_pose<R: Rng>(&self, playground: &Playground, rng: &mut R) -> Pose2 {
+        let grid_size = self.config.grid_size;
+        let angle_step = self.config.angle_step;
+        let x = rng.gen_range(0..playground.size.0 / grid_size) * grid_size;
+        let y = rng.gen_range(0..playground.size.1 / grid_size) * grid_size;
+        let t = rng.gen_range(0..(360 + angle_step - 1) / angle_step) * angle_step;
+        return Pose2::new(x as f64, y as f64, (t as f64).to_radians());
+    }
+
+    // Embeds a pose in 4D so that Euclidean distance there approximates the configured metric. The
+    // heading becomes a point on a circle whose radius is the turn weight, so turning by a small
+    // angle costs about turn_weight * angle and headings wrap around correctly.
+    pub(crate) fn se2_point(&self, pose: &Pose2) -> [f64; 4] {
+        let w = self.turn_weight();
+        return [pose.x, pose.y, w * pose.theta.cos(), w * pose.theta.sin()];
+    }
+
+    pub(crate) fn euclid_dist(from: &Pose2, to: &Pose2) -> f64 {
+        return from.translation(to);
+    }
+
+    // Half the width and height of the robot's axis-aligned bounding box at this heading.
+    pub(crate) fn half_extents(&self, theta: f64) -> (f64, f64) {
+        let (w, l) = (
+            self.config.robot_size.0 as f64,
+            self.config.robot_size.1 as f64,
         );
-        let ys = cmp::max(
-            (((self.size.0 as f64) * ft.sin()).abs() + ((self.size.1 as f64) * ft.cos()).abs())
-                as i32,
-            (((self.size.0 as f64) * tt.sin()).abs() + ((self.size.1 as f64) * tt.cos()).abs())
-                as i32,
+        let (sin, cos) = (theta.sin().abs(), theta.cos().abs());
+        return ((w * cos + l * sin) / 2.0, (w * sin + l * cos) / 2.0);
+    }
+
+    // The largest half extents the robot's bounding box reaches while turning from heading `from`
+    // to `to` the short way round. Between the ends, each extent peaks where the heading is
+    // ±atan2(length, width) from a multiple of π/2, so those headings are checked too.
+    pub(crate) fn swept_half_extents(&self, from: f64, to: f64) -> (f64, f64) {
+        let (w, l) = (
+            self.config.robot_size.0 as f64,
+            self.config.robot_size.1 as f64,
         );
-        let rect = Rect {
-            anchor: (cmp::min(f.x, t.x) - xs / 2, cmp::min(f.y, t.y) - ys / 2),
-            size: ((f.x - t.x).abs() + xs, (f.y - t.y).abs() + ys),
+        let end = from + angle_diff(from, to);
+        let (lo, hi) = (from.min(end), from.max(end));
+        let mut headings = vec![from, end];
+        let peak = l.atan2(w);
+        for offset in [peak, -peak] {
+            let mut k = ((lo - offset) / FRAC_PI_2).ceil();
+            while offset + k * FRAC_PI_2 <= hi {
+                headings.push(offset + k * FRAC_PI_2);
+                k += 1.0;
+            }
+        }
+        return headings
+            .iter()
+            .map(|&theta| self.half_extents(theta))
+            .fold((0.0, 0.0), |(x, y), (hx, hy)| (x.max(hx), y.max(hy)));
+    }
+
+    // The smallest whole-pixel rectangle containing the box from (x0, y0) to (x1, y1).
+    fn bounding_rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Rect {
+        let anchor = (x0.floor() as i32, y0.floor() as i32);
+        return Rect {
+            anchor,
+            size: (x1.ceil() as i32 - anchor.0, y1.ceil() as i32 - anchor.1),
         };
+    }
+
+    pub fn is_valid_path(&self, playground: &Playground, f: &Pose2, t: &Pose2) -> bool {
+        self.checks.count_path();
+        let (xs, ys) = self.swept_half_extents(f.theta, t.theta);
+        let rect = Self::bounding_rect(
+            f.x.min(t.x) - xs,
+            f.y.min(t.y) - ys,
+            f.x.max(t.x) + xs,
+            f.y.max(t.y) + ys,
+        );
         return !playground.is_collision(&rect);
     }
 
-    fn is_valid_pose(&self, playground: &Playground, pose: &Pose) -> bool {
-        let mut hitboxes: Vec<Rect> = Vec::new();
+    // The box the robot covers at a pose, as is_valid_pose checks it.
+    pub fn footprint(&self, pose: &Pose2) -> Rect {
+        let (xs, ys) = self.half_extents(pose.theta);
+        return Self::bounding_rect(pose.x - xs, pose.y - ys, pose.x + xs, pose.y + ys);
+    }
 
+    pub fn is_valid_pose(&self, playground: &Playground, pose: &Pose2) -> bool {
+        self.checks.count_pose();
         // TODO: Generate a set of hitboxes conforming to the shape rather than a giant rectangle.
-        // TODO: See if we can make the giant rectangle more concisely with a rotation matrix.
-        let t = (pose.t as f64).to_radians();
-        let xs = (((self.size.0 as f64) * t.cos()).abs() + ((self.size.1 as f64) * t.sin()).abs())
-            as i32;
-        let ys = (((self.size.0 as f64) * t.sin()).abs() + ((self.size.1 as f64) * t.cos()).abs())
-            as i32;
-        hitboxes.push(Rect {
-            anchor: (pose.x - xs / 2, pose.y - ys / 2),
-            size: (xs, ys),
-        });
+        let hitboxes: Vec<Rect> = vec![self.footprint(pose)];
 
         for hitbox in hitboxes {
             if playground.is_collision(&hitbox) {
@@ -225,36 +686,119 @@ impl Planner {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{Profile, Smoothing};
+    use crate::playground::GoalRegion;
+    use std::f64::consts::{FRAC_PI_2, PI};
 
     #[test]
     fn build_spline() {
         let path = vec![
-            Pose { x: 0, y: 0, t: 0 },
-            Pose {
-                x: 100,
-                y: 0,
-                t: 128,
-            },
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(100.0, 0.0, 128f64.to_radians()),
         ];
-        let splines = Planner::build_spline(&path);
+        let playground = Playground::new((400, 400), (0, 0), (100, 0));
+        let actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                metric: DistanceMetric::Euclidean,
+                ..Default::default()
+            },
+        );
+        let splines = actor.build_spline(&path, 0.0);
         assert_eq!(splines.0.clamped_sample(0.0), Some(0.0));
         assert_eq!(splines.1.clamped_sample(0.0), Some(0.0));
         assert_eq!(splines.2.clamped_sample(0.0), Some(0.0));
 
         assert_eq!(splines.0.clamped_sample(50.0), Some(50.0));
         assert_eq!(splines.1.clamped_sample(50.0), Some(0.0));
-        assert_eq!(splines.2.clamped_sample(50.0), Some(64.0));
+        assert_eq!(splines.2.clamped_sample(50.0), Some(64f64.to_radians()));
 
         assert_eq!(splines.0.clamped_sample(100.0), Some(100.0));
         assert_eq!(splines.1.clamped_sample(100.0), Some(0.0));
-        assert_eq!(splines.2.clamped_sample(100.0), Some(128.0));
+        assert_eq!(splines.2.clamped_sample(100.0), Some(128f64.to_radians()));
+    }
+
+    #[test]
+    fn turning_takes_time() {
+        // Turning on the spot costs heading_weight per radian, so it is not instantaneous.
+        let playground = Playground::new((400, 400), (0, 0), (100, 0));
+        let mut actor = Planner::new(&playground);
+        let quarter = actor.config.heading_weight * std::f64::consts::FRAC_PI_2;
+        actor.drive(
+            &[
+                Pose2::new(0.0, 0.0, 0.0),
+                Pose2::new(0.0, 0.0, 90f64.to_radians()),
+            ],
+            MotionState::default(),
+        );
+        actor.update_pos(quarter / 2.0 / actor.config.speed);
+        assert_eq!(actor.pose.key().t, 45);
+        actor.update_pos(quarter / actor.config.speed);
+        assert_eq!(actor.pose.key().t, 90);
+    }
+
+    #[test]
+    fn update_pos_turns_the_short_way() {
+        let playground = Playground::new((400, 400), (0, 0), (100, 0));
+        let mut actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                metric: DistanceMetric::Euclidean,
+                ..Default::default()
+            },
+        );
+        actor.drive(
+            &[
+                Pose2::new(0.0, 0.0, 170f64.to_radians()),
+                Pose2::new(100.0, 0.0, -170f64.to_radians()),
+            ],
+            MotionState::default(),
+        );
+        actor.update_pos(50.0 / actor.config.speed);
+        assert_eq!(actor.pose.x, 50.0);
+        assert!((actor.pose.theta.abs() - std::f64::consts::PI).abs() < 1e-9);
+        actor.update_pos(75.0 / actor.config.speed);
+        assert_eq!(actor.pose.key().t, -175);
+    }
+
+    #[test]
+    fn retime() {
+        let playground = Playground::new((400, 400), (50, 50), (350, 350));
+        let mut actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                profile: Profile::Trapezoidal,
+                ..Default::default()
+            },
+        );
+        actor.seed = 1;
+        actor.compute_path(&playground);
+        assert_eq!(
+            actor.stats.travel_time,
+            actor.trajectory().unwrap().duration()
+        );
+        actor.update_pos(5.0);
+        let (pose, speed) = (actor.pose, actor.trajectory().unwrap().sample(5.0).speed);
+        assert!(speed > 0.0);
+
+        // Slowing down carries on from the same place and speed, and still reaches the goal.
+        actor.config.speed = 10.0;
+        actor.retime();
+        let first = actor.trajectory().unwrap().points[0];
+        assert_eq!((first.time, first.speed), (5.0, speed));
+        actor.update_pos(5.0);
+        assert!(actor.pose.translation(&pose) < 1e-9);
+        actor.update_pos(1e6);
+        assert_eq!(actor.pose.key(), playground.goal_pose().key());
     }
 
     #[test]
     fn compact_path() {
         let playground = Playground::new((800, 800), (50, 50), (750, 750));
         let actor = Planner::new(&playground);
-        let path = actor.rrt_to_goal(&playground);
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1))
+            .unwrap();
         let path = actor.compact_path(&playground, &path);
         assert_eq!(path.len(), 2);
     }
@@ -263,10 +807,285 @@ mod tests {
     fn rrt_to_goal() {
         let playground = Playground::new((800, 800), (50, 50), (750, 750));
         let actor = Planner::new(&playground);
-        let path = actor.rrt_to_goal(&playground);
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1))
+            .unwrap();
         assert_ne!(path.len(), 0);
     }
 
+    #[test]
+    fn rrt_to_goal_seeded() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let actor = Planner::new(&playground);
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(42))
+            .unwrap();
+        let keys: Vec<Pose> = path.iter().map(|p| p.key()).collect();
+        let expected = [
+            (50, 50, 0),
+            (50, 90, 10),
+            (70, 180, -160),
+            (40, 240, 180),
+            (50, 340, 40),
+            (370, 360, 130),
+            (360, 350, -100),
+            (350, 350, 0),
+        ];
+        assert_eq!(keys, expected.map(|(x, y, t)| Pose { x, y, t }).to_vec());
+    }
+
+    #[test]
+    fn compute_path_replays_seed() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let mut actor = Planner::new(&playground);
+        actor.seed = 7;
+        actor.compute_path(&playground);
+        let first = actor.full_path.clone();
+
+        actor.reset(&playground);
+        assert_eq!(actor.pose, Pose2::new(50.0, 50.0, 0.0));
+        actor.compute_path(&playground);
+        assert_eq!(actor.full_path, first);
+    }
+
+    #[test]
+    fn compute_path_stats() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let mut actor = Planner::new(&playground);
+        actor.seed = 42;
+        actor.compute_path(&playground);
+
+        let stats = &actor.stats;
+        assert_eq!(stats.seed, 42);
+        assert!(stats.success);
+        assert_eq!(
+            stats.iterations,
+            stats.rejected_samples + stats.unconnected_samples + stats.tree_size - 1
+        );
+        assert!(stats.pose_checks > 0 && stats.path_checks > 0);
+        assert_eq!(stats.full_waypoints, 8);
+        assert!(stats.compact_length <= stats.full_length);
+    }
+
+    #[test]
+    fn compute_path_smooths() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let mut actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                smoothing: Smoothing::CatmullRom,
+                ..Default::default()
+            },
+        );
+        actor.seed = 42;
+        actor.compute_path(&playground);
+        assert!(actor.smooth_path.len() > actor.compact_path.len());
+        for w in actor.smooth_path.windows(2) {
+            assert!(actor.is_valid_path(&playground, &w[0], &w[1]));
+        }
+
+        // The robot follows the smoothed path all the way to the goal.
+        actor.update_pos(1e6);
+        assert_eq!(actor.pose.key(), actor.compact_path.last().unwrap().key());
+    }
+
+    #[test]
+    fn plan_anytime() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let config = PlannerConfig {
+            max_iterations: Some(20_000),
+            anytime_rounds: 4,
+            ..Default::default()
+        };
+        let mut once = Planner::with_config(&playground, config.clone());
+        once.seed = 42;
+        once.compute_path(&playground);
+
+        let mut actor = Planner::with_config(&playground, config);
+        actor.seed = 42;
+        let mut solutions = Vec::new();
+        assert!(actor.plan_anytime(&playground, |s| {
+            solutions.push(s.clone());
+            return true;
+        }));
+
+        // The first round is an ordinary plan, and every later solution is shorter.
+        assert_eq!(solutions[0].round, 0);
+        assert_eq!(solutions[0].compact_path, once.compact_path);
+        for w in solutions.windows(2) {
+            assert!(w[0].round < w[1].round);
+            assert!(w[1].stats.compact_length < w[0].stats.compact_length);
+        }
+        let last = solutions.last().unwrap();
+        assert_eq!(actor.compact_path, last.compact_path);
+        assert_eq!(actor.stats, last.stats);
+        assert!(actor.has_plan());
+    }
+
+    #[test]
+    fn plan_anytime_stops_when_asked() {
+        let playground = Playground::new((400, 400), (50, 50), (350, 350));
+        let mut actor = Planner::new(&playground);
+        actor.seed = 1;
+        let mut calls = 0;
+        actor.plan_anytime(&playground, |_| {
+            calls += 1;
+            return false;
+        });
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn switch_path() {
+        // The robot is a third of the way along a wide detour around the obstacle when a
+        // shorter path turns up.
+        let mut playground = Playground::new((400, 400), (50, 200), (350, 200));
+        playground.add_obstacles(Rect {
+            anchor: (190, 190),
+            size: (20, 20),
+        });
+        let mut actor = Planner::new(&playground);
+        let detour = Solution {
+            round: 0,
+            full_path: vec![],
+            compact_path: vec![
+                Pose2::new(50.0, 200.0, 0.0),
+                Pose2::new(50.0, 50.0, 0.0),
+                Pose2::new(350.0, 50.0, 0.0),
+                Pose2::new(350.0, 200.0, 0.0),
+            ],
+            stats: PlanStats::default(),
+        };
+        assert!(actor.switch_path(&playground, &detour));
+        assert_eq!(actor.compact_path, detour.compact_path);
+        actor.update_pos(200.0 / actor.config.speed);
+        assert_eq!(actor.pose, Pose2::new(100.0, 50.0, 0.0));
+
+        // Joining the new path partway along is shorter than going back to its start.
+        let shorter = Solution {
+            compact_path: vec![
+                Pose2::new(50.0, 200.0, 0.0),
+                Pose2::new(50.0, 100.0, 0.0),
+                Pose2::new(350.0, 100.0, 0.0),
+                Pose2::new(350.0, 200.0, 0.0),
+            ],
+            ..detour.clone()
+        };
+        assert!(actor.switch_path(&playground, &shorter));
+        assert_eq!(
+            actor.compact_path,
+            vec![
+                Pose2::new(100.0, 50.0, 0.0),
+                Pose2::new(350.0, 100.0, 0.0),
+                Pose2::new(350.0, 200.0, 0.0),
+            ]
+        );
+        // The robot carries on from where it was.
+        actor.update_pos(200.0 / actor.config.speed);
+        assert_eq!(actor.pose, Pose2::new(100.0, 50.0, 0.0));
+
+        // Going back to the detour would now be longer.
+        assert!(!actor.switch_path(&playground, &detour));
+    }
+
+    #[test]
+    fn monitor_cancels() {
+        // The goal is walled in, so only the monitor can stop the search.
+        let mut playground = Playground::new((400, 400), (50, 50), (300, 300));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (200, 200),
+        });
+        let mut actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                max_iterations: None,
+                ..Default::default()
+            },
+        );
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        actor.monitor = Some(Arc::new(move |p: &Progress| {
+            seen.lock().unwrap().push(p.clone());
+            return p.iterations < 1000;
+        }));
+        actor.seed = 1;
+        actor.compute_path(&playground);
+
+        assert!(actor.stats.cancelled);
+        assert!(!actor.stats.success);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.first().unwrap().iterations, 0);
+        assert!(reports.last().unwrap().iterations >= 1000);
+        assert!(reports.windows(2).all(|w| w[0].tree_size <= w[1].tree_size));
+    }
+
+    #[test]
+    fn rrt_to_goal_gives_up() {
+        // The goal is walled in, so the search can never reach it.
+        let mut playground = Playground::new((400, 400), (50, 50), (300, 300));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (200, 200),
+        });
+        let mut actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                max_iterations: Some(1000),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            actor.rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1)),
+            None
+        );
+        actor.seed = 1;
+
+        assert!(!actor.compute_path(&playground));
+        assert!(actor.full_path.is_empty());
+        actor.update_pos(10.0);
+        assert_eq!(actor.pose, Pose2::new(50.0, 50.0, 0.0));
+    }
+
+    #[test]
+    fn rrt_to_goal_step_size() {
+        let playground = Playground::new((800, 800), (50, 50), (750, 750));
+        let actor = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                step_size: Some(100.0),
+                ..Default::default()
+            },
+        );
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1))
+            .unwrap();
+        for w in path.windows(2) {
+            // Snapping to the grid can stretch an edge by up to half a grid cell on each axis.
+            assert!(Planner::euclid_dist(&w[0], &w[1]) <= 100.0 + 10.0);
+        }
+    }
+
     #[test]
     fn rrt_to_goal_obstacle() {
         let mut playground = Playground::new((800, 800), (50, 50), (750, 750));
@@ -275,47 +1094,96 @@ mod tests {
             size: (600, 600),
         });
         let actor = Planner::new(&playground);
-        let path = actor.rrt_to_goal(&playground);
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1))
+            .unwrap();
         assert_ne!(path.len(), 0);
     }
 
     #[test]
-    fn euclid_dist() {
-        let target = Pose {
-            x: 750,
-            y: 50,
-            t: 0,
+    fn rrt_to_goal_region() {
+        // The goal itself is inside an obstacle, but anywhere near it facing any way will do.
+        let mut playground = Playground::new((800, 800), (50, 50), (750, 750));
+        playground.add_obstacles(Rect {
+            anchor: (700, 700),
+            size: (100, 100),
+        });
+        playground.goal_region = GoalRegion {
+            radius: 150.0,
+            heading_tolerance: PI,
+            ..Default::default()
         };
-        assert!(
-            Planner::euclid_dist(
-                &target,
-                &Pose {
-                    x: 751,
-                    y: 51,
-                    t: 0
-                }
-            ) < 10.0
-        );
-        assert!(
-            Planner::euclid_dist(
-                &target,
-                &Pose {
-                    x: 751,
-                    y: 25,
-                    t: 0
-                }
-            ) > 10.0
-        );
-        assert!(
-            Planner::euclid_dist(
-                &target,
-                &Pose {
-                    x: 751,
-                    y: 51,
-                    t: 180
-                }
-            ) < 10.0
-        );
+        let actor = Planner::new(&playground);
+        let path = actor
+            .rrt_to_goal(&playground, &mut ChaCha8Rng::seed_from_u64(1))
+            .unwrap();
+        let end = path.last().unwrap();
+        assert!(playground.in_goal(end));
+        assert!(actor.is_valid_pose(&playground, end));
+
+        // Docking: right on the goal, facing down the y axis give or take a few degrees, which
+        // is off the sampling lattice.
+        let mut playground = Playground::new((800, 800), (50, 50), (400, 400));
+        playground.goal_heading = FRAC_PI_2;
+        playground.goal_region.heading_tolerance = 0.05;
+        let mut actor = Planner::new(&playground);
+        actor.seed = 1;
+        actor.compute_path(&playground);
+        let end = actor.full_path.last().unwrap();
+        assert_eq!((end.x, end.y), (400.0, 400.0));
+        assert!(end.rotation(&playground.goal_pose()) <= 0.05);
+        assert_eq!(actor.smooth_path.last(), Some(end));
+    }
+
+    #[test]
+    fn path_length() {
+        let path = vec![
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(30.0, 40.0, 90f64.to_radians()),
+            Pose2::new(30.0, 100.0, 0.0),
+        ];
+        assert_eq!(Planner::path_length(&path), 110.0);
+        assert_eq!(Planner::path_length(&path[..1]), 0.0);
+    }
+
+    #[test]
+    fn path_cost() {
+        let playground = Playground::new((400, 400), (0, 0), (100, 0));
+        let path = vec![
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(30.0, 40.0, 90f64.to_radians()),
+            Pose2::new(30.0, 100.0, -90f64.to_radians()),
+        ];
+        let mut actor = Planner::new(&playground);
+        actor.config.metric = DistanceMetric::Euclidean;
+        assert_eq!(actor.path_cost(&path), 110.0);
+
+        actor.config.metric = DistanceMetric::Se2;
+        actor.config.heading_weight = 10.0;
+        let expected = 110.0 + 10.0 * (std::f64::consts::FRAC_PI_2 + std::f64::consts::PI);
+        assert!((actor.path_cost(&path) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn distance() {
+        let playground = Playground::new((400, 400), (0, 0), (100, 0));
+        let target = Pose2::new(750.0, 50.0, 0.0);
+        let turned = Pose2::new(751.0, 51.0, 180f64.to_radians());
+        assert!(Planner::euclid_dist(&target, &Pose2::new(751.0, 25.0, 0.0)) > 10.0);
+        assert!(Planner::euclid_dist(&target, &turned) < 10.0);
+
+        // Turning round costs heading_weight * π on top of the distance travelled...
+        let mut actor = Planner::new(&playground);
+        let expected = 2f64.sqrt() + actor.config.heading_weight * std::f64::consts::PI;
+        assert!((actor.distance(&target, &turned) - expected).abs() < 1e-9);
+
+        // ...or nothing if the metric ignores rotation.
+        actor.config.metric = DistanceMetric::Euclidean;
+        assert_eq!(actor.distance(&target, &turned), 2f64.sqrt());
+
+        // A 10x50 footprint sweeps (10² + 50²) / 4 per radian, the same as driving 65 pixels.
+        actor.config.metric = DistanceMetric::SweptArea;
+        assert_eq!(actor.turn_weight(), 65.0);
     }
 
     #[test]
@@ -326,39 +1194,36 @@ mod tests {
             size: (300, 300),
         });
 
-        let actor = Planner {
-            pose: Pose { x: 0, y: 0, t: 0 },
-            size: (40, 40),
-            full_path: vec![],
-            compact_path: vec![],
-            splines: None,
-        };
-        assert!(actor.is_valid_path(
+        let actor = Planner::with_config(
             &playground,
-            &Pose {
-                x: 50,
-                y: 50,
-                t: 90
+            PlannerConfig {
+                robot_size: (40, 40),
+                ..Default::default()
             },
-            &Pose {
-                x: 450,
-                y: 50,
-                t: 90
-            }
+        );
+        assert!(actor.is_valid_path(
+            &playground,
+            &Pose2::new(50.0, 50.0, 90f64.to_radians()),
+            &Pose2::new(450.0, 50.0, 90f64.to_radians())
         ));
         assert!(!actor.is_valid_path(
             &playground,
-            &Pose {
-                x: 50,
-                y: 50,
-                t: 90
-            },
-            &Pose {
-                x: 450,
-                y: 450,
-                t: 90
-            }
+            &Pose2::new(50.0, 50.0, 90f64.to_radians()),
+            &Pose2::new(450.0, 450.0, 90f64.to_radians())
         ));
+
+        // Turning on the spot beside the block: the robot fits facing either way, but its corners
+        // swing out to 28.3 pixels from its centre on the way round.
+        let (beside, turned) = (
+            Pose2::new(75.0, 250.0, 0.0),
+            Pose2::new(75.0, 250.0, FRAC_PI_2),
+        );
+        assert!(actor.is_valid_pose(&playground, &beside));
+        assert!(actor.is_valid_pose(&playground, &turned));
+        assert!(!actor.is_valid_path(&playground, &beside, &turned));
+        assert!(!actor.is_valid_path(&playground, &turned, &beside));
+        let (xs, ys) = actor.swept_half_extents(-0.1, 0.1);
+        assert_eq!((xs, ys), actor.half_extents(0.1));
     }
 
     #[test]
@@ -369,55 +1234,20 @@ mod tests {
             size: (300, 300),
         });
         // Vertical line
-        let actor = Planner {
-            pose: Pose { x: 0, y: 0, t: 0 },
-            size: (1, 128),
-            full_path: vec![],
-            compact_path: vec![],
-            splines: None,
-        };
-
-        assert!(actor.is_valid_pose(
-            &playground,
-            &Pose {
-                x: 50,
-                y: 250,
-                t: 0
-            },
-        ));
-        assert!(!actor.is_valid_pose(
+        let actor = Planner::with_config(
             &playground,
-            &Pose {
-                x: 50,
-                y: 250,
-                t: 90
+            PlannerConfig {
+                robot_size: (1, 128),
+                ..Default::default()
             },
-        ));
+        );
 
-        assert!(!actor.is_valid_pose(
-            &playground,
-            &Pose {
-                x: 250,
-                y: 50,
-                t: 0
-            },
-        ));
-        assert!(actor.is_valid_pose(
-            &playground,
-            &Pose {
-                x: 250,
-                y: 50,
-                t: 90
-            },
-        ));
+        assert!(actor.is_valid_pose(&playground, &Pose2::new(50.0, 250.0, 0.0),));
+        assert!(!actor.is_valid_pose(&playground, &Pose2::new(50.0, 250.0, 90f64.to_radians()),));
 
-        assert!(!actor.is_valid_pose(
-            &playground,
-            &Pose {
-                x: 250,
-                y: 250,
-                t: 0
-            },
-        ));
+        assert!(!actor.is_valid_pose(&playground, &Pose2::new(250.0, 50.0, 0.0),));
+        assert!(actor.is_valid_pose(&playground, &Pose2::new(250.0, 50.0, 90f64.to_radians()),));
+
+        assert!(!actor.is_valid_pose(&playground, &Pose2::new(250.0, 250.0, 0.0),));
     }
 }
@@ -2,19 +2,180 @@ extern crate quadtree_rs;
 
 use quadtree_rs::area::AreaBuilder;
 use quadtree_rs::{point::Point, Quadtree};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, PI};
 
-#[derive(Debug, PartialEq)]
+use crate::pose::Pose2;
+use crate::robot::Robot;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Rect {
     pub anchor: (i32, i32), // The top-left corner
     pub size: (i32, i32),
 }
 
+impl Rect {
+    // Do the two rectangles overlap? Rectangles that only share an edge do not.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        return self.anchor.0 < other.anchor.0 + other.size.0
+            && other.anchor.0 < self.anchor.0 + self.size.0
+            && self.anchor.1 < other.anchor.1 + other.size.1
+            && other.anchor.1 < self.anchor.1 + self.size.1;
+    }
+
+    // Is (x, y) inside the rectangle, or on its edge?
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        return self.nearest_point(x, y) == (x, y);
+    }
+
+    // The closest point in the rectangle to (x, y), which is (x, y) itself if it is inside.
+    pub fn nearest_point(&self, x: f64, y: f64) -> (f64, f64) {
+        let (left, top) = (self.anchor.0 as f64, self.anchor.1 as f64);
+        return (
+            x.clamp(left, left + self.size.0 as f64),
+            y.clamp(top, top + self.size.1 as f64),
+        );
+    }
+}
+
+// Draws from a goal region give up and settle for the goal itself after this many misses, as they
+// would forever for an area with no inside to speak of.
+const GOAL_SAMPLE_TRIES: usize = 100;
+
+// Where the robot has to finish to have reached the goal: with its centre within `radius` pixels
+// of the goal or inside `area`, and facing within `heading_tolerance` radians of goal_heading
+// either way. The default is the goal pose exactly.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GoalRegion {
+    #[serde(default)]
+    pub radius: f64,
+    #[serde(default)]
+    pub area: Vec<(i32, i32)>, // The corners of a polygon in order, if it has at least three
+    #[serde(default)]
+    pub heading_tolerance: f64, // π or more allows any heading
+}
+
+impl GoalRegion {
+    // Is the region the goal pose and nothing else?
+    pub fn is_exact(&self) -> bool {
+        return self.radius <= 0.0 && self.area.len() < 3 && self.heading_tolerance <= 0.0;
+    }
+
+    // Checks that the region is one the robot can finish in: a finite radius and heading
+    // tolerance, neither of them negative.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("goal radius", self.radius),
+            ("heading tolerance", self.heading_tolerance),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                return Err(format!("{name} must not be negative, got {value}"));
+            }
+        }
+        return Ok(());
+    }
+
+    // Is `pose` in the region around `goal`?
+    pub fn contains(&self, goal: &Pose2, pose: &Pose2) -> bool {
+        return pose.rotation(goal) <= self.heading_tolerance
+            && self.distance(goal, pose.x, pose.y) == 0.0;
+    }
+
+    // How far (x, y) is from anywhere the region allows the robot's centre to be, which is 0 if
+    // it is one of them.
+    pub fn distance(&self, goal: &Pose2, x: f64, y: f64) -> f64 {
+        let near = ((x - goal.x).hypot(y - goal.y) - self.radius).max(0.0);
+        return match self.area.len() < 3 {
+            true => near,
+            false => near.min(polygon_distance(&self.area, x, y)),
+        };
+    }
+
+    // A random pose in the region around `goal`, uniformly distributed over its positions and
+    // headings. The exact region is just the goal, which takes nothing from `rng`.
+    pub fn sample<R: Rng>(&self, goal: &Pose2, rng: &mut R) -> Pose2 {
+        if self.is_exact() {
+            return *goal;
+        }
+        let tolerance = self.heading_tolerance.clamp(0.0, PI);
+        let theta = goal.theta + rng.gen_range(-tolerance..=tolerance);
+
+        // Draw from the bounding box until a position lands inside.
+        let r = self.radius.max(0.0);
+        let (mut x0, mut y0, mut x1, mut y1) = (goal.x - r, goal.y - r, goal.x + r, goal.y + r);
+        if self.area.len() >= 3 {
+            for &(x, y) in &self.area {
+                (x0, y0) = (x0.min(x as f64), y0.min(y as f64));
+                (x1, y1) = (x1.max(x as f64), y1.max(y as f64));
+            }
+        }
+        for _ in 0..GOAL_SAMPLE_TRIES {
+            let (x, y) = (rng.gen_range(x0..=x1), rng.gen_range(y0..=y1));
+            if self.distance(goal, x, y) == 0.0 {
+                return Pose2::new(x, y, theta);
+            }
+        }
+        return Pose2::new(goal.x, goal.y, theta);
+    }
+}
+
+// How far (x, y) is from the polygon with `corners`, which is 0 inside it. Inside is decided by
+// the even-odd rule, counting the edges a ray from the point crosses.
+fn polygon_distance(corners: &[(i32, i32)], x: f64, y: f64) -> f64 {
+    let mut inside = false;
+    let mut nearest = f64::INFINITY;
+    for (k, &(ax, ay)) in corners.iter().enumerate() {
+        let (bx, by) = corners[(k + 1) % corners.len()];
+        let (ax, ay, bx, by) = (ax as f64, ay as f64, bx as f64, by as f64);
+        if (ay > y) != (by > y) && x < ax + (y - ay) * (bx - ax) / (by - ay) {
+            inside = !inside;
+        }
+        let (dx, dy) = (bx - ax, by - ay);
+        let length = dx * dx + dy * dy;
+        let s = match length > 0.0 {
+            true => (((x - ax) * dx + (y - ay) * dy) / length).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        nearest = nearest.min((x - ax - s * dx).hypot(y - ay - s * dy));
+    }
+    return match inside {
+        true => 0.0,
+        false => nearest,
+    };
+}
+
+// A place the robot has to call at on its way from the start to the goal.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub position: (i32, i32),
+    #[serde(default)]
+    pub heading: Option<f64>, // Radians. If unset the robot may face whichever way fits.
+    #[serde(default)]
+    pub dwell: f64, // Seconds to wait there before setting off again
+}
+
+impl Waypoint {
+    pub fn new(position: (i32, i32)) -> Self {
+        return Self {
+            position,
+            heading: None,
+            dwell: 0.0,
+        };
+    }
+}
+
 pub struct Playground {
     pub size: (i32, i32), // (x,y) bounds. 0,0 is the top-left corner.
     obstacles: Quadtree<i32, u32>,
     obstacle_counter: u32, // TODO: Find a use for the obstacle IDs
     pub start: (i32, i32),
+    pub start_heading: f64, // Radians
     pub goal: (i32, i32),
+    pub goal_heading: f64,        // Radians
+    pub goal_region: GoalRegion,  // Where around the goal will do
+    pub waypoints: Vec<Waypoint>, // Calls to make between the start and the goal, in order
+    pub robot: Robot,             // How the robot drives
 }
 
 impl Playground {
@@ -24,10 +185,59 @@ impl Playground {
             obstacle_counter: 0,
             size,
             start,
+            start_heading: 0.0,
             goal,
+            goal_heading: 0.0,
+            goal_region: GoalRegion::default(),
+            waypoints: vec![],
+            robot: Robot::default(),
         };
     }
 
+    // A copy of the playground with the robot starting at `start` and finishing at `goal`
+    // exactly, for planning one part of a longer journey. The journey's waypoints are left out,
+    // as the part is only from one to the next.
+    pub fn between(&self, start: &Pose2, goal: &Pose2) -> Playground {
+        let mut playground = Playground::new(
+            self.size,
+            (start.x.round() as i32, start.y.round() as i32),
+            (goal.x.round() as i32, goal.y.round() as i32),
+        );
+        playground.start_heading = start.theta;
+        playground.goal_heading = goal.theta;
+        playground.robot = self.robot;
+        for o in self.get_obstacles() {
+            playground.add_obstacles(o);
+        }
+        return playground;
+    }
+
+    // The robot starts facing along start_heading, which is the x axis unless it's been set.
+    pub fn start_pose(&self) -> Pose2 {
+        return Pose2::new(self.start.0 as f64, self.start.1 as f64, self.start_heading);
+    }
+
+    // Likewise the robot should finish facing along goal_heading, or near enough for the goal
+    // region.
+    pub fn goal_pose(&self) -> Pose2 {
+        return Pose2::new(self.goal.0 as f64, self.goal.1 as f64, self.goal_heading);
+    }
+
+    // Has the robot reached the goal region, at `pose`?
+    pub fn in_goal(&self, pose: &Pose2) -> bool {
+        return self.goal_region.contains(&self.goal_pose(), pose);
+    }
+
+    // How far the robot's centre at `pose` has to go at least to reach the goal region.
+    pub fn goal_distance(&self, pose: &Pose2) -> f64 {
+        return self.goal_region.distance(&self.goal_pose(), pose.x, pose.y);
+    }
+
+    // A random pose in the goal region.
+    pub fn sample_goal<R: Rng>(&self, rng: &mut R) -> Pose2 {
+        return self.goal_region.sample(&self.goal_pose(), rng);
+    }
+
     // input format is (x,y) for (top_left_corner, bottom_right_corner)
     // Allows adding obstacles that overlap the bounds of the playground.
     pub fn add_obstacles(&mut self, o: Rect) {
@@ -58,11 +268,7 @@ impl Playground {
 
     // Does the input rectangle intersect any of the obstacles (or playground bounds?)
     pub fn is_collision(&self, r: &Rect) -> bool {
-        if r.anchor.0 < 0
-            || r.anchor.1 < 0
-            || r.anchor.0 + r.size.0 >= self.size.0
-            || r.anchor.1 + r.size.1 >= self.size.1
-        {
+        if !self.contains(r) {
             return true;
         }
 
@@ -75,13 +281,148 @@ impl Playground {
             .build()
             .unwrap();
         let mut query = self.obstacles.query(region);
-        return !query.next().is_none();
+        return query.next().is_some();
+    }
+
+    // Distance from a point to the nearest obstacle or edge of the playground, or zero if the
+    // point is inside an obstacle. Anything further away than `limit` reads as `limit`.
+    pub fn clearance(&self, x: f64, y: f64, limit: f64) -> f64 {
+        return match self.nearest_obstacle(x, y, limit) {
+            Some((nx, ny)) => (nx - x).hypot(ny - y),
+            None => limit,
+        };
+    }
+
+    // The closest point to (x, y) on an obstacle or the edge of the playground, if there is one
+    // within `limit`. Only obstacles near the point are looked up in the quadtree. A point inside
+    // an obstacle is its own nearest point.
+    pub fn nearest_obstacle(&self, x: f64, y: f64, limit: f64) -> Option<(f64, f64)> {
+        let (w, h) = (self.size.0 as f64, self.size.1 as f64);
+        let edges = [(0.0, y), (x, 0.0), (w, y), (x, h)];
+        let mut nearest = None;
+        let mut best = limit;
+        for (nx, ny) in edges {
+            let d = (nx - x).hypot(ny - y);
+            if d <= best {
+                (nearest, best) = (Some((nx, ny)), d);
+            }
+        }
+
+        for o in self.obstacles_near(x, y, limit) {
+            let (nx, ny) = o.nearest_point(x, y);
+            let d = (nx - x).hypot(ny - y);
+            if d <= best {
+                (nearest, best) = (Some((nx, ny)), d);
+            }
+        }
+        return nearest;
+    }
+
+    // Is the rectangle inside the playground? Touching the far edges counts as leaving it.
+    pub fn contains(&self, r: &Rect) -> bool {
+        return r.anchor.0 >= 0
+            && r.anchor.1 >= 0
+            && r.anchor.0 + r.size.0 < self.size.0
+            && r.anchor.1 + r.size.1 < self.size.1;
+    }
+
+    // The obstacles within `radius` of (x, y), looked up in the quadtree. Obstacles a little
+    // further away may be included too. A radius wider than the playground finds them all.
+    pub fn obstacles_near(&self, x: f64, y: f64, radius: f64) -> Vec<Rect> {
+        let radius = radius.min((self.size.0 as f64).hypot(self.size.1 as f64));
+        let region = AreaBuilder::default()
+            .anchor(Point {
+                x: ((x - radius).floor() as i32).max(0),
+                y: ((y - radius).floor() as i32).max(0),
+            })
+            .dimensions((
+                (2.0 * radius).ceil() as i32 + 2,
+                (2.0 * radius).ceil() as i32 + 2,
+            ))
+            .build()
+            .unwrap();
+        return self
+            .obstacles
+            .query(region)
+            .map(|entry| Rect {
+                anchor: (entry.anchor().x, entry.anchor().y),
+                size: (entry.area().width(), entry.area().height()),
+            })
+            .collect();
+    }
+}
+
+// The hand-built obstacle course used by the viewer, laid out for an 800x800 playground.
+pub fn demo(size: (i32, i32)) -> Playground {
+    let start = (50, 50);
+    let goal = (750, 50);
+    let mut playground = Playground::new(size, start, goal);
+
+    let obstacles = [
+        // vertical barrier 1
+        Rect {
+            anchor: (200, 0),
+            size: (100, 650),
+        },
+        Rect {
+            anchor: (200, 700),
+            size: (100, 100),
+        },
+        // vertical barrier 2
+        Rect {
+            anchor: (650, 0),
+            size: (50, 100),
+        },
+        Rect {
+            anchor: (650, 200),
+            size: (50, 100),
+        },
+        // vertical barrier 3
+        Rect {
+            anchor: (500, 50),
+            size: (50, 200),
+        },
+        // horizontal barrier 1
+        Rect {
+            anchor: (300, 500),
+            size: (400, 100),
+        },
+        Rect {
+            anchor: (750, 500),
+            size: (300, 100),
+        },
+        // horizontal barrier 2
+        Rect {
+            anchor: (300, 300),
+            size: (50, 100),
+        },
+        Rect {
+            anchor: (450, 300),
+            size: (400, 100),
+        },
+    ];
+    for o in obstacles {
+        playground.add_obstacles(o);
     }
+    // Listed in a roundabout order, so visiting them in any order makes a shorter tour.
+    playground.waypoints = vec![
+        Waypoint::new((400, 150)),
+        Waypoint {
+            position: (600, 700),
+            heading: Some(FRAC_PI_2),
+            dwell: 1.0,
+        },
+        Waypoint::new((100, 700)),
+    ];
+
+    return playground;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
 
     #[test]
     fn add_obstacle() {
@@ -143,4 +484,96 @@ mod tests {
             size: (100, 100)
         }));
     }
+
+    #[test]
+    fn goal_region() {
+        let mut p = Playground::new((500, 500), (0, 0), (100, 100));
+        p.goal_heading = FRAC_PI_2;
+        let at = |x: f64, y: f64, theta: f64| Pose2::new(x, y, theta);
+
+        // Exactly the goal pose by default.
+        assert!(p.goal_region.is_exact());
+        assert!(p.in_goal(&at(100.0, 100.0, FRAC_PI_2)));
+        assert!(!p.in_goal(&at(101.0, 100.0, FRAC_PI_2)));
+        assert!(!p.in_goal(&at(100.0, 100.0, 1.5)));
+        assert_eq!(p.goal_distance(&at(103.0, 104.0, 0.0)), 5.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert_eq!(p.sample_goal(&mut rng), p.goal_pose());
+
+        // Within 10 pixels, facing within 0.1 radians of the heading.
+        p.goal_region = GoalRegion {
+            radius: 10.0,
+            heading_tolerance: 0.1,
+            ..Default::default()
+        };
+        assert!(p.in_goal(&at(106.0, 108.0, 1.5)));
+        assert!(!p.in_goal(&at(106.0, 108.0, 1.4)));
+        assert!(!p.in_goal(&at(106.0, 109.0, 1.5)));
+        assert!((p.goal_distance(&at(112.0, 116.0, 0.0)) - 10.0).abs() < 1e-9);
+
+        // Or anywhere in an L-shaped area well away from the goal, facing any way.
+        p.goal_region = GoalRegion {
+            area: vec![
+                (300, 300),
+                (400, 300),
+                (400, 350),
+                (350, 350),
+                (350, 400),
+                (300, 400),
+            ],
+            heading_tolerance: PI,
+            ..Default::default()
+        };
+        assert!(p.in_goal(&at(100.0, 100.0, 0.0)));
+        assert!(p.in_goal(&at(320.0, 380.0, -3.0)));
+        assert!(p.in_goal(&at(400.0, 310.0, 2.0)));
+        assert!(!p.in_goal(&at(380.0, 380.0, 0.0)));
+        assert_eq!(p.goal_distance(&at(380.0, 380.0, 0.0)), 30.0);
+        assert_eq!(p.goal_distance(&at(200.0, 350.0, 0.0)), 100.0);
+        for _ in 0..100 {
+            assert!(p.in_goal(&p.sample_goal(&mut rng)));
+        }
+
+        // Negative or NaN tolerances aren't regions at all.
+        assert_eq!(p.goal_region.validate(), Ok(()));
+        for region in [
+            GoalRegion {
+                radius: -1.0,
+                ..Default::default()
+            },
+            GoalRegion {
+                heading_tolerance: f64::NAN,
+                ..Default::default()
+            },
+        ] {
+            assert!(region.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn clearance() {
+        let mut p = Playground::new((500, 500), (0, 0), (0, 0));
+        p.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (300, 300),
+        });
+        assert_eq!(p.clearance(50.0, 250.0, 100.0), 50.0);
+        assert_eq!(p.clearance(70.0, 60.0, 100.0), 50.0);
+        assert_eq!(p.clearance(97.0, 96.0, 100.0), 5.0);
+        assert_eq!(p.clearance(250.0, 250.0, 100.0), 0.0);
+        assert_eq!(p.clearance(490.0, 450.0, 100.0), 10.0);
+        assert_eq!(p.clearance(250.0, 50.0, 20.0), 20.0);
+
+        assert_eq!(p.nearest_obstacle(97.0, 96.0, 100.0), Some((100.0, 100.0)));
+        assert_eq!(p.nearest_obstacle(250.0, 90.0, 100.0), Some((250.0, 100.0)));
+        assert_eq!(
+            p.nearest_obstacle(490.0, 450.0, 100.0),
+            Some((500.0, 450.0))
+        );
+        assert_eq!(p.nearest_obstacle(250.0, 50.0, 20.0), None);
+
+        // Radii far wider than the playground are no different to one that covers it.
+        assert_eq!(p.obstacles_near(250.0, 250.0, 1e10), p.get_obstacles());
+        assert_eq!(p.clearance(50.0, 250.0, f64::INFINITY), 50.0);
+    }
 }
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// A robot pose in the playground frame: position in pixels and heading in radians, normalised to
+// (-π, π] so that every heading has exactly one representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pose2 {
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+}
+
+// A pose snapped to a lattice of whole pixels and degrees. Unlike Pose2 it can be hashed and
+// compared exactly, so the graph searches use it to key their nodes.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Pose {
+    pub x: i32, // Cartesian coordinates in playground frame
+    pub y: i32,
+    pub t: i32, // degrees, in (-180, 180]
+}
+
+// Wraps an angle in radians into (-π, π].
+pub fn normalize_angle(a: f64) -> f64 {
+    let a = a.rem_euclid(2.0 * PI);
+    return match a > PI {
+        true => a - 2.0 * PI,
+        false => a,
+    };
+}
+
+// The signed turn from heading `from` to heading `to` the short way round, in (-π, π].
+pub fn angle_diff(from: f64, to: f64) -> f64 {
+    return normalize_angle(to - from);
+}
+
+impl Pose2 {
+    pub fn new(x: f64, y: f64, theta: f64) -> Self {
+        return Self {
+            x,
+            y,
+            theta: normalize_angle(theta),
+        };
+    }
+
+    // Straight-line distance between the two positions, ignoring heading.
+    pub fn translation(&self, other: &Pose2) -> f64 {
+        return (other.x - self.x).hypot(other.y - self.y);
+    }
+
+    // Smallest angle the robot has to turn through to go from one heading to the other.
+    pub fn rotation(&self, other: &Pose2) -> f64 {
+        return angle_diff(self.theta, other.theta).abs();
+    }
+
+    // The pose a fraction `s` of the way to `other`, turning the short way round.
+    pub fn interpolate(&self, other: &Pose2, s: f64) -> Pose2 {
+        return Pose2::new(
+            self.x + (other.x - self.x) * s,
+            self.y + (other.y - self.y) * s,
+            self.theta + angle_diff(self.theta, other.theta) * s,
+        );
+    }
+
+    // The nearest pose on a lattice with the given spacing in pixels and degrees.
+    pub fn to_lattice(&self, grid_size: i32, angle_step: i32) -> Pose {
+        let snap = |v: f64, step: i32| (v / step as f64).round() as i32 * step;
+        let mut t = snap(self.theta.to_degrees(), angle_step);
+        if t > 180 {
+            t -= 360;
+        } else if t <= -180 {
+            t += 360;
+        }
+        return Pose {
+            x: snap(self.x, grid_size),
+            y: snap(self.y, grid_size),
+            t,
+        };
+    }
+
+    // The hash key for this pose: the nearest whole pixel and degree.
+    pub fn key(&self) -> Pose {
+        return self.to_lattice(1, 1);
+    }
+}
+
+impl Pose {
+    pub fn to_pose2(&self) -> Pose2 {
+        return Pose2::new(self.x as f64, self.y as f64, (self.t as f64).to_radians());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_angle() {
+        assert_eq!(super::normalize_angle(PI), PI);
+        assert_eq!(super::normalize_angle(-PI), PI);
+        assert_eq!(super::normalize_angle(0.0), 0.0);
+        assert!((super::normalize_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1e-12);
+        assert!((super::normalize_angle(-5.0 * PI) - PI).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interpolate_turns_the_short_way() {
+        let from = Pose2::new(0.0, 0.0, 170f64.to_radians());
+        let to = Pose2::new(10.0, 20.0, -170f64.to_radians());
+        assert!((from.rotation(&to) - 20f64.to_radians()).abs() < 1e-12);
+
+        let mid = from.interpolate(&to, 0.5);
+        assert_eq!((mid.x, mid.y), (5.0, 10.0));
+        assert!((mid.theta.abs() - PI).abs() < 1e-12);
+        assert_eq!(from.interpolate(&to, 1.0).key(), to.key());
+    }
+
+    #[test]
+    fn lattice() {
+        let pose = Pose2::new(14.0, -6.0, 268f64.to_radians());
+        assert_eq!(
+            pose.to_lattice(10, 10),
+            Pose {
+                x: 10,
+                y: -10,
+                t: -90
+            }
+        );
+        assert_eq!(
+            pose.key(),
+            Pose {
+                x: 14,
+                y: -6,
+                t: -92
+            }
+        );
+        assert_eq!(
+            Pose { x: 3, y: 4, t: 180 }.to_pose2().key(),
+            Pose { x: 3, y: 4, t: 180 }
+        );
+        assert_eq!(Pose2::new(0.0, 0.0, PI).to_lattice(1, 90).t, 180);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt::Write;
+
+use crate::config::Smoothing;
+use crate::planner::Planner;
+use crate::playground::Playground;
+use crate::pose::Pose2;
+
+// Renders the playground and the planner's paths as an SVG document, using the same colours as the
+// interactive viewer: black obstacles, a green raw path, a red compacted path, a purple smoothed
+// path, a blue robot and the goal region outlined in red.
+pub fn to_svg(playground: &Playground, planner: &Planner) -> String {
+    let mut svg = String::new();
+    let (w, h) = playground.size;
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#
+    )
+    .unwrap();
+    writeln!(svg, r#"<rect width="{w}" height="{h}" fill="white"/>"#).unwrap();
+
+    for r in playground.get_obstacles() {
+        writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="black"/>"#,
+            r.anchor.0, r.anchor.1, r.size.0, r.size.1
+        )
+        .unwrap();
+    }
+
+    polyline(&mut svg, &planner.full_path, "green");
+    polyline(&mut svg, &planner.compact_path, "red");
+    if planner.config.smoothing != Smoothing::Linear {
+        polyline(&mut svg, &planner.smooth_path, "purple");
+    }
+
+    for (x, y) in [playground.start, playground.goal] {
+        writeln!(svg, r#"<circle cx="{x}" cy="{y}" r="10" fill="red"/>"#).unwrap();
+    }
+    let region = &playground.goal_region;
+    let (gx, gy) = playground.goal;
+    if region.radius > 0.0 {
+        writeln!(
+            svg,
+            r#"<circle cx="{gx}" cy="{gy}" r="{}" fill="none" stroke="red"/>"#,
+            region.radius
+        )
+        .unwrap();
+    }
+    if region.area.len() >= 3 {
+        let corners: Vec<String> = region
+            .area
+            .iter()
+            .map(|(x, y)| format!("{x},{y}"))
+            .collect();
+        writeln!(
+            svg,
+            r#"<polygon points="{}" fill="none" stroke="red"/>"#,
+            corners.join(" ")
+        )
+        .unwrap();
+    }
+
+    let p = planner.pose;
+    let (sx, sy) = planner.config.robot_size;
+    writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{sx}" height="{sy}" fill="blue" transform="translate({} {}) rotate({})"/>"#,
+        -sx as f64 / 2.0,
+        -sy as f64 / 2.0,
+        p.x,
+        p.y,
+        p.theta.to_degrees()
+    )
+    .unwrap();
+
+    svg.push_str("</svg>\n");
+    return svg;
+}
+
+fn polyline(svg: &mut String, path: &[Pose2], colour: &str) {
+    if path.is_empty() {
+        return;
+    }
+    let points: Vec<String> = path.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+    writeln!(
+        svg,
+        r#"<polyline points="{}" fill="none" stroke="{colour}" stroke-width="1"/>"#,
+        points.join(" ")
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::Rect;
+
+    #[test]
+    fn to_svg() {
+        let mut playground = Playground::new((200, 100), (10, 10), (190, 90));
+        playground.add_obstacles(Rect {
+            anchor: (50, 0),
+            size: (20, 40),
+        });
+        let mut planner = Planner::new(&playground);
+        planner.compact_path = vec![Pose2::new(10.0, 10.0, 0.0), Pose2::new(190.0, 90.0, 0.0)];
+
+        let svg = super::to_svg(&playground, &planner);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"<rect x="50" y="0" width="20" height="40" fill="black"/>"#));
+        assert!(svg.contains(r#"points="10,10 190,90""#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
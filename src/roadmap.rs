@@ -0,0 +1,280 @@
+use rand::Rng;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+};
+
+use crate::kdtree::KdTree;
+use crate::parallel;
+use crate::planner::{Planner, Progress};
+use crate::playground::Playground;
+use crate::pose::{Pose, Pose2};
+use crate::stats::PlanStats;
+
+// Poses drawn from a goal region that is more than the goal itself and added to the roadmap, so it
+// reaches into the region however the lattice lies across it.
+const GOAL_SAMPLES: usize = 20;
+
+// Probabilistic roadmap pathfinder. Samples config.roadmap_samples poses on the planner's lattice,
+// links each to its nearest neighbours, and searches the resulting graph for the shortest path to
+// any node in the goal region. The pose and edge collision checks dominate the cost, so both are
+// run as parallel batches.
+pub(crate) fn prm_search<R: Rng>(
+    planner: &Planner,
+    playground: &Playground,
+    rng: &mut R,
+    stats: &mut PlanStats,
+) -> Option<Vec<Pose2>> {
+    let config = &planner.config;
+    let start = playground.start_pose();
+    let goal = playground.goal_pose();
+
+    // Sample the roadmap. The start and goal are always nodes 0 and 1.
+    let mut samples = vec![start, goal];
+    let mut seen: HashSet<Pose> = samples.iter().map(|p| p.key()).collect();
+    let goal_samples = match playground.goal_region.is_exact() {
+        true => 0,
+        false => GOAL_SAMPLES,
+    };
+    for _ in 0..goal_samples {
+        let p = playground.sample_goal(rng);
+        if seen.insert(p.key()) {
+            samples.push(p);
+        }
+    }
+    for _ in 0..config.roadmap_samples {
+        let p = planner.sample_pose(playground, rng);
+        if !planner.beyond_bound(playground, &p) && seen.insert(p.key()) {
+            samples.push(p);
+        }
+    }
+    stats.iterations = config.roadmap_samples;
+    if !report(planner, stats) {
+        return None;
+    }
+
+    let valid = parallel::check_poses(planner, playground, &samples[2..]);
+    let mut nodes = vec![start, goal];
+    nodes.extend(samples[2..].iter().zip(valid).filter(|v| v.1).map(|v| *v.0));
+    stats.rejected_samples = goal_samples + config.roadmap_samples - (nodes.len() - 2);
+    stats.tree_size = nodes.len();
+
+    // Link every node to its nearest neighbours, each undirected edge once.
+    let mut index = KdTree::new();
+    for (i, n) in nodes.iter().enumerate() {
+        index.insert(planner.se2_point(n), i);
+    }
+    let mut candidates = Vec::new();
+    let mut linked = HashSet::new();
+    for (i, n) in nodes.iter().enumerate() {
+        for (j, _) in index.k_nearest(&planner.se2_point(n), config.neighbours + 1) {
+            let key = (i.min(*j), i.max(*j));
+            if i != *j && linked.insert(key) {
+                candidates.push(key);
+            }
+        }
+    }
+    let edges: Vec<(Pose2, Pose2)> = candidates
+        .iter()
+        .map(|&(i, j)| (nodes[i], nodes[j]))
+        .collect();
+    if !report(planner, stats) {
+        return None;
+    }
+    let valid = parallel::check_edges(planner, playground, &edges);
+
+    let mut adjacent: Vec<Vec<(usize, f64)>> = vec![Vec::new(); nodes.len()];
+    for (&(i, j), ok) in candidates.iter().zip(valid) {
+        if !ok {
+            continue;
+        }
+        let cost = planner.distance(&nodes[i], &nodes[j]);
+        adjacent[i].push((j, cost));
+        adjacent[j].push((i, cost));
+    }
+    stats.unconnected_samples = adjacent.iter().filter(|a| a.is_empty()).count();
+    if !report(planner, stats) {
+        return None;
+    }
+
+    // The robot has to go somewhere, even if it starts in the goal region.
+    let in_goal: Vec<bool> = nodes.iter().map(|n| playground.in_goal(n)).collect();
+    let path = shortest_path(&adjacent, 0, |n| n != 0 && in_goal[n])?;
+    return Some(path.into_iter().map(|i| nodes[i]).collect());
+}
+
+// Reports progress between the roadmap's phases. Returns false if the search was cancelled.
+fn report(planner: &Planner, stats: &mut PlanStats) -> bool {
+    let progress = Progress {
+        iterations: stats.iterations,
+        tree_size: stats.tree_size,
+        ..Default::default()
+    };
+    stats.cancelled = !planner.report(&progress);
+    return !stats.cancelled;
+}
+
+// A node on the Dijkstra frontier, ordered so the BinaryHeap pops the cheapest (then lowest index).
+struct Frontier {
+    cost: f64,
+    node: usize,
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other
+            .cost
+            .total_cmp(&self.cost)
+            .then(other.node.cmp(&self.node));
+    }
+}
+
+// Dijkstra over an adjacency list. Returns the node indices from `from` to the nearest node that
+// `is_goal` accepts, inclusive.
+fn shortest_path(
+    adjacent: &[Vec<(usize, f64)>],
+    from: usize,
+    is_goal: impl Fn(usize) -> bool,
+) -> Option<Vec<usize>> {
+    let mut cost = vec![f64::INFINITY; adjacent.len()];
+    let mut parent = vec![usize::MAX; adjacent.len()];
+    let mut frontier = BinaryHeap::new();
+    cost[from] = 0.0;
+    frontier.push(Frontier {
+        cost: 0.0,
+        node: from,
+    });
+
+    let mut to = None;
+    while let Some(Frontier { cost: c, node }) = frontier.pop() {
+        if is_goal(node) {
+            to = Some(node);
+            break;
+        }
+        if c > cost[node] {
+            continue;
+        }
+        for &(next, edge) in &adjacent[node] {
+            let c = c + edge;
+            if c < cost[next] {
+                cost[next] = c;
+                parent[next] = node;
+                frontier.push(Frontier {
+                    cost: c,
+                    node: next,
+                });
+            }
+        }
+    }
+
+    let mut path = vec![to?];
+    while *path.last().unwrap() != from {
+        path.push(parent[*path.last().unwrap()]);
+    }
+    path.reverse();
+    return Some(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Algorithm, PlannerConfig};
+    use crate::playground::{GoalRegion, Rect};
+
+    #[test]
+    fn shortest_path() {
+        // 0 - 1 directly costs 10, but 0 - 2 - 1 costs 3.
+        let adjacent = vec![
+            vec![(1, 10.0), (2, 1.0)],
+            vec![(0, 10.0), (2, 2.0)],
+            vec![(0, 1.0), (1, 2.0)],
+            vec![],
+        ];
+        assert_eq!(
+            super::shortest_path(&adjacent, 0, |n| n == 1),
+            Some(vec![0, 2, 1])
+        );
+        assert_eq!(super::shortest_path(&adjacent, 0, |n| n == 3), None);
+        // Either 1 or 2 will do, and 2 is nearer.
+        assert_eq!(
+            super::shortest_path(&adjacent, 0, |n| n == 1 || n == 2),
+            Some(vec![0, 2])
+        );
+    }
+
+    #[test]
+    fn prm_finds_path() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                algorithm: Algorithm::Prm,
+                roadmap_samples: 500,
+                threads: Some(2),
+                ..Default::default()
+            },
+        );
+        planner.seed = 1;
+        planner.compute_path(&playground);
+
+        let path = &planner.full_path;
+        assert!(planner.stats.success);
+        assert_eq!(path.first(), Some(&Pose2::new(50.0, 50.0, 0.0)));
+        assert_eq!(path.last(), Some(&Pose2::new(350.0, 350.0, 0.0)));
+        for w in path.windows(2) {
+            assert!(planner.is_valid_path(&playground, &w[0], &w[1]));
+        }
+    }
+
+    #[test]
+    fn prm_reaches_goal_region() {
+        // The goal is walled in, but anywhere in the room beside it will do, facing up or down.
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (300, 300),
+            size: (100, 100),
+        });
+        playground.goal_heading = std::f64::consts::FRAC_PI_2;
+        playground.goal_region = GoalRegion {
+            area: vec![(250, 200), (400, 200), (400, 300), (250, 300)],
+            heading_tolerance: 0.2,
+            ..Default::default()
+        };
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                algorithm: Algorithm::Prm,
+                roadmap_samples: 500,
+                threads: Some(2),
+                ..Default::default()
+            },
+        );
+        planner.seed = 1;
+        planner.compute_path(&playground);
+
+        let path = &planner.full_path;
+        assert!(planner.stats.success);
+        assert!(playground.in_goal(path.last().unwrap()));
+        for w in path.windows(2) {
+            assert!(planner.is_valid_path(&playground, &w[0], &w[1]));
+        }
+    }
+}
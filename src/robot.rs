@@ -0,0 +1,488 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fmt::Debug;
+
+use crate::pose::{angle_diff, Pose2};
+
+// How far the direction of travel may stray from the robot's heading, in radians, before a path
+// counts as sliding sideways.
+const HEADING_TOLERANCE: f64 = 0.035;
+
+// Edges shorter than this, in pixels, or turning less than this, in radians, count as not moving.
+const EPSILON: f64 = 1e-6;
+
+// A velocity command in the robot's own frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Control {
+    pub linear: f64,  // Pixels per second along the heading
+    pub lateral: f64, // Pixels per second sideways, towards where a positive turn would face
+    pub angular: f64, // Radians per second
+}
+
+// How a robot drives. Every model takes the same velocity commands, but each can only carry out
+// some of them: `limit` maps a command onto the nearest one the robot can follow.
+pub trait RobotModel: Debug {
+    fn limit(&self, control: &Control) -> Control;
+
+    // Can the robot get from one pose to the next along a single circular arc or straight line,
+    // turning on the spot if it has to? Describes the problem if not.
+    fn check_edge(&self, from: &Pose2, to: &Pose2) -> Result<(), String>;
+
+    // Where the robot ends up after following a command for `dt` seconds. The command is limited
+    // first, then integrated exactly, so a constant command traces a circular arc.
+    fn integrate(&self, pose: &Pose2, control: &Control, dt: f64) -> Pose2 {
+        let Control {
+            linear: v,
+            lateral: l,
+            angular: w,
+        } = self.limit(control);
+        let turn = w * dt;
+        // The displacement in the robot's starting frame.
+        let (bx, by) = match turn.abs() < EPSILON {
+            true => (v * dt, l * dt),
+            false => (
+                (v * turn.sin() + l * (turn.cos() - 1.0)) / w,
+                (v * (1.0 - turn.cos()) + l * turn.sin()) / w,
+            ),
+        };
+        let (sin, cos) = pose.theta.sin_cos();
+        return Pose2::new(
+            pose.x + bx * cos - by * sin,
+            pose.y + bx * sin + by * cos,
+            pose.theta + turn,
+        );
+    }
+
+    // Checks that the robot can follow every edge of the path, reporting the first it can't.
+    fn check_path(&self, path: &[Pose2]) -> Result<(), String> {
+        for (i, w) in path.windows(2).enumerate() {
+            self.check_edge(&w[0], &w[1])
+                .map_err(|e| format!("edge {i} from ({:.0}, {:.0}) {e}", w[0].x, w[0].y))?;
+        }
+        return Ok(());
+    }
+}
+
+// A robot with two independently driven wheels either side of its centre. It can turn on the spot
+// but not move sideways, and the faster it turns the slower it can drive.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffDrive {
+    pub track_width: f64,     // Distance between the wheels, in pixels
+    pub max_wheel_speed: f64, // Pixels per second
+}
+
+impl Default for DiffDrive {
+    fn default() -> Self {
+        return Self {
+            track_width: 10.0,
+            max_wheel_speed: 25.0,
+        };
+    }
+}
+
+impl RobotModel for DiffDrive {
+    fn limit(&self, control: &Control) -> Control {
+        // Scale the whole command down, rather than clipping each wheel, so the robot still
+        // follows the same curve.
+        let fastest = control.linear.abs() + control.angular.abs() * self.track_width / 2.0;
+        let scale = match fastest > self.max_wheel_speed {
+            true => self.max_wheel_speed / fastest,
+            false => 1.0,
+        };
+        return Control {
+            linear: control.linear * scale,
+            lateral: 0.0,
+            angular: control.angular * scale,
+        };
+    }
+
+    fn check_edge(&self, from: &Pose2, to: &Pose2) -> Result<(), String> {
+        arc(from, to)?;
+        return Ok(());
+    }
+}
+
+// A car-like robot that steers with its front wheels. It can't move sideways or turn on the spot,
+// and can only turn as tightly as its steering allows.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ackermann {
+    pub wheelbase: f64,    // Distance between the axles, in pixels
+    pub max_steering: f64, // Radians either side of straight ahead
+    pub max_speed: f64,    // Pixels per second
+}
+
+impl Ackermann {
+    // Radius of the tightest circle the robot can drive round, in pixels.
+    pub fn min_turning_radius(&self) -> f64 {
+        return self.wheelbase / self.max_steering.tan();
+    }
+}
+
+impl Default for Ackermann {
+    fn default() -> Self {
+        return Self {
+            wheelbase: 40.0,
+            max_steering: 35f64.to_radians(),
+            max_speed: 25.0,
+        };
+    }
+}
+
+impl RobotModel for Ackermann {
+    fn limit(&self, control: &Control) -> Control {
+        let linear = control.linear.clamp(-self.max_speed, self.max_speed);
+        let max_angular = linear.abs() / self.min_turning_radius();
+        return Control {
+            linear,
+            lateral: 0.0,
+            angular: control.angular.clamp(-max_angular, max_angular),
+        };
+    }
+
+    fn check_edge(&self, from: &Pose2, to: &Pose2) -> Result<(), String> {
+        let Some((length, turn)) = arc(from, to)? else {
+            return Ok(());
+        };
+        if length < EPSILON {
+            return Err("turns on the spot".to_string());
+        }
+        let radius = length / turn.abs();
+        if radius < self.min_turning_radius() - EPSILON {
+            return Err(format!(
+                "turns with radius {radius:.1}, tighter than the minimum {:.1}",
+                self.min_turning_radius()
+            ));
+        }
+        return Ok(());
+    }
+}
+
+// A robot on omnidirectional wheels, which can move in any direction while turning.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Holonomic {
+    pub max_speed: f64,         // Pixels per second, in any direction
+    pub max_angular_speed: f64, // Radians per second
+}
+
+impl Default for Holonomic {
+    fn default() -> Self {
+        return Self {
+            max_speed: 25.0,
+            max_angular_speed: 1.0,
+        };
+    }
+}
+
+impl RobotModel for Holonomic {
+    fn limit(&self, control: &Control) -> Control {
+        let speed = control.linear.hypot(control.lateral);
+        let scale = match speed > self.max_speed {
+            true => self.max_speed / speed,
+            false => 1.0,
+        };
+        return Control {
+            linear: control.linear * scale,
+            lateral: control.lateral * scale,
+            angular: control
+                .angular
+                .clamp(-self.max_angular_speed, self.max_angular_speed),
+        };
+    }
+
+    fn check_edge(&self, _from: &Pose2, _to: &Pose2) -> Result<(), String> {
+        return Ok(());
+    }
+}
+
+// The robot a scenario is driven by. Scenario files name the model and give its parameters, e.g.
+// {"model": "ackermann", "wheelbase": 40.0}, with defaults for anything left out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum Robot {
+    DiffDrive(DiffDrive),
+    Ackermann(Ackermann),
+    Holonomic(Holonomic),
+}
+
+impl Default for Robot {
+    // The planner's paths slide and turn at the same time, which only a holonomic robot can do.
+    fn default() -> Self {
+        return Robot::Holonomic(Holonomic::default());
+    }
+}
+
+impl Robot {
+    // One robot of each model, with its default parameters.
+    pub fn defaults() -> [Robot; 3] {
+        return [
+            Robot::DiffDrive(DiffDrive::default()),
+            Robot::Ackermann(Ackermann::default()),
+            Robot::Holonomic(Holonomic::default()),
+        ];
+    }
+
+    pub fn model(&self) -> &dyn RobotModel {
+        return match self {
+            Robot::DiffDrive(m) => m,
+            Robot::Ackermann(m) => m,
+            Robot::Holonomic(m) => m,
+        };
+    }
+
+    // Checks that the model's parameters describe a robot that can drive: positive, finite
+    // speeds and sizes, and steering that turns but not all the way sideways.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = match self {
+            Robot::DiffDrive(m) => vec![
+                ("track_width", m.track_width),
+                ("max_wheel_speed", m.max_wheel_speed),
+            ],
+            Robot::Ackermann(m) => {
+                if !(m.max_steering > 0.0 && m.max_steering < FRAC_PI_2) {
+                    return Err(format!(
+                        "max_steering must be between 0 and π/2, got {}",
+                        m.max_steering
+                    ));
+                }
+                vec![("wheelbase", m.wheelbase), ("max_speed", m.max_speed)]
+            }
+            Robot::Holonomic(m) => vec![
+                ("max_speed", m.max_speed),
+                ("max_angular_speed", m.max_angular_speed),
+            ],
+        };
+        for (name, value) in positive {
+            if !(value > 0.0 && value.is_finite()) {
+                return Err(format!("robot {name} must be positive, got {value}"));
+            }
+        }
+        return Ok(());
+    }
+}
+
+// Treats the edge as a circular arc, or a straight line, driven forwards or backwards. Returns the
+// arc's length and the turn along it, None if the robot only turns on the spot, or an error if the
+// robot would have to move sideways to follow it.
+fn arc(from: &Pose2, to: &Pose2) -> Result<Option<(f64, f64)>, String> {
+    let chord = from.translation(to);
+    let turn = angle_diff(from.theta, to.theta);
+    if chord < EPSILON {
+        return Ok(match turn.abs() < EPSILON {
+            true => None,
+            false => Some((0.0, turn)),
+        });
+    }
+
+    // On an arc the chord points halfway between the start and end headings.
+    let direction = (to.y - from.y).atan2(to.x - from.x);
+    let mut error = angle_diff(from.theta + turn / 2.0, direction);
+    if error.abs() > PI / 2.0 {
+        error = angle_diff(from.theta + turn / 2.0 + PI, direction);
+    }
+    if error.abs() > HEADING_TOLERANCE {
+        return Err(format!(
+            "moves {:.0} degrees off its heading",
+            error.abs().to_degrees()
+        ));
+    }
+    let length = match turn.abs() < EPSILON {
+        true => chord,
+        false => chord * (turn / 2.0) / (turn / 2.0).sin(),
+    };
+    return Ok(Some((length, turn)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Pose2, b: &Pose2) {
+        assert!(
+            a.translation(b) < 1e-9 && a.rotation(b) < 1e-9,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn integrate() {
+        let model = Holonomic {
+            max_speed: 100.0,
+            max_angular_speed: 10.0,
+        };
+        let start = Pose2::new(10.0, 20.0, FRAC_PI_2);
+        let straight = Control {
+            linear: 5.0,
+            ..Default::default()
+        };
+        assert_near(
+            &model.integrate(&start, &straight, 2.0),
+            &Pose2::new(10.0, 30.0, FRAC_PI_2),
+        );
+
+        // A quarter circle of radius 10.
+        let arc = Control {
+            linear: 10.0 * FRAC_PI_2,
+            lateral: 0.0,
+            angular: FRAC_PI_2,
+        };
+        assert_near(
+            &model.integrate(&start, &arc, 1.0),
+            &Pose2::new(0.0, 30.0, PI),
+        );
+
+        // Sliding sideways while turning, the robot circles the other way round.
+        let slide = Control {
+            linear: 0.0,
+            lateral: 10.0 * FRAC_PI_2,
+            angular: FRAC_PI_2,
+        };
+        assert_near(
+            &model.integrate(&start, &slide, 1.0),
+            &Pose2::new(0.0, 10.0, PI),
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let command = Control {
+            linear: 30.0,
+            lateral: 40.0,
+            angular: 2.0,
+        };
+
+        // Both wheels have to stay within their speed limit, so the command shrinks evenly.
+        let diff = DiffDrive {
+            track_width: 10.0,
+            max_wheel_speed: 20.0,
+        };
+        let limited = diff.limit(&command);
+        assert_eq!(limited.lateral, 0.0);
+        assert!((limited.linear + limited.angular * 5.0 - 20.0).abs() < 1e-9);
+        assert!((limited.linear / limited.angular - 15.0).abs() < 1e-9);
+
+        let car = Ackermann::default();
+        let limited = car.limit(&command);
+        assert_eq!((limited.linear, limited.lateral), (25.0, 0.0));
+        assert!((limited.angular - 25.0 / car.min_turning_radius()).abs() < 1e-9);
+        let spin = Control {
+            angular: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(car.limit(&spin).angular, 0.0);
+
+        let limited = Holonomic::default().limit(&command);
+        assert!((limited.linear.hypot(limited.lateral) - 25.0).abs() < 1e-9);
+        assert_eq!(limited.angular, 1.0);
+    }
+
+    #[test]
+    fn integrated_paths_are_feasible() {
+        let start = Pose2::new(100.0, 100.0, 0.3);
+        let robots = Robot::defaults();
+        for robot in robots {
+            let model = robot.model();
+            let mut path = vec![start];
+            for (linear, angular) in [(20.0, 0.0), (20.0, 0.4), (-10.0, -0.5), (15.0, -2.0)] {
+                let control = Control {
+                    linear,
+                    lateral: 0.0,
+                    angular,
+                };
+                let next = model.integrate(path.last().unwrap(), &control, 0.5);
+                path.push(next);
+            }
+            assert_eq!(model.check_path(&path), Ok(()), "{robot:?}");
+        }
+    }
+
+    #[test]
+    fn check_path() {
+        let diff = DiffDrive::default();
+        let car = Ackermann::default();
+
+        // Turn on the spot, then drive straight along the new heading.
+        let spin = [
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(0.0, 0.0, FRAC_PI_2),
+            Pose2::new(0.0, 50.0, FRAC_PI_2),
+        ];
+        assert_eq!(diff.check_path(&spin), Ok(()));
+        assert!(car.check_path(&spin).unwrap_err().contains("on the spot"));
+
+        // Reversing is fine.
+        let reverse = [Pose2::new(0.0, 0.0, 0.0), Pose2::new(-50.0, 0.0, 0.0)];
+        assert_eq!(diff.check_path(&reverse), Ok(()));
+        assert_eq!(car.check_path(&reverse), Ok(()));
+
+        // Sliding sideways is not.
+        let slide = [Pose2::new(0.0, 0.0, 0.0), Pose2::new(0.0, 50.0, 0.0)];
+        assert!(diff
+            .check_path(&slide)
+            .unwrap_err()
+            .contains("off its heading"));
+        assert_eq!(Holonomic::default().check_path(&slide), Ok(()));
+
+        // A quarter circle is only feasible if it's wider than the car's turning circle.
+        let quarter = |r: f64| [Pose2::new(0.0, 0.0, 0.0), Pose2::new(r, r, FRAC_PI_2)];
+        let radius = car.min_turning_radius();
+        assert_eq!(car.check_path(&quarter(radius + 1.0)), Ok(()));
+        assert!(car
+            .check_path(&quarter(radius - 1.0))
+            .unwrap_err()
+            .contains("tighter"));
+    }
+
+    #[test]
+    fn validate() {
+        for robot in Robot::defaults() {
+            assert_eq!(robot.validate(), Ok(()));
+        }
+        let bad = [
+            Robot::DiffDrive(DiffDrive {
+                track_width: 0.0,
+                ..Default::default()
+            }),
+            Robot::Ackermann(Ackermann {
+                max_speed: -1.0,
+                ..Default::default()
+            }),
+            Robot::Ackermann(Ackermann {
+                max_steering: 0.0,
+                ..Default::default()
+            }),
+            Robot::Ackermann(Ackermann {
+                max_steering: FRAC_PI_2,
+                ..Default::default()
+            }),
+            Robot::Holonomic(Holonomic {
+                max_angular_speed: -1.0,
+                ..Default::default()
+            }),
+            Robot::Holonomic(Holonomic {
+                max_speed: f64::NAN,
+                ..Default::default()
+            }),
+        ];
+        for robot in bad {
+            assert!(robot.validate().is_err(), "{robot:?}");
+        }
+    }
+
+    #[test]
+    fn serde() {
+        let robot: Robot =
+            serde_json::from_str(r#"{"model": "ackermann", "wheelbase": 60.0}"#).unwrap();
+        assert_eq!(
+            robot,
+            Robot::Ackermann(Ackermann {
+                wheelbase: 60.0,
+                ..Default::default()
+            })
+        );
+        let json = serde_json::to_string(&robot).unwrap();
+        assert_eq!(serde_json::from_str::<Robot>(&json).unwrap(), robot);
+    }
+}
@@ -0,0 +1,325 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, path::Path};
+
+use crate::playground::{GoalRegion, Playground, Rect, Waypoint};
+use crate::robot::Robot;
+
+// Scenario::generate gives up after this many obstacles in a row land on the start or goal, as
+// it would forever if the keep-out zones covered the whole map.
+const GENERATE_TRIES: usize = 1000;
+
+// A serialisable description of a playground, used to load and save maps from disk.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub size: (i32, i32),
+    pub start: (i32, i32),
+    pub goal: (i32, i32),
+    #[serde(default)]
+    pub start_heading: f64, // Radians
+    #[serde(default)]
+    pub goal_heading: f64, // Radians
+    #[serde(default)]
+    pub goal_region: GoalRegion,
+    #[serde(default)]
+    pub obstacles: Vec<Rect>,
+    #[serde(default)]
+    pub waypoints: Vec<Waypoint>,
+    #[serde(default)]
+    pub robot: Robot,
+}
+
+impl Scenario {
+    pub fn from_playground(playground: &Playground) -> Self {
+        return Self {
+            size: playground.size,
+            start: playground.start,
+            goal: playground.goal,
+            start_heading: playground.start_heading,
+            goal_heading: playground.goal_heading,
+            goal_region: playground.goal_region.clone(),
+            obstacles: playground.get_obstacles(),
+            waypoints: playground.waypoints.clone(),
+            robot: playground.robot,
+        };
+    }
+
+    pub fn to_playground(&self) -> Playground {
+        let mut playground = Playground::new(self.size, self.start, self.goal);
+        playground.start_heading = self.start_heading;
+        playground.goal_heading = self.goal_heading;
+        playground.goal_region = self.goal_region.clone();
+        playground.waypoints = self.waypoints.clone();
+        playground.robot = self.robot;
+        for o in &self.obstacles {
+            playground.add_obstacles(*o);
+        }
+        return playground;
+    }
+
+    // Checks that the scenario describes a playground the planner can work in: a non-empty map,
+    // obstacles with some area, the start and goal inside the map, a goal region the robot can
+    // finish in and a robot that can drive.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.size.0 <= 0 || self.size.1 <= 0 {
+            return Err(format!("size must be positive, got {:?}", self.size));
+        }
+        let inside =
+            |p: (i32, i32)| (0..self.size.0).contains(&p.0) && (0..self.size.1).contains(&p.1);
+        for (name, p) in [("start", self.start), ("goal", self.goal)] {
+            if !inside(p) {
+                return Err(format!("{name} must be inside the map, got {p:?}"));
+            }
+        }
+        for (name, heading) in [
+            ("start_heading", self.start_heading),
+            ("goal_heading", self.goal_heading),
+        ] {
+            if !heading.is_finite() {
+                return Err(format!("{name} must be finite, got {heading}"));
+            }
+        }
+        self.goal_region.validate()?;
+        for o in &self.obstacles {
+            if o.size.0 <= 0 || o.size.1 <= 0 {
+                return Err(format!("obstacle sizes must be positive, got {:?}", o.size));
+            }
+        }
+        for w in &self.waypoints {
+            if !inside(w.position) {
+                return Err(format!(
+                    "waypoints must be inside the map, got {:?}",
+                    w.position
+                ));
+            }
+            if w.heading.is_some_and(|h| !h.is_finite()) {
+                return Err(format!(
+                    "waypoint headings must be finite, got {:?}",
+                    w.heading
+                ));
+            }
+            if !(w.dwell >= 0.0 && w.dwell.is_finite()) {
+                return Err(format!("dwell must not be negative, got {}", w.dwell));
+            }
+        }
+        return self.robot.validate();
+    }
+
+    // Reads a JSON scenario file. The result is validated before it is returned.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let scenario: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        scenario.validate()?;
+        return Ok(scenario);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        return Ok(());
+    }
+
+    // Scatters `count` random obstacles over an empty map with the start and goal in opposite
+    // corners. Obstacles that would cover the start or goal (plus `clearance` on every side) are
+    // redrawn so that both endpoints stay free, giving up if they cover too much of the map.
+    pub fn generate<R: Rng>(
+        size: (i32, i32),
+        count: usize,
+        clearance: i32,
+        rng: &mut R,
+    ) -> Result<Self, String> {
+        let margin = clearance.max(size.0 / 16).max(size.1 / 16);
+        let start = (margin, margin);
+        let goal = (size.0 - margin, size.1 - margin);
+        let keep_out = |p: (i32, i32)| Rect {
+            anchor: (p.0 - clearance, p.1 - clearance),
+            size: (2 * clearance, 2 * clearance),
+        };
+        let keep_out = [keep_out(start), keep_out(goal)];
+
+        let mut obstacles = Vec::new();
+        let mut misses = 0;
+        while obstacles.len() < count {
+            let w = rng.gen_range(size.0 / 40..=size.0 / 6).max(1);
+            let h = rng.gen_range(size.1 / 40..=size.1 / 6).max(1);
+            let o = Rect {
+                anchor: (rng.gen_range(0..size.0 - w), rng.gen_range(0..size.1 - h)),
+                size: (w, h),
+            };
+            if keep_out.iter().any(|k| k.intersects(&o)) {
+                misses += 1;
+                if misses == GENERATE_TRIES {
+                    return Err(format!(
+                        "could only place {} of {count} obstacles clear of the start and goal",
+                        obstacles.len()
+                    ));
+                }
+                continue;
+            }
+            misses = 0;
+            obstacles.push(o);
+        }
+
+        let scenario = Self {
+            size,
+            start,
+            goal,
+            start_heading: 0.0,
+            goal_heading: 0.0,
+            goal_region: GoalRegion::default(),
+            obstacles,
+            waypoints: vec![],
+            robot: Robot::default(),
+        };
+        scenario.validate()?;
+        return Ok(scenario);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::DiffDrive;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn round_trip() {
+        let mut playground = Playground::new((500, 400), (10, 20), (480, 380));
+        playground.robot = Robot::DiffDrive(DiffDrive::default());
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (50, 60),
+        });
+        playground.start_heading = -2.0;
+        playground.goal_heading = 1.0;
+        playground.goal_region = GoalRegion {
+            radius: 20.0,
+            area: vec![(400, 300), (450, 350), (400, 400)],
+            heading_tolerance: 0.3,
+        };
+        playground.waypoints = vec![
+            Waypoint::new((200, 300)),
+            Waypoint {
+                position: (400, 50),
+                heading: Some(1.5),
+                dwell: 2.0,
+            },
+        ];
+
+        let scenario = Scenario::from_playground(&playground);
+        let json = serde_json::to_string(&scenario).unwrap();
+        let parsed: Scenario = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, scenario);
+
+        let restored = parsed.to_playground();
+        assert_eq!(restored.size, playground.size);
+        assert_eq!(restored.get_obstacles(), playground.get_obstacles());
+        assert_eq!(restored.start_pose(), playground.start_pose());
+        assert_eq!(restored.goal_pose(), playground.goal_pose());
+        assert_eq!(restored.goal_region, playground.goal_region);
+        assert_eq!(restored.waypoints, playground.waypoints);
+        assert_eq!(restored.robot, playground.robot);
+    }
+
+    #[test]
+    fn robot_defaults_to_holonomic() {
+        let json = r#"{"size": [100, 100], "start": [10, 10], "goal": [90, 90]}"#;
+        let scenario: Scenario = serde_json::from_str(json).unwrap();
+        assert_eq!(scenario.robot, Robot::default());
+        assert!(scenario.goal_region.is_exact());
+        assert!(scenario.waypoints.is_empty());
+    }
+
+    #[test]
+    fn waypoint_defaults() {
+        let json = r#"{"position": [10, 20]}"#;
+        let waypoint: Waypoint = serde_json::from_str(json).unwrap();
+        assert_eq!(waypoint, Waypoint::new((10, 20)));
+    }
+
+    #[test]
+    fn generate_keeps_endpoints_clear() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let scenario = Scenario::generate((800, 800), 30, 40, &mut rng).unwrap();
+        assert_eq!(scenario.obstacles.len(), 30);
+
+        let playground = scenario.to_playground();
+        for p in [scenario.start, scenario.goal] {
+            assert!(!playground.is_collision(&Rect {
+                anchor: (p.0 - 30, p.1 - 30),
+                size: (60, 60),
+            }));
+        }
+    }
+
+    #[test]
+    fn generate_gives_up_when_the_endpoints_cover_the_map() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(Scenario::generate((800, 800), 5, 400, &mut rng).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let good =
+            Scenario::generate((100, 100), 3, 10, &mut ChaCha8Rng::seed_from_u64(1)).unwrap();
+        assert_eq!(good.validate(), Ok(()));
+
+        let obstacle = Rect {
+            anchor: (10, 10),
+            size: (0, 5),
+        };
+        let bad = [
+            Scenario {
+                size: (0, 100),
+                ..good.clone()
+            },
+            Scenario {
+                start: (-1, 10),
+                ..good.clone()
+            },
+            Scenario {
+                goal: (10, 100),
+                ..good.clone()
+            },
+            Scenario {
+                start_heading: f64::INFINITY,
+                ..good.clone()
+            },
+            Scenario {
+                goal_heading: f64::NAN,
+                ..good.clone()
+            },
+            Scenario {
+                goal_region: GoalRegion {
+                    radius: -1.0,
+                    ..Default::default()
+                },
+                ..good.clone()
+            },
+            Scenario {
+                obstacles: vec![obstacle],
+                ..good.clone()
+            },
+            Scenario {
+                waypoints: vec![Waypoint::new((200, 10))],
+                ..good.clone()
+            },
+            Scenario {
+                robot: Robot::DiffDrive(DiffDrive {
+                    max_wheel_speed: 0.0,
+                    ..Default::default()
+                }),
+                ..good.clone()
+            },
+            Scenario {
+                waypoints: vec![Waypoint {
+                    dwell: -1.0,
+                    ..Waypoint::new((50, 50))
+                }],
+                ..good.clone()
+            },
+        ];
+        for scenario in bad {
+            assert!(scenario.validate().is_err(), "{scenario:?}");
+        }
+    }
+}
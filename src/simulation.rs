@@ -0,0 +1,372 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::controller::{TrackedPath, Tracker};
+use crate::localise::Localiser;
+use crate::planner::Planner;
+use crate::playground::Playground;
+use crate::pose::Pose2;
+
+// The robot has arrived once it is this close to the end of the path, in pixels, or anywhere in the
+// goal region.
+const GOAL_TOLERANCE: f64 = 5.0;
+
+// Seconds the robot gets to finish, beyond `TIME_FACTOR` times the time the path takes at full
+// speed, before the simulation gives up.
+const TIME_ALLOWANCE: f64 = 10.0;
+const TIME_FACTOR: f64 = 3.0;
+
+// The state of the simulated robot at one step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct SimSample {
+    pub time: f64,
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+    pub linear: f64, // The command for the step that follows, after the robot's limits
+    pub lateral: f64,
+    pub angular: f64,
+    pub cross_track_error: f64, // Signed distance from the path, in pixels
+    pub estimated_x: f64,       // The pose the controller steers by, which is the true one
+    pub estimated_y: f64,       // unless the robot is localising
+    pub estimated_theta: f64,
+    pub dead_reckoning_error: f64, // How far odometry alone would have put the robot out
+}
+
+impl SimSample {
+    pub fn pose(&self) -> Pose2 {
+        return Pose2::new(self.x, self.y, self.theta);
+    }
+
+    pub fn estimate(&self) -> Pose2 {
+        return Pose2::new(self.estimated_x, self.estimated_y, self.estimated_theta);
+    }
+
+    // How far the estimate is from the true position.
+    pub fn localisation_error(&self) -> f64 {
+        return self.pose().translation(&self.estimate());
+    }
+}
+
+// A closed-loop run of the robot along the planned path. Unlike update_pos, which puts the robot
+// exactly where the trajectory says it should be, the controller only sees the robot's pose and
+// the robot only moves as its model allows, so the run shows whether the plan can be followed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Simulation {
+    pub samples: Vec<SimSample>, // One per config.timestep seconds
+    pub reached_goal: bool,
+    pub collision_time: Option<f64>, // When the robot first hit something, if it did
+    pub particles: Vec<(f64, Vec<Pose2>)>, // The particle filter's guesses after every scan, if any
+}
+
+impl Simulation {
+    // Drives the playground's robot along the planner's smoothed path with config.controller,
+    // from the start of the path until it arrives or runs out of time. With config.localise the
+    // controller steers by a particle filter's estimate of the pose, seeded with the planner's
+    // seed, rather than the pose itself.
+    pub fn run(planner: &Planner, playground: &Playground) -> Self {
+        return Self::run_cancellable(planner, playground, &AtomicBool::new(false));
+    }
+
+    // Like run, but stops early, with the samples so far, once `cancel` is set.
+    pub fn run_cancellable(
+        planner: &Planner,
+        playground: &Playground,
+        cancel: &AtomicBool,
+    ) -> Self {
+        let config = &planner.config;
+        let model = playground.robot.model();
+        let poses = match planner.smooth_path.is_empty() {
+            true => vec![planner.pose],
+            false => planner.smooth_path.clone(),
+        };
+        let path = TrackedPath::new(&poses);
+        let mut tracker = Tracker::new(config, &playground.robot);
+        let dt = config.timestep;
+        let max_time = TIME_FACTOR * path.length() / config.speed + TIME_ALLOWANCE;
+
+        let mut simulation = Simulation::default();
+        let mut pose = poses[0];
+        let mut time = 0.0;
+        let mut localiser = match config.localise {
+            true => {
+                let localiser = Localiser::new(config, &pose, planner.seed);
+                simulation
+                    .particles
+                    .push((time, localiser.filter.particles.clone()));
+                Some(localiser)
+            }
+            false => None,
+        };
+        loop {
+            let (estimate, dead_reckoning) = match &localiser {
+                Some(l) => (l.estimate, l.dead_reckoning),
+                None => (pose, pose),
+            };
+            let (command, projection) = tracker.command(planner, playground, &path, &estimate, dt);
+            let control = model.limit(&command);
+            simulation.samples.push(SimSample {
+                time,
+                x: pose.x,
+                y: pose.y,
+                theta: pose.theta,
+                linear: control.linear,
+                lateral: control.lateral,
+                angular: control.angular,
+                cross_track_error: projection.error,
+                estimated_x: estimate.x,
+                estimated_y: estimate.y,
+                estimated_theta: estimate.theta,
+                dead_reckoning_error: pose.translation(&dead_reckoning),
+            });
+            if simulation.collision_time.is_none() && !planner.is_valid_pose(playground, &pose) {
+                simulation.collision_time = Some(time);
+            }
+
+            let (ex, ey) = path.end();
+            if (ex - pose.x).hypot(ey - pose.y) < GOAL_TOLERANCE || playground.in_goal(&pose) {
+                simulation.reached_goal = true;
+                break;
+            }
+            if time >= max_time || cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let next = model.integrate(&pose, &control, dt);
+            time += dt;
+            if let Some(localiser) = &mut localiser {
+                if localiser.step(playground, &pose, &next, dt) {
+                    let particles = localiser.filter.particles.clone();
+                    simulation.particles.push((time, particles));
+                }
+            }
+            pose = next;
+        }
+        return simulation;
+    }
+
+    pub fn duration(&self) -> f64 {
+        return self.samples.last().map_or(0.0, |s| s.time);
+    }
+
+    // Where the simulated robot is at time `t`, holding still before the start and after the end.
+    pub fn sample(&self, t: f64) -> Option<&SimSample> {
+        let i = self.samples.partition_point(|s| s.time <= t);
+        return self.samples.get(i.saturating_sub(1));
+    }
+
+    // The particle filter's guesses as of time `t`, if the robot was localising.
+    pub fn particles_at(&self, t: f64) -> Option<&[Pose2]> {
+        let i = self.particles.partition_point(|(time, _)| *time <= t);
+        return self
+            .particles
+            .get(i.saturating_sub(1))
+            .map(|(_, particles)| particles.as_slice());
+    }
+
+    pub fn max_cross_track_error(&self) -> f64 {
+        return self.max(|s| s.cross_track_error.abs());
+    }
+
+    // Root mean square cross-track error over the run.
+    pub fn rms_cross_track_error(&self) -> f64 {
+        return self.rms(|s| s.cross_track_error);
+    }
+
+    pub fn max_localisation_error(&self) -> f64 {
+        return self.max(SimSample::localisation_error);
+    }
+
+    pub fn rms_localisation_error(&self) -> f64 {
+        return self.rms(SimSample::localisation_error);
+    }
+
+    pub fn max_dead_reckoning_error(&self) -> f64 {
+        return self.max(|s| s.dead_reckoning_error);
+    }
+
+    pub fn rms_dead_reckoning_error(&self) -> f64 {
+        return self.rms(|s| s.dead_reckoning_error);
+    }
+
+    fn max(&self, value: impl Fn(&SimSample) -> f64) -> f64 {
+        return self.samples.iter().map(value).fold(0.0, f64::max);
+    }
+
+    fn rms(&self, value: impl Fn(&SimSample) -> f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.samples.iter().map(|s| value(s).powi(2)).sum();
+        return (sum / self.samples.len() as f64).sqrt();
+    }
+
+    // How the run went, for the stats overlay and `simulate`.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("reached goal: {}", self.reached_goal),
+            match self.collision_time {
+                None => "collision: none".to_string(),
+                Some(t) => format!("collision: at {t:.2} s"),
+            },
+            format!("tracking time: {:.1} s", self.duration()),
+            format!(
+                "cross-track error: {:.1} max, {:.1} rms",
+                self.max_cross_track_error(),
+                self.rms_cross_track_error()
+            ),
+        ];
+        if !self.particles.is_empty() {
+            lines.push(format!(
+                "localisation error: {:.1} max, {:.1} rms",
+                self.max_localisation_error(),
+                self.rms_localisation_error()
+            ));
+            lines.push(format!(
+                "dead reckoning error: {:.1} max, {:.1} rms",
+                self.max_dead_reckoning_error(),
+                self.rms_dead_reckoning_error()
+            ));
+        }
+        return lines;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Controller, LocalisationConfig, PlannerConfig, Smoothing};
+    use crate::robot::Robot;
+
+    #[test]
+    fn controllers_follow_the_path() {
+        let mut playground = Playground::new((600, 600), (50, 300), (550, 300));
+        let robots = Robot::defaults();
+        for robot in robots {
+            playground.robot = robot;
+            for controller in [
+                Controller::PurePursuit,
+                Controller::Stanley,
+                Controller::PidHeading,
+                Controller::Mpc,
+                Controller::Dwa,
+            ] {
+                let mut planner = Planner::with_config(
+                    &playground,
+                    PlannerConfig {
+                        controller,
+                        smoothing: Smoothing::Arcs,
+                        corner_radius: 80.0,
+                        ..Default::default()
+                    },
+                );
+                // A dogleg with gentle corners, starting in line with the first leg.
+                planner.smooth_path = crate::smoothing::smooth(
+                    &planner,
+                    &playground,
+                    &[
+                        Pose2::new(50.0, 300.0, 0.0),
+                        Pose2::new(250.0, 300.0, 0.0),
+                        Pose2::new(350.0, 150.0, 0.0),
+                        Pose2::new(550.0, 150.0, 0.0),
+                    ],
+                );
+                let simulation = Simulation::run(&planner, &playground);
+                let name = format!("{robot:?} {controller:?}");
+                assert!(simulation.reached_goal, "{name}");
+                assert_eq!(simulation.collision_time, None, "{name}");
+                assert!(simulation.max_cross_track_error() < 10.0, "{name}");
+                assert!(simulation.rms_cross_track_error() < 3.0, "{name}");
+
+                // Fixed timestep.
+                for w in simulation.samples.windows(2) {
+                    assert!((w[1].time - w[0].time - 0.02).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn steers_by_the_localised_pose() {
+        // Pillars either side of the way give the lidar something to see.
+        let mut playground = Playground::new((600, 600), (50, 300), (550, 150));
+        for anchor in [(120, 220), (300, 380), (300, 60), (450, 230), (520, 40)] {
+            playground.add_obstacles(crate::playground::Rect {
+                anchor,
+                size: (30, 30),
+            });
+        }
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                localise: true,
+                localisation: LocalisationConfig {
+                    heading_drift: 0.01,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        planner.seed = 3;
+        planner.smooth_path = vec![
+            Pose2::new(50.0, 300.0, 0.0),
+            Pose2::new(250.0, 300.0, 0.0),
+            Pose2::new(350.0, 150.0, 0.0),
+            Pose2::new(550.0, 150.0, 0.0),
+        ];
+        let simulation = Simulation::run(&planner, &playground);
+        assert!(simulation.reached_goal);
+        assert_eq!(simulation.collision_time, None);
+        assert!(simulation.max_localisation_error() < 6.0);
+        assert!(simulation.rms_localisation_error() < 2.0);
+        assert!(simulation.max_dead_reckoning_error() > 5.0 * simulation.max_localisation_error());
+        assert!(simulation.rms_cross_track_error() < 5.0);
+        let lines = simulation.lines();
+        assert!(lines[4].starts_with("localisation error: "));
+        assert!(lines[5].starts_with("dead reckoning error: "));
+
+        // The guesses start out round the start, and are redrawn at every scan.
+        assert_eq!(simulation.particles_at(0.0).unwrap().len(), 300);
+        let scans = (simulation.duration() / 0.1).floor() as usize;
+        assert!(simulation.particles.len().abs_diff(scans + 1) <= 1);
+
+        // Replays exactly.
+        assert_eq!(Simulation::run(&planner, &playground), simulation);
+    }
+
+    #[test]
+    fn reports_collisions() {
+        // Cutting the corner hits the obstacle inside it.
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(crate::playground::Rect {
+            anchor: (200, 70),
+            size: (100, 100),
+        });
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                robot_size: (10, 10),
+                lookahead: 150.0,
+                ..Default::default()
+            },
+        );
+        planner.smooth_path = vec![
+            Pose2::new(50.0, 50.0, 0.0),
+            Pose2::new(320.0, 50.0, 0.0),
+            Pose2::new(320.0, 350.0, 0.0),
+        ];
+        let simulation = Simulation::run(&planner, &playground);
+        assert!(simulation.collision_time.is_some());
+        assert!(simulation.max_cross_track_error() > 10.0);
+        assert!(simulation.lines()[1].starts_with("collision: at"));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let playground = Playground::new((400, 400), (50, 50), (350, 50));
+        let mut planner = Planner::new(&playground);
+        planner.smooth_path = vec![Pose2::new(50.0, 50.0, 0.0), Pose2::new(350.0, 50.0, 0.0)];
+        let simulation = Simulation::run_cancellable(&planner, &playground, &AtomicBool::new(true));
+        assert_eq!(simulation.samples.len(), 1);
+        assert!(!simulation.reached_goal);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::config::Smoothing;
+use crate::planner::Planner;
+use crate::playground::Playground;
+use crate::pose::{angle_diff, Pose2};
+
+// Spacing between the samples that approximate a curve, in pixels.
+const SAMPLE_SPACING: f64 = 5.0;
+
+// x, y and the unwrapped heading, so that the curves can treat headings like any other coordinate.
+type Point = [f64; 3];
+
+// A stretch of the smoothed path, and the waypoints to leave as sharp corners if it collides.
+// Straight pieces lie along the original segments, which the planner has already checked, so
+// they have no waypoints and are not checked again.
+struct Piece {
+    points: Vec<Point>,
+    waypoints: Vec<usize>,
+}
+
+// Smooths a path with config.smoothing and returns the curve sampled every few pixels. Every
+// curved piece is collision checked; where one collides, the waypoints that shaped it are kept as
+// sharp corners and the path is smoothed again either side of them. In the worst case this falls
+// all the way back to the original, linear path.
+pub fn smooth(planner: &Planner, playground: &Playground, path: &[Pose2]) -> Vec<Pose2> {
+    if planner.config.smoothing == Smoothing::Linear || path.len() < 3 {
+        return path.to_vec();
+    }
+    let mut points: Vec<Point> = vec![[path[0].x, path[0].y, path[0].theta]];
+    for w in path.windows(2) {
+        let h = points.last().unwrap()[2] + angle_diff(w[0].theta, w[1].theta);
+        points.push([w[1].x, w[1].y, h]);
+    }
+
+    let mut sharp = vec![false; path.len()];
+    sharp[0] = true;
+    sharp[path.len() - 1] = true;
+    loop {
+        let mut pieces = Vec::new();
+        let mut from = 0;
+        for to in 1..path.len() {
+            if sharp[to] {
+                pieces.extend(section(planner, &points[from..=to], from));
+                from = to;
+            }
+        }
+
+        let mut done = true;
+        for piece in &pieces {
+            if !piece.waypoints.is_empty() && !is_valid(planner, playground, &piece.points) {
+                for &w in &piece.waypoints {
+                    sharp[w] = true;
+                }
+                done = false;
+            }
+        }
+        if done {
+            return join(&pieces);
+        }
+    }
+}
+
+// Smooths the waypoints between two sharp corners. `offset` is the index of the first one in the
+// whole path, so that pieces can name the waypoints that shaped them.
+fn section(planner: &Planner, points: &[Point], offset: usize) -> Vec<Piece> {
+    if points.len() == 2 {
+        return vec![Piece {
+            points: points.to_vec(),
+            waypoints: vec![],
+        }];
+    }
+    // The interior waypoints are the only ones that can be made sharp.
+    let interior = |range: std::ops::RangeInclusive<usize>| -> Vec<usize> {
+        return range
+            .filter(|&i| i > 0 && i < points.len() - 1)
+            .map(|i| i + offset)
+            .collect();
+    };
+    let last = points.len() - 1;
+    let mut pieces = Vec::new();
+
+    match planner.config.smoothing {
+        Smoothing::Linear => unreachable!(),
+        Smoothing::CatmullRom | Smoothing::Hermite => {
+            let tangents: Vec<Point> = (0..=last)
+                .map(|i| match planner.config.smoothing {
+                    Smoothing::CatmullRom => catmull_rom_tangent(points, i),
+                    _ => hermite_tangent(points, i),
+                })
+                .collect();
+            for i in 0..last {
+                let (p1, p2) = (points[i], points[i + 1]);
+                let (m1, m2) = (tangents[i], tangents[i + 1]);
+                let samples = sample_count(&[p1, p2]);
+                pieces.push(Piece {
+                    points: (0..=samples)
+                        .map(|k| hermite(p1, m1, p2, m2, k as f64 / samples as f64))
+                        .collect(),
+                    waypoints: interior(i..=i + 1),
+                });
+            }
+        }
+        Smoothing::BSpline => {
+            // Tripling the end points clamps the curve to them.
+            let mut control = vec![points[0], points[0]];
+            control.extend_from_slice(points);
+            control.extend([points[last], points[last]]);
+            for j in 0..control.len() - 3 {
+                let span = [control[j], control[j + 1], control[j + 2], control[j + 3]];
+                let samples = sample_count(&span);
+                // Control point j + k is waypoint j + k - 2, clamped to the ends.
+                let first = j.saturating_sub(2);
+                let end = (j + 1).min(last);
+                pieces.push(Piece {
+                    points: (0..=samples)
+                        .map(|k| b_spline(&span, k as f64 / samples as f64))
+                        .collect(),
+                    waypoints: interior(first..=end),
+                });
+            }
+            // The clamped ends land on the end points, up to rounding, so pin them exactly.
+            pieces[0].points[0] = points[0];
+            *pieces.last_mut().unwrap().points.last_mut().unwrap() = points[last];
+        }
+        Smoothing::Arcs => {
+            let mut from = points[0];
+            for i in 1..last {
+                let corner = match fillet(points[i - 1], points[i], points[i + 1], planner) {
+                    None => continue,
+                    Some(corner) => corner,
+                };
+                pieces.push(Piece {
+                    points: vec![from, corner[0]],
+                    waypoints: vec![],
+                });
+                from = *corner.last().unwrap();
+                pieces.push(Piece {
+                    points: corner,
+                    waypoints: vec![i + offset],
+                });
+            }
+            pieces.push(Piece {
+                points: vec![from, points[last]],
+                waypoints: vec![],
+            });
+        }
+    }
+    return pieces;
+}
+
+// Catmull-Rom tangent: half the chord between the neighbouring waypoints.
+fn catmull_rom_tangent(points: &[Point], i: usize) -> Point {
+    let prev = points[i.saturating_sub(1)];
+    let next = points[(i + 1).min(points.len() - 1)];
+    return scale(sub(next, prev), 0.5);
+}
+
+// Hermite tangent: along the chord between the neighbouring waypoints, but no longer than the
+// shorter of the two segments, so that short segments don't overshoot. The end tangents follow
+// the first and last segments.
+fn hermite_tangent(points: &[Point], i: usize) -> Point {
+    let last = points.len() - 1;
+    if i == 0 {
+        return sub(points[1], points[0]);
+    }
+    if i == last {
+        return sub(points[last], points[last - 1]);
+    }
+    let chord = sub(points[i + 1], points[i - 1]);
+    let length = chord[0].hypot(chord[1]);
+    if length == 0.0 {
+        return [0.0; 3];
+    }
+    let shorter = xy_dist(points[i - 1], points[i]).min(xy_dist(points[i], points[i + 1]));
+    return scale(chord, shorter / length);
+}
+
+// Cubic Hermite curve from p1 to p2 with tangents m1 and m2, at s in [0, 1].
+fn hermite(p1: Point, m1: Point, p2: Point, m2: Point, s: f64) -> Point {
+    let (s2, s3) = (s * s, s * s * s);
+    let basis = [
+        2.0 * s3 - 3.0 * s2 + 1.0,
+        s3 - 2.0 * s2 + s,
+        -2.0 * s3 + 3.0 * s2,
+        s3 - s2,
+    ];
+    return combine(&[p1, m1, p2, m2], &basis);
+}
+
+// One span of a uniform cubic B-spline, at s in [0, 1].
+fn b_spline(span: &[Point; 4], s: f64) -> Point {
+    let (s2, s3) = (s * s, s * s * s);
+    let basis = [
+        (1.0 - s).powi(3) / 6.0,
+        (3.0 * s3 - 6.0 * s2 + 4.0) / 6.0,
+        (-3.0 * s3 + 3.0 * s2 + 3.0 * s + 1.0) / 6.0,
+        s3 / 6.0,
+    ];
+    return combine(span, &basis);
+}
+
+// Rounds the corner at b with a circular arc of config.corner_radius, tangent to both segments.
+// The radius shrinks if the arc would use up more than half of either segment. Returns the arc
+// sampled from where it leaves the first segment to where it joins the second, or None if the
+// path doesn't turn (or turns straight back on itself).
+fn fillet(a: Point, b: Point, c: Point, planner: &Planner) -> Option<Vec<Point>> {
+    let (len_in, len_out) = (xy_dist(a, b), xy_dist(b, c));
+    if len_in == 0.0 || len_out == 0.0 {
+        return None;
+    }
+    let u = [(b[0] - a[0]) / len_in, (b[1] - a[1]) / len_in];
+    let v = [(c[0] - b[0]) / len_out, (c[1] - b[1]) / len_out];
+    let cross = u[0] * v[1] - u[1] * v[0];
+    let turn = cross.atan2(u[0] * v[0] + u[1] * v[1]);
+    if turn.abs() < 1e-6 || turn.abs() > std::f64::consts::PI - 1e-3 {
+        return None;
+    }
+
+    let half = (turn.abs() / 2.0).tan();
+    let trim = (planner.config.corner_radius * half)
+        .min(len_in / 2.0)
+        .min(len_out / 2.0);
+    let radius = trim / half;
+    let start = lerp(b, a, trim / len_in);
+    let end = lerp(b, c, trim / len_out);
+
+    // The centre is a radius to the left of the first segment for a left turn, right otherwise.
+    let side = turn.signum();
+    let centre = [
+        start[0] - u[1] * radius * side,
+        start[1] + u[0] * radius * side,
+    ];
+    let from = (start[1] - centre[1]).atan2(start[0] - centre[0]);
+    let samples = sample_count(&[start, end]).max((radius * turn.abs() / SAMPLE_SPACING) as usize);
+    let arc = (0..=samples)
+        .map(|k| {
+            let s = k as f64 / samples as f64;
+            let angle = from + turn * s;
+            return [
+                centre[0] + radius * angle.cos(),
+                centre[1] + radius * angle.sin(),
+                start[2] + (end[2] - start[2]) * s,
+            ];
+        })
+        .collect();
+    return Some(arc);
+}
+
+fn is_valid(planner: &Planner, playground: &Playground, points: &[Point]) -> bool {
+    let poses: Vec<Pose2> = points.iter().map(to_pose).collect();
+    return poses
+        .windows(2)
+        .all(|w| planner.is_valid_path(playground, &w[0], &w[1]));
+}
+
+// Concatenates the pieces, dropping repeated points where they meet.
+fn join(pieces: &[Piece]) -> Vec<Pose2> {
+    let mut acc: Vec<Pose2> = Vec::new();
+    for p in pieces.iter().flat_map(|p| p.points.iter()) {
+        let pose = to_pose(p);
+        let repeated = acc
+            .last()
+            .is_some_and(|last| last.translation(&pose) < 1e-9 && last.rotation(&pose) < 1e-9);
+        if !repeated {
+            acc.push(pose);
+        }
+    }
+    return acc;
+}
+
+// Enough samples to keep them about SAMPLE_SPACING apart along the control polygon.
+fn sample_count(points: &[Point]) -> usize {
+    let length: f64 = points.windows(2).map(|w| xy_dist(w[0], w[1])).sum();
+    return ((length / SAMPLE_SPACING).ceil() as usize).max(2);
+}
+
+fn to_pose(p: &Point) -> Pose2 {
+    return Pose2::new(p[0], p[1], p[2]);
+}
+
+fn xy_dist(a: Point, b: Point) -> f64 {
+    return (b[0] - a[0]).hypot(b[1] - a[1]);
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+fn scale(a: Point, s: f64) -> Point {
+    return [a[0] * s, a[1] * s, a[2] * s];
+}
+
+fn lerp(a: Point, b: Point, s: f64) -> Point {
+    return [
+        a[0] + (b[0] - a[0]) * s,
+        a[1] + (b[1] - a[1]) * s,
+        a[2] + (b[2] - a[2]) * s,
+    ];
+}
+
+fn combine(points: &[Point], weights: &[f64]) -> Point {
+    let mut acc = [0.0; 3];
+    for (p, w) in points.iter().zip(weights) {
+        for (a, v) in acc.iter_mut().zip(p) {
+            *a += v * w;
+        }
+    }
+    return acc;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlannerConfig;
+    use crate::playground::Rect;
+
+    // An L-shaped path around the corner of an obstacle.
+    fn corner() -> (Playground, Vec<Pose2>) {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 0),
+            size: (300, 300),
+        });
+        let path = vec![
+            Pose2::new(50.0, 50.0, 0.0),
+            Pose2::new(50.0, 200.0, 0.0),
+            Pose2::new(50.0, 350.0, 0.0),
+            Pose2::new(350.0, 350.0, 0.0),
+        ];
+        return (playground, path);
+    }
+
+    fn planner(playground: &Playground, smoothing: Smoothing) -> Planner {
+        return Planner::with_config(
+            playground,
+            PlannerConfig {
+                smoothing,
+                robot_size: (10, 10),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn linear_is_unchanged() {
+        let (playground, path) = corner();
+        let planner = planner(&playground, Smoothing::Linear);
+        assert_eq!(smooth(&planner, &playground, &path), path);
+    }
+
+    #[test]
+    fn smoothed_paths_are_valid() {
+        let (playground, path) = corner();
+        for mode in [
+            Smoothing::CatmullRom,
+            Smoothing::Hermite,
+            Smoothing::BSpline,
+            Smoothing::Arcs,
+        ] {
+            let planner = planner(&playground, mode);
+            let smoothed = smooth(&planner, &playground, &path);
+            assert!(smoothed.len() > path.len(), "{mode:?}");
+            assert_eq!(smoothed.first(), path.first(), "{mode:?}");
+            assert_eq!(smoothed.last(), path.last(), "{mode:?}");
+            for w in smoothed.windows(2) {
+                assert!(planner.is_valid_path(&playground, &w[0], &w[1]), "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn collisions_fall_back_to_linear() {
+        // Catmull-Rom swings wide of the corner, into a wall just below the second segment.
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (0, 365),
+            size: (400, 35),
+        });
+        let planner = planner(&playground, Smoothing::CatmullRom);
+        let path = vec![
+            Pose2::new(50.0, 50.0, 0.0),
+            Pose2::new(50.0, 350.0, 0.0),
+            Pose2::new(350.0, 350.0, 0.0),
+        ];
+        assert_eq!(smooth(&planner, &playground, &path), path);
+
+        // Without the wall the corner is smoothed.
+        let open = Playground::new((400, 400), (50, 50), (350, 350));
+        assert!(smooth(&planner, &open, &path).len() > path.len());
+    }
+
+    #[test]
+    fn arcs_are_tangent() {
+        let playground = Playground::new((400, 400), (50, 50), (350, 350));
+        let mut planner = planner(&playground, Smoothing::Arcs);
+        planner.config.corner_radius = 50.0;
+        let path = vec![
+            Pose2::new(50.0, 50.0, 0.0),
+            Pose2::new(200.0, 50.0, 0.0),
+            Pose2::new(200.0, 200.0, 0.0),
+        ];
+        let smoothed = smooth(&planner, &playground, &path);
+        // A quarter circle from (150, 50) to (200, 100) about (150, 100), joined by straight lines.
+        let on_arc: Vec<&Pose2> = smoothed
+            .iter()
+            .filter(|p| p.x > 150.0 - 1e-6 && p.y < 100.0 + 1e-6)
+            .collect();
+        assert!(on_arc.len() > 2);
+        for p in on_arc {
+            assert!(((p.x - 150.0).hypot(p.y - 100.0) - 50.0).abs() < 1e-6);
+        }
+        assert_eq!(smoothed.first(), path.first());
+        assert_eq!(smoothed.last(), path.last());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+// Instrumentation collected while computing a single plan.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PlanStats {
+    pub seed: u64,
+    pub success: bool,
+    pub cancelled: bool,
+    pub iterations: usize,          // Samples drawn by the search
+    pub rejected_samples: usize,    // Samples already in the tree or in collision
+    pub unconnected_samples: usize, // Valid samples with no collision-free edge to the tree
+    pub tree_size: usize,
+    pub pose_checks: usize, // Calls to is_valid_pose
+    pub path_checks: usize, // Calls to is_valid_path
+    pub search_time: Duration,
+    pub compaction_time: Duration,
+    pub optimisation_time: Duration,
+    pub spline_time: Duration,
+    pub full_length: f64,
+    pub compact_length: f64,
+    pub unoptimised_cost: f64, // Of the compacted path before config.optimisers ran
+    pub cost: f64,             // Of the compacted path, under the configured distance metric
+    pub travel_time: f64,      // Seconds the robot takes to follow the path
+    pub infeasible: Option<String>, // Why the robot's drive can't follow the path, if it can't
+    pub full_waypoints: usize,
+    pub compact_waypoints: usize,
+}
+
+impl PlanStats {
+    pub fn total_time(&self) -> Duration {
+        return self.search_time + self.compaction_time + self.optimisation_time + self.spline_time;
+    }
+
+    // One "name: value" line per field, for printing or drawing as an overlay.
+    pub fn lines(&self) -> Vec<String> {
+        let ms = |d: Duration| format!("{:.2} ms", d.as_secs_f64() * 1000.0);
+        return vec![
+            format!("seed: {}", self.seed),
+            format!("success: {}", self.success),
+            format!("cancelled: {}", self.cancelled),
+            format!("iterations: {}", self.iterations),
+            format!("rejected samples: {}", self.rejected_samples),
+            format!("unconnected samples: {}", self.unconnected_samples),
+            format!("tree size: {}", self.tree_size),
+            format!("pose checks: {}", self.pose_checks),
+            format!("path checks: {}", self.path_checks),
+            format!("search time: {}", ms(self.search_time)),
+            format!("compaction time: {}", ms(self.compaction_time)),
+            format!("optimisation time: {}", ms(self.optimisation_time)),
+            format!("spline time: {}", ms(self.spline_time)),
+            format!("total time: {}", ms(self.total_time())),
+            format!(
+                "path length: {:.1} -> {:.1}",
+                self.full_length, self.compact_length
+            ),
+            format!(
+                "path cost: {:.1} -> {:.1}",
+                self.unoptimised_cost, self.cost
+            ),
+            format!("travel time: {:.1} s", self.travel_time),
+            match &self.infeasible {
+                None => "feasible: yes".to_string(),
+                Some(e) => format!("feasible: no, {e}"),
+            },
+            format!(
+                "waypoints: {} -> {}",
+                self.full_waypoints, self.compact_waypoints
+            ),
+        ];
+    }
+}
+
+impl fmt::Display for PlanStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "{line}")?;
+        }
+        return Ok(());
+    }
+}
+
+// Collision query counters. These are atomic so that the planner's collision checks can keep
+// taking &self.
+#[derive(Debug, Default)]
+pub struct CheckCounter {
+    pose: AtomicUsize,
+    path: AtomicUsize,
+}
+
+impl CheckCounter {
+    pub fn count_pose(&self) {
+        self.pose.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_path(&self) {
+        self.path.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // (pose checks, path checks) so far
+    pub fn get(&self) -> (usize, usize) {
+        return (
+            self.pose.load(Ordering::Relaxed),
+            self.path.load(Ordering::Relaxed),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_counter() {
+        let counter = CheckCounter::default();
+        counter.count_pose();
+        counter.count_path();
+        counter.count_path();
+        assert_eq!(counter.get(), (1, 2));
+    }
+
+    #[test]
+    fn display() {
+        let stats = PlanStats {
+            iterations: 12,
+            search_time: Duration::from_millis(3),
+            spline_time: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let text = stats.to_string();
+        assert!(text.contains("iterations: 12\n"));
+        assert!(text.contains("total time: 4.00 ms\n"));
+    }
+}
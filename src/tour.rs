@@ -0,0 +1,499 @@
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::iter::once;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::PlannerConfig;
+use crate::planner::Planner;
+use crate::playground::{GoalRegion, Playground, Waypoint};
+use crate::pose::Pose2;
+use crate::trajectory::{MotionState, Trajectory, TrajectoryPoint};
+use crate::worker::cancel_monitor;
+
+// Tours calling at more waypoints than this are ordered by nearest neighbour and 2-opt instead of
+// exactly, which takes time and memory that double with every waypoint.
+const EXACT_ORDER: usize = 12;
+
+// What a leg no path was found for costs when choosing the order, so that every order has a cost
+// to compare and the fewer legs fail the cheaper it is.
+const FAILED_LEG: f64 = 1e9;
+
+// The order a tour calls at the playground's waypoints in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Listed, // The order they are listed in
+    Any,    // Whichever order makes the cheapest tour
+}
+
+// A planned path between two stops of a tour.
+#[derive(Clone, Debug)]
+struct Leg {
+    cost: f64,        // Under the distance metric, or infinite if no path was found
+    path: Vec<Pose2>, // Smoothed, or empty if no path was found
+    trajectory: Trajectory,
+}
+
+// A mission from the start to the goal calling at each of the playground's waypoints on the way,
+// planned leg by leg and timed as one trajectory that comes to rest at every waypoint and waits
+// there for its dwell time.
+#[derive(Clone, Debug)]
+pub struct Tour {
+    pub order: Order,
+    pub visits: Vec<usize>, // Indices into the playground's waypoints, in the order called at
+    pub stops: Vec<Pose2>,  // The start, the waypoints in that order and the goal
+    pub legs: Vec<Vec<Pose2>>, // The smoothed path of each leg driven
+    pub failed: Option<usize>, // The first leg no path was found for, where the tour stops short
+    pub cost: f64,          // Of the legs driven, under the distance metric
+    pub plans: usize,       // Legs planned, counting those only planned to weigh up the order
+    pub trajectory: Trajectory,
+}
+
+impl Tour {
+    // Plans a tour of the playground's waypoints. In any order, the leg from every stop to every
+    // other is planned and the order that is cheapest under the planned costs is driven. Each leg
+    // is seeded by which stops it joins, so a leg planned for either order takes the same path.
+    pub fn plan(playground: &Playground, config: &PlannerConfig, order: Order, seed: u64) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        return Self::plan_cancellable(playground, config, order, seed, &cancel);
+    }
+
+    // Like plan, but once `cancel` is set the legs still to plan fail, so the tour stops short.
+    pub fn plan_cancellable(
+        playground: &Playground,
+        config: &PlannerConfig,
+        order: Order,
+        seed: u64,
+        cancel: &Arc<AtomicBool>,
+    ) -> Self {
+        let checker = Planner::with_config(playground, config.clone());
+        let mut stops = vec![playground.start_pose()];
+        stops.extend(
+            playground
+                .waypoints
+                .iter()
+                .map(|w| stop_pose(&checker, playground, w)),
+        );
+        stops.push(playground.goal_pose());
+
+        // Stops are numbered as above: the start is 0, the waypoints 1 to n and the goal n + 1.
+        let n = playground.waypoints.len();
+        let pairs: Vec<(usize, usize)> = match order {
+            Order::Listed => (0..=n).map(|i| (i, i + 1)).collect(),
+            Order::Any => (0..=n)
+                .flat_map(|i| (1..=n + 1).map(move |j| (i, j)))
+                .filter(|&(i, j)| (i != j && (i, j) != (0, n + 1)) || n == 0)
+                .collect(),
+        };
+        let legs: HashMap<(usize, usize), Leg> = pairs
+            .par_iter()
+            .map(|&(i, j)| {
+                let seed = seed.wrapping_add((i * (n + 2) + j) as u64);
+                // Only the last leg can end anywhere in the goal region.
+                let region = match j == n + 1 {
+                    true => playground.goal_region.clone(),
+                    false => GoalRegion::default(),
+                };
+                let (from, to) = (&stops[i], &stops[j]);
+                let leg = plan_leg(playground, &checker, from, to, region, seed, cancel);
+                return ((i, j), leg);
+            })
+            .collect();
+        let visits = match order {
+            Order::Listed => (0..n).collect(),
+            Order::Any => shortest_order(n, |i, j| legs[&(i, j)].cost.min(FAILED_LEG)),
+        };
+
+        let route: Vec<usize> = once(0)
+            .chain(visits.iter().map(|v| v + 1))
+            .chain(once(n + 1))
+            .collect();
+        let mut tour = Self {
+            order,
+            visits,
+            stops: route.iter().map(|&i| stops[i]).collect(),
+            legs: vec![],
+            failed: None,
+            cost: 0.0,
+            plans: pairs.len(),
+            trajectory: Trajectory::default(),
+        };
+        let mut driven = Vec::new();
+        for (k, w) in route.windows(2).enumerate() {
+            let leg = &legs[&(w[0], w[1])];
+            if leg.path.is_empty() {
+                tour.failed = Some(k);
+                break;
+            }
+            // The robot waits at a waypoint before setting off on the next leg.
+            let dwell = match w[0] {
+                0 => 0.0,
+                i => playground.waypoints[i - 1].dwell.max(0.0),
+            };
+            driven.push((&leg.trajectory, dwell));
+            tour.legs.push(leg.path.clone());
+            tour.cost += leg.cost;
+        }
+        tour.trajectory = stitch(&stops[0], &driven);
+        return tour;
+    }
+
+    // The order, what it cost and where it failed, if it did, for the stats overlay and `tour`.
+    pub fn lines(&self) -> Vec<String> {
+        let visits: Vec<String> = self.visits.iter().map(|v| v.to_string()).collect();
+        let mut lines = vec![
+            format!("order: {:?}", self.order),
+            format!("waypoints: {}", visits.join(", ")),
+            format!("legs planned: {}", self.plans),
+            format!("cost: {:.1}", self.cost),
+            format!("duration: {:.2} s", self.trajectory.duration()),
+        ];
+        if let Some(k) = self.failed {
+            lines.push(format!("failed: leg {} of {}", k + 1, self.stops.len() - 1));
+        }
+        return lines;
+    }
+}
+
+// Where the robot stops at a waypoint: facing along its heading if it has one, and otherwise at
+// the first heading that fits, trying the x axis first and then every config.angle_step degrees.
+fn stop_pose(checker: &Planner, playground: &Playground, waypoint: &Waypoint) -> Pose2 {
+    let (x, y) = (waypoint.position.0 as f64, waypoint.position.1 as f64);
+    if let Some(heading) = waypoint.heading {
+        return Pose2::new(x, y, heading);
+    }
+    let step = checker.config.angle_step as usize;
+    return (0..360)
+        .step_by(step)
+        .map(|d| Pose2::new(x, y, (d as f64).to_radians()))
+        .find(|pose| checker.is_valid_pose(playground, pose))
+        .unwrap_or(Pose2::new(x, y, 0.0));
+}
+
+// Plans the leg from `from` to anywhere in `region` around `to` on its own. A cancelled leg
+// fails.
+fn plan_leg(
+    playground: &Playground,
+    checker: &Planner,
+    from: &Pose2,
+    to: &Pose2,
+    region: GoalRegion,
+    seed: u64,
+    cancel: &Arc<AtomicBool>,
+) -> Leg {
+    let reachable = !region.is_exact() || checker.is_valid_pose(playground, to);
+    if !checker.is_valid_pose(playground, from) || !reachable || cancel.load(Ordering::Relaxed) {
+        return Leg {
+            cost: f64::INFINITY,
+            path: vec![],
+            trajectory: Trajectory::default(),
+        };
+    }
+    // The search can't find its way to where it starts, but there's nowhere to go anyway.
+    if from.key() == to.key() {
+        return Leg {
+            cost: 0.0,
+            path: vec![*from],
+            trajectory: Trajectory::new(checker, &[*from], MotionState::default()),
+        };
+    }
+    let mut playground = playground.between(from, to);
+    playground.goal_region = region;
+    let mut planner = Planner::with_config(&playground, checker.config.clone());
+    planner.seed = seed;
+    planner.monitor = Some(cancel_monitor(cancel));
+    planner.compute_path(&playground);
+    if planner.compact_path.is_empty() {
+        return Leg {
+            cost: f64::INFINITY,
+            path: vec![],
+            trajectory: Trajectory::default(),
+        };
+    }
+    return Leg {
+        cost: planner.stats.cost,
+        trajectory: planner.trajectory().unwrap().clone(),
+        path: planner.smooth_path,
+    };
+}
+
+// Joins the legs' trajectories into one, each leg setting off after the robot has waited at the
+// end of the one before for the time paired with the leg. With no legs the robot stays at
+// `start`.
+fn stitch(start: &Pose2, legs: &[(&Trajectory, f64)]) -> Trajectory {
+    let mut points: Vec<TrajectoryPoint> = Vec::new();
+    for &(leg, dwell) in legs {
+        // The robot stops dead to wait, whatever the profile had it doing at the end of the leg.
+        if let Some(last) = points.last_mut().filter(|_| dwell > 0.0) {
+            *last = TrajectoryPoint {
+                time: last.time,
+                x: last.x,
+                y: last.y,
+                theta: last.theta,
+                distance: last.distance,
+                ..Default::default()
+            };
+            let rested = TrajectoryPoint {
+                time: last.time + dwell,
+                ..*last
+            };
+            points.push(rested);
+        }
+        let first = leg.points[0];
+        let (time, distance, theta) = match points.last() {
+            None => (0.0, 0.0, first.theta),
+            Some(p) => (p.time, p.distance, p.theta),
+        };
+        // Headings are unwrapped, so the leg may start a whole number of turns away from where
+        // the one before ended.
+        let turns = ((theta - first.theta) / TAU).round() * TAU;
+        // After the first leg, each leg's first point is the last one's twin.
+        let skip = match points.is_empty() {
+            true => 0,
+            false => 1,
+        };
+        points.extend(leg.points.iter().skip(skip).map(|p| TrajectoryPoint {
+            time: p.time + time,
+            distance: p.distance + distance,
+            theta: p.theta + turns,
+            ..*p
+        }));
+    }
+    if points.is_empty() {
+        points.push(TrajectoryPoint {
+            x: start.x,
+            y: start.y,
+            theta: start.theta,
+            ..Default::default()
+        });
+    }
+    return Trajectory { points };
+}
+
+// The cheapest order to call at `n` waypoints in, numbered from 0, on the way from the start to
+// the goal, where cost(i, j) is the cost from stop i to stop j numbered as in Tour::plan.
+fn shortest_order(n: usize, cost: impl Fn(usize, usize) -> f64) -> Vec<usize> {
+    return match n <= EXACT_ORDER {
+        true => exact_order(n, cost),
+        false => improved_order(n, cost),
+    };
+}
+
+// Held-Karp: the cheapest way from the start through each set of waypoints, ending at each of
+// them, built up from smaller sets.
+fn exact_order(n: usize, cost: impl Fn(usize, usize) -> f64) -> Vec<usize> {
+    if n == 0 {
+        return vec![];
+    }
+    let full = (1 << n) - 1;
+    let mut best = vec![vec![f64::INFINITY; n]; full + 1];
+    let mut previous = vec![vec![0; n]; full + 1];
+    for last in 0..n {
+        best[1 << last][last] = cost(0, last + 1);
+    }
+    for set in 1..=full {
+        for last in (0..n).filter(|&last| set & (1 << last) != 0 && set != 1 << last) {
+            let rest = set & !(1 << last);
+            for before in (0..n).filter(|&before| rest & (1 << before) != 0) {
+                let c = best[rest][before] + cost(before + 1, last + 1);
+                if c < best[set][last] {
+                    best[set][last] = c;
+                    previous[set][last] = before;
+                }
+            }
+        }
+    }
+
+    let to_goal = |last: usize| best[full][last] + cost(last + 1, n + 1);
+    let mut last = (0..n)
+        .min_by(|&a, &b| to_goal(a).total_cmp(&to_goal(b)))
+        .unwrap();
+    let mut set = full;
+    let mut order = vec![last];
+    while set != 1 << last {
+        let before = previous[set][last];
+        set &= !(1 << last);
+        last = before;
+        order.push(last);
+    }
+    order.reverse();
+    return order;
+}
+
+// Nearest neighbour from the start, then 2-opt: reverses any run of calls that makes the tour
+// cheaper, until none does.
+fn improved_order(n: usize, cost: impl Fn(usize, usize) -> f64) -> Vec<usize> {
+    let total = |order: &[usize]| -> f64 {
+        let stops: Vec<usize> = once(0)
+            .chain(order.iter().map(|v| v + 1))
+            .chain(once(n + 1))
+            .collect();
+        return stops.windows(2).map(|w| cost(w[0], w[1])).sum();
+    };
+
+    let mut order = Vec::with_capacity(n);
+    let mut left: Vec<usize> = (0..n).collect();
+    let mut here = 0;
+    while !left.is_empty() {
+        let k = (0..left.len())
+            .min_by(|&a, &b| cost(here, left[a] + 1).total_cmp(&cost(here, left[b] + 1)))
+            .unwrap();
+        let next = left.remove(k);
+        order.push(next);
+        here = next + 1;
+    }
+
+    let mut best = total(&order);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..n {
+            for j in i + 1..n {
+                order[i..=j].reverse();
+                let c = total(&order);
+                match c < best - 1e-9 {
+                    true => {
+                        best = c;
+                        improved = true;
+                    }
+                    false => order[i..=j].reverse(),
+                }
+            }
+        }
+    }
+    return order;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::Rect;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    // Every order of 0..n, for checking the cheapest against.
+    fn permutations(n: usize) -> Vec<Vec<usize>> {
+        if n == 0 {
+            return vec![vec![]];
+        }
+        let mut all = Vec::new();
+        for order in permutations(n - 1) {
+            for i in 0..=order.len() {
+                let mut order = order.clone();
+                order.insert(i, n - 1);
+                all.push(order);
+            }
+        }
+        return all;
+    }
+
+    #[test]
+    fn shortest_order() {
+        // Lopsided costs, so that which way round matters.
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let n = 6;
+        let costs: Vec<Vec<f64>> = (0..n + 2)
+            .map(|_| (0..n + 2).map(|_| rng.gen_range(1.0..100.0)).collect())
+            .collect();
+        let cost = |i: usize, j: usize| costs[i][j];
+        let total = |order: &[usize]| -> f64 {
+            let stops: Vec<usize> = once(0)
+                .chain(order.iter().map(|v| v + 1))
+                .chain(once(n + 1))
+                .collect();
+            return stops.windows(2).map(|w| cost(w[0], w[1])).sum();
+        };
+        let cheapest = permutations(n)
+            .iter()
+            .map(|order| total(order))
+            .fold(f64::INFINITY, f64::min);
+        assert!((total(&exact_order(n, cost)) - cheapest).abs() < 1e-9);
+        assert!(total(&improved_order(n, cost)) >= cheapest - 1e-9);
+        assert_eq!(exact_order(0, cost), Vec::<usize>::new());
+
+        // Along a line the heuristic finds the way straight along it too.
+        let n = 20;
+        let xs: Vec<f64> = (0..n).map(|i| ((i * 7) % n) as f64 + 1.0).collect();
+        let x = |i: usize| match i {
+            0 => 0.0,
+            i if i == n + 1 => n as f64 + 1.0,
+            i => xs[i - 1],
+        };
+        let order = super::shortest_order(n, |i, j| (x(i) - x(j)).abs());
+        let along: Vec<f64> = order.iter().map(|&v| xs[v]).collect();
+        assert!(along.windows(2).all(|w| w[0] < w[1]), "{along:?}");
+    }
+
+    #[test]
+    fn tour_calls_at_every_waypoint() {
+        // A wall down the middle with a gap at the bottom.
+        let mut playground = Playground::new((400, 300), (50, 50), (350, 50));
+        playground.add_obstacles(Rect {
+            anchor: (190, 0),
+            size: (20, 220),
+        });
+        playground.waypoints = vec![
+            Waypoint::new((300, 150)),
+            Waypoint {
+                position: (100, 150),
+                heading: Some(std::f64::consts::FRAC_PI_2),
+                dwell: 2.0,
+            },
+        ];
+        let config = PlannerConfig::default();
+
+        let listed = Tour::plan(&playground, &config, Order::Listed, 1);
+        assert_eq!(listed.failed, None);
+        assert_eq!(listed.visits, vec![0, 1]);
+        assert_eq!(listed.legs.len(), 3);
+        assert_eq!(listed.plans, 3);
+
+        // Calling at the waypoint on the start's side of the wall first saves going through
+        // the gap twice more.
+        let any = Tour::plan(&playground, &config, Order::Any, 1);
+        assert_eq!(any.failed, None);
+        assert_eq!(any.visits, vec![1, 0]);
+        assert_eq!(any.plans, 6);
+        assert!(any.cost < listed.cost);
+
+        // The trajectory goes from the start to the goal, calls at each waypoint facing the way it
+        // has to, and stops dead to wait out the dwell.
+        let points = &any.trajectory.points;
+        assert!(points.windows(2).all(|w| w[0].time < w[1].time));
+        assert_eq!(points[0].pose().key(), playground.start_pose().key());
+        assert_eq!(
+            points.last().unwrap().pose().key(),
+            playground.goal_pose().key()
+        );
+        for stop in &any.stops[1..3] {
+            assert!(points
+                .iter()
+                .any(|p| p.pose().translation(stop) < 1e-6 && p.pose().rotation(stop) < 1e-6));
+        }
+        let waited = points
+            .windows(2)
+            .filter(|w| w[0].pose() == w[1].pose() && w[0].speed == 0.0 && w[1].speed == 0.0)
+            .map(|w| w[1].time - w[0].time)
+            .sum::<f64>();
+        assert!((waited - 2.0).abs() < 1e-9, "{waited}");
+
+        // A waypoint inside an obstacle can't be reached, so the tour stops short of it.
+        playground.waypoints[0].position = (200, 100);
+        let blocked = Tour::plan(&playground, &config, Order::Listed, 1);
+        assert_eq!(blocked.failed, Some(0));
+        assert!(blocked.legs.is_empty());
+        assert_eq!(blocked.trajectory.points.len(), 1);
+    }
+
+    #[test]
+    fn stops_short_when_cancelled() {
+        let mut playground = Playground::new((400, 300), (50, 50), (350, 50));
+        playground.waypoints = vec![Waypoint::new((200, 150))];
+        let cancel = Arc::new(AtomicBool::new(true));
+        let config = PlannerConfig::default();
+        let tour = Tour::plan_cancellable(&playground, &config, Order::Listed, 1, &cancel);
+        assert_eq!(tour.failed, Some(0));
+        assert!(tour.legs.is_empty());
+    }
+}
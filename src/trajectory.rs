@@ -0,0 +1,520 @@
+use serde::Serialize;
+
+use crate::config::Profile;
+use crate::planner::Planner;
+use crate::pose::{angle_diff, Pose2};
+
+// Longest stretch of path between trajectory points, in metric units. Short enough that the speed
+// can change smoothly along a long straight segment.
+const POINT_SPACING: f64 = 2.0;
+
+// Time step used when filtering the speed into an S-curve, in seconds.
+const FILTER_STEP: f64 = 0.005;
+
+// Where a trajectory starts: the time, the metric distance along the path so far and the speed
+// along the path, in metric units per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionState {
+    pub time: f64,
+    pub distance: f64,
+    pub speed: f64,
+}
+
+// The robot's state at one instant of a trajectory. Velocities and accelerations are those of the
+// centre in pixels and of the heading in radians; the angular ones are signed, positive turning
+// the way theta increases.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TrajectoryPoint {
+    pub time: f64,
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64, // Unwrapped, so that it changes continuously along the trajectory
+    pub distance: f64, // Metric distance along the path
+    pub speed: f64, // Metric units per second along the path
+    pub velocity: f64,
+    pub angular_velocity: f64,
+    pub acceleration: f64,
+    pub angular_acceleration: f64,
+}
+
+impl TrajectoryPoint {
+    pub fn pose(&self) -> Pose2 {
+        return Pose2::new(self.x, self.y, self.theta);
+    }
+}
+
+// A path with timing: when the robot reaches each point along it, and how fast it is moving and
+// accelerating there. The points are at most POINT_SPACING apart along the path and include every
+// waypoint.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trajectory {
+    pub points: Vec<TrajectoryPoint>,
+}
+
+// The change in position and heading from one trajectory point to the next.
+struct Step {
+    length: f64, // Metric distance
+    dx: f64,
+    dy: f64,
+    turn: f64,
+}
+
+impl Step {
+    fn translation(&self) -> f64 {
+        return self.dx.hypot(self.dy);
+    }
+}
+
+impl Trajectory {
+    // Times the robot's motion along `path` with config.profile, starting from `start` and coming
+    // to rest at the end of the path. Distances are measured with the planner's metric, like the
+    // path cost, and config.speed is the top speed along the path.
+    //
+    // The trapezoidal profile is the fastest one within the limits. Each step between points
+    // has a speed limit from the angular speed limit and from the lateral acceleration on the
+    // curve through its ends, and an acceleration limit from the linear and angular ones. A
+    // forward pass accelerates as hard as the limits allow and a backward pass makes sure the
+    // robot can still slow down in time for each limit ahead of it.
+    //
+    // The S-curve profile averages the trapezoidal speed over a sliding window of
+    // 2 * max_acceleration / max_jerk seconds. That ramps the acceleration up and down at no more
+    // than max_jerk and keeps it within max_acceleration, at the cost of taking that much longer.
+    // Averaging can let the robot round a tight corner a little faster than the corner's limit,
+    // but it still stays on the path.
+    pub fn new(planner: &Planner, path: &[Pose2], start: MotionState) -> Trajectory {
+        let config = &planner.config;
+        let (points, steps) = Self::subdivide(planner, path, start.distance);
+
+        let speeds = match config.profile {
+            Profile::Constant => vec![config.speed; points.len()],
+            Profile::Trapezoidal | Profile::SCurve => Self::limit_speeds(planner, &steps, start),
+        };
+        let times: Vec<f64> = match config.profile {
+            // Without summing the steps, so that the times are exact.
+            Profile::Constant => points
+                .iter()
+                .map(|p| start.time + (p.distance - start.distance) / config.speed)
+                .collect(),
+            _ => {
+                let mut times = vec![start.time];
+                for (i, step) in steps.iter().enumerate() {
+                    let mean = ((speeds[i] + speeds[i + 1]) / 2.0).max(f64::MIN_POSITIVE);
+                    times.push(times[i] + step.length / mean);
+                }
+                times
+            }
+        };
+        let mut changes: Vec<f64> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (speeds[i + 1].powi(2) - speeds[i].powi(2)) / (2.0 * step.length))
+            .collect();
+        changes.push(0.0);
+
+        let (times, speeds, changes) = match config.profile {
+            Profile::SCurve => {
+                let window = 2.0 * config.max_acceleration / config.max_jerk;
+                Self::filter(&points, &times, &speeds, window, start.speed)
+            }
+            _ => (times, speeds, changes),
+        };
+
+        let mut trajectory = Trajectory { points: vec![] };
+        for (i, point) in points.iter().enumerate() {
+            // The step leaving each point sets its direction, except at the end of the path.
+            let (linear, angular) = match steps.get(i).or(steps.last()) {
+                None => (0.0, 0.0),
+                Some(s) => (s.translation() / s.length, s.turn / s.length),
+            };
+            trajectory.points.push(TrajectoryPoint {
+                time: times[i],
+                speed: speeds[i],
+                velocity: speeds[i] * linear,
+                angular_velocity: speeds[i] * angular,
+                acceleration: changes[i] * linear,
+                angular_acceleration: changes[i] * angular,
+                ..*point
+            });
+        }
+        return trajectory;
+    }
+
+    pub fn start_time(&self) -> f64 {
+        return self.points.first().map_or(0.0, |p| p.time);
+    }
+
+    pub fn end_time(&self) -> f64 {
+        return self.points.last().map_or(0.0, |p| p.time);
+    }
+
+    pub fn duration(&self) -> f64 {
+        return self.end_time() - self.start_time();
+    }
+
+    // The state at `time`, interpolated between the neighbouring points. Before the start the
+    // robot waits at the first point, and after the end it rests at the last.
+    pub fn sample(&self, time: f64) -> TrajectoryPoint {
+        let i = self.points.partition_point(|p| p.time <= time);
+        if i == 0 {
+            return self.points.first().copied().unwrap_or_default();
+        }
+        if i == self.points.len() {
+            return *self.points.last().unwrap();
+        }
+        let (a, b) = (&self.points[i - 1], &self.points[i]);
+        let s = (time - a.time) / (b.time - a.time);
+        let lerp = |from: f64, to: f64| from + (to - from) * s;
+        return TrajectoryPoint {
+            time,
+            x: lerp(a.x, b.x),
+            y: lerp(a.y, b.y),
+            theta: lerp(a.theta, b.theta),
+            distance: lerp(a.distance, b.distance),
+            speed: lerp(a.speed, b.speed),
+            velocity: lerp(a.velocity, b.velocity),
+            angular_velocity: lerp(a.angular_velocity, b.angular_velocity),
+            acceleration: lerp(a.acceleration, b.acceleration),
+            angular_acceleration: lerp(a.angular_acceleration, b.angular_acceleration),
+        };
+    }
+
+    // Splits the path into steps no longer than POINT_SPACING. Waypoints the metric puts no
+    // distance apart are merged, so a turn the metric doesn't charge for happens instantly.
+    fn subdivide(
+        planner: &Planner,
+        path: &[Pose2],
+        start: f64,
+    ) -> (Vec<TrajectoryPoint>, Vec<Step>) {
+        let mut points: Vec<TrajectoryPoint> = Vec::new();
+        let mut steps = Vec::new();
+        if let Some(first) = path.first() {
+            points.push(TrajectoryPoint {
+                x: first.x,
+                y: first.y,
+                theta: first.theta,
+                distance: start,
+                ..Default::default()
+            });
+        }
+        for w in path.windows(2) {
+            let length = planner.distance(&w[0], &w[1]);
+            if length == 0.0 {
+                continue;
+            }
+            let from = *points.last().unwrap();
+            // Measured from the last point rather than w[0], in case a turn was merged into it.
+            let turn = angle_diff(from.theta, w[1].theta);
+            let n = (length / POINT_SPACING).ceil() as usize;
+            for k in 1..=n {
+                let s = k as f64 / n as f64;
+                let prev = *points.last().unwrap();
+                let point = TrajectoryPoint {
+                    x: from.x + (w[1].x - from.x) * s,
+                    y: from.y + (w[1].y - from.y) * s,
+                    theta: from.theta + turn * s,
+                    distance: from.distance + length * s,
+                    ..Default::default()
+                };
+                steps.push(Step {
+                    length: point.distance - prev.distance,
+                    dx: point.x - prev.x,
+                    dy: point.y - prev.y,
+                    turn: point.theta - prev.theta,
+                });
+                points.push(point);
+            }
+        }
+        return (points, steps);
+    }
+
+    // The fastest speed at each point that keeps within the limits and comes to rest at the end.
+    fn limit_speeds(planner: &Planner, steps: &[Step], start: MotionState) -> Vec<f64> {
+        let config = &planner.config;
+        let mut limits = vec![config.speed; steps.len() + 1];
+        let mut accelerations = Vec::new();
+        for (i, step) in steps.iter().enumerate() {
+            let (translation, turn) = (step.translation(), step.turn.abs());
+            let mut limit = config.speed;
+            let mut acceleration = f64::INFINITY;
+            if translation > 0.0 {
+                acceleration =
+                    acceleration.min(config.max_acceleration * step.length / translation);
+            }
+            if turn > 0.0 {
+                limit = limit.min(config.max_angular_speed * step.length / turn);
+                acceleration =
+                    acceleration.min(config.max_angular_acceleration * step.length / turn);
+            }
+            limits[i] = limits[i].min(limit);
+            limits[i + 1] = limits[i + 1].min(limit);
+            accelerations.push(acceleration);
+        }
+
+        // Sideways acceleration is speed squared times curvature, which is the change in
+        // direction over the distance it happens in.
+        for (i, w) in steps.windows(2).enumerate() {
+            let (a, b) = (&w[0], &w[1]);
+            let (ta, tb) = (a.translation(), b.translation());
+            if ta == 0.0 || tb == 0.0 {
+                continue;
+            }
+            let bend = (a.dx * b.dy - a.dy * b.dx)
+                .atan2(a.dx * b.dx + a.dy * b.dy)
+                .abs();
+            if bend > 1e-9 {
+                let curvature = bend / ((ta + tb) / 2.0);
+                let velocity = (config.max_lateral_acceleration / curvature).sqrt();
+                let per_unit = (a.length / ta).min(b.length / tb);
+                limits[i + 1] = limits[i + 1].min(velocity * per_unit);
+            }
+        }
+        *limits.last_mut().unwrap() = 0.0;
+
+        // The robot may already be going faster than the limits allow, say after the speed was
+        // turned down, in which case it brakes as hard as it can until it is back within them.
+        let mut speeds = limits.clone();
+        speeds[0] = start.speed;
+        for (i, step) in steps.iter().enumerate() {
+            let change = 2.0 * accelerations[i] * step.length;
+            let fastest = (speeds[i].powi(2) + change).sqrt();
+            speeds[i + 1] = speeds[i + 1].min(fastest);
+            if speeds[i] > limits[i] {
+                let slowest = (speeds[i].powi(2) - change).max(0.0).sqrt();
+                speeds[i + 1] = speeds[i + 1].max(slowest);
+            }
+        }
+        for (i, step) in steps.iter().enumerate().rev() {
+            let stoppable = (speeds[i + 1].powi(2) + 2.0 * accelerations[i] * step.length).sqrt();
+            speeds[i] = speeds[i].min(stoppable);
+        }
+        // Nor can it change speed instantly, even if that leaves it too fast to stop in time.
+        speeds[0] = start.speed;
+        return speeds;
+    }
+
+    // Averages the speed over a sliding window of `window` seconds and returns the new times,
+    // speeds and rates of change of speed at each point.
+    fn filter(
+        points: &[TrajectoryPoint],
+        times: &[f64],
+        speeds: &[f64],
+        window: f64,
+        start_speed: f64,
+    ) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let width = (window / FILTER_STEP).round() as usize;
+        let start = times[0];
+        let end = *times.last().unwrap();
+        if width < 2 || points.len() < 2 {
+            let changes = vec![0.0; points.len()];
+            return (times.to_vec(), speeds.to_vec(), changes);
+        }
+
+        // The trapezoidal speed on a regular grid. The acceleration is constant between points,
+        // so the speed is linear in time there.
+        let samples = ((end - start) / FILTER_STEP).ceil() as usize + 1;
+        let mut raw = Vec::with_capacity(samples + width);
+        let mut i = 0;
+        for k in 0..samples {
+            let t = (start + k as f64 * FILTER_STEP).min(end);
+            while i + 2 < times.len() && times[i + 1] < t {
+                i += 1;
+            }
+            let s = ((t - times[i]) / (times[i + 1] - times[i])).clamp(0.0, 1.0);
+            raw.push(speeds[i] + (speeds[i + 1] - speeds[i]) * s);
+        }
+        // Rest at the end while the window catches up.
+        raw.resize(samples + width, 0.0);
+
+        // Before the start the robot was moving at its starting speed.
+        let mut sum = start_speed * width as f64;
+        let mut filtered = Vec::with_capacity(raw.len());
+        for k in 0..raw.len() {
+            let old = match k < width {
+                true => start_speed,
+                false => raw[k - width],
+            };
+            sum += raw[k] - old;
+            filtered.push(sum / width as f64);
+        }
+
+        let mut travelled = vec![points[0].distance];
+        for k in 1..filtered.len() {
+            let step = (filtered[k - 1] + filtered[k]) / 2.0 * FILTER_STEP;
+            travelled.push(travelled[k - 1] + step);
+        }
+        // Correct for the grid's rounding so the robot ends exactly at the end of the path.
+        let total = *travelled.last().unwrap() - points[0].distance;
+        let scale = (points.last().unwrap().distance - points[0].distance) / total;
+        for (d, v) in travelled.iter_mut().zip(filtered.iter_mut()) {
+            *d = points[0].distance + (*d - points[0].distance) * scale;
+            *v *= scale;
+        }
+
+        // When the filtered motion reaches each point.
+        let (mut times, mut new_speeds, mut changes) = (vec![], vec![], vec![]);
+        let mut k = 0;
+        for point in points {
+            while k + 2 < travelled.len() && travelled[k + 1] < point.distance {
+                k += 1;
+            }
+            let gap = travelled[k + 1] - travelled[k];
+            let s = match gap > 0.0 {
+                true => ((point.distance - travelled[k]) / gap).clamp(0.0, 1.0),
+                false => 0.0,
+            };
+            times.push(start + (k as f64 + s) * FILTER_STEP);
+            new_speeds.push(filtered[k] + (filtered[k + 1] - filtered[k]) * s);
+            changes.push((filtered[k + 1] - filtered[k]) / FILTER_STEP);
+        }
+        *new_speeds.last_mut().unwrap() = 0.0;
+        *changes.last_mut().unwrap() = 0.0;
+        return (times, new_speeds, changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlannerConfig;
+    use crate::playground::Playground;
+
+    fn planner(profile: Profile) -> Planner {
+        let playground = Playground::new((400, 400), (0, 0), (400, 0));
+        return Planner::with_config(
+            &playground,
+            PlannerConfig {
+                profile,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn straight() -> Vec<Pose2> {
+        return vec![Pose2::new(0.0, 0.0, 0.0), Pose2::new(400.0, 0.0, 0.0)];
+    }
+
+    #[test]
+    fn constant_speed() {
+        let planner = planner(Profile::Constant);
+        let trajectory = Trajectory::new(&planner, &straight(), MotionState::default());
+        assert!((trajectory.duration() - 400.0 / planner.config.speed).abs() < 1e-9);
+        assert!(trajectory.points.iter().all(|p| p.velocity == 25.0));
+
+        let half = trajectory.sample(trajectory.duration() / 2.0);
+        assert!((half.x - 200.0).abs() < 1e-9);
+        assert_eq!(trajectory.sample(-1.0), trajectory.points[0]);
+        assert_eq!(trajectory.sample(1e6).x, 400.0);
+    }
+
+    #[test]
+    fn trapezoidal() {
+        let planner = planner(Profile::Trapezoidal);
+        let config = &planner.config;
+        let trajectory = Trajectory::new(&planner, &straight(), MotionState::default());
+
+        // One second to reach full speed, 15 seconds cruising and one second to stop.
+        assert!((trajectory.duration() - 17.0).abs() < 1e-2);
+        let first = trajectory.points.first().unwrap();
+        let last = trajectory.points.last().unwrap();
+        assert_eq!((first.velocity, last.velocity), (0.0, 0.0));
+        assert_eq!(trajectory.sample(8.5).velocity, config.speed);
+        for p in &trajectory.points {
+            assert!(p.velocity <= config.speed + 1e-9);
+            assert!(p.acceleration.abs() <= config.max_acceleration + 1e-9);
+        }
+    }
+
+    #[test]
+    fn turns_respect_angular_limits() {
+        let planner = planner(Profile::Trapezoidal);
+        let config = &planner.config;
+        let path = [Pose2::new(0.0, 0.0, 0.0), Pose2::new(0.0, 0.0, 3.0)];
+        let trajectory = Trajectory::new(&planner, &path, MotionState::default());
+        let top = trajectory
+            .points
+            .iter()
+            .map(|p| p.angular_velocity)
+            .fold(0.0, f64::max);
+        assert!((top - config.max_angular_speed).abs() < 1e-9);
+        for p in &trajectory.points {
+            assert_eq!(p.velocity, 0.0);
+            assert!(p.angular_acceleration.abs() <= config.max_angular_acceleration + 1e-9);
+        }
+        assert!((trajectory.points.last().unwrap().theta - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slows_for_corners() {
+        let planner = planner(Profile::Trapezoidal);
+        let config = &planner.config;
+        // A sharp right angle has to be taken almost at a standstill.
+        let path = [
+            Pose2::new(0.0, 0.0, 0.0),
+            Pose2::new(200.0, 0.0, 0.0),
+            Pose2::new(200.0, 200.0, 0.0),
+        ];
+        let trajectory = Trajectory::new(&planner, &path, MotionState::default());
+        let corner = trajectory
+            .points
+            .iter()
+            .find(|p| (p.x, p.y) == (200.0, 0.0))
+            .unwrap();
+        assert!(corner.velocity < 5.0);
+
+        // A wide curve is taken at the speed that keeps the sideways acceleration in limits.
+        let radius = 40.0;
+        let arc: Vec<Pose2> = (0..=90)
+            .map(|d| {
+                let a = (d as f64).to_radians();
+                return Pose2::new(radius * a.sin(), radius * (1.0 - a.cos()), 0.0);
+            })
+            .collect();
+        let trajectory = Trajectory::new(&planner, &arc, MotionState::default());
+        let fastest = trajectory
+            .points
+            .iter()
+            .map(|p| p.velocity)
+            .fold(0.0, f64::max);
+        let expected = (config.max_lateral_acceleration * radius).sqrt();
+        assert!((fastest - expected).abs() < 0.05 * expected, "{fastest}");
+    }
+
+    #[test]
+    fn s_curve_limits_jerk() {
+        let planner = planner(Profile::SCurve);
+        let config = &planner.config;
+        let trajectory = Trajectory::new(&planner, &straight(), MotionState::default());
+        let trapezoidal = Trajectory::new(
+            &self::planner(Profile::Trapezoidal),
+            &straight(),
+            MotionState::default(),
+        );
+        assert!(trajectory.duration() > trapezoidal.duration());
+        assert_eq!(trajectory.points.last().unwrap().x, 400.0);
+        assert_eq!(trajectory.points.last().unwrap().velocity, 0.0);
+        for w in trajectory.points.windows(2) {
+            assert!(w[1].velocity <= config.speed + 1e-4);
+            assert!(w[1].acceleration.abs() <= config.max_acceleration + 1e-4);
+            let jerk = (w[1].acceleration - w[0].acceleration) / (w[1].time - w[0].time);
+            assert!(jerk.abs() <= config.max_jerk * 1.05, "{jerk}");
+        }
+    }
+
+    #[test]
+    fn starts_moving() {
+        // A trajectory can pick up where another left off, already moving.
+        let planner = planner(Profile::Trapezoidal);
+        let start = MotionState {
+            time: 10.0,
+            distance: 50.0,
+            speed: 20.0,
+        };
+        let trajectory = Trajectory::new(&planner, &straight(), start);
+        let first = trajectory.points[0];
+        assert_eq!(
+            (first.time, first.distance, first.speed),
+            (10.0, 50.0, 20.0)
+        );
+        assert_eq!(trajectory.points.last().unwrap().distance, 450.0);
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::planner::{Monitor, Planner, Progress, Solution};
+use crate::playground::Playground;
+
+pub enum WorkerEvent {
+    Progress(Progress),
+    Solution(Box<Solution>), // An improved path from an anytime search
+    Finished(Box<Planner>),  // The planner after compute_path, whether or not it found a path
+    Failed(String),          // The search panicked with this message, so no Finished follows
+}
+
+// Runs compute_path on a background thread so the caller (e.g. a render loop) stays responsive.
+// Progress (and, for anytime searches, Solution) events arrive while the search runs, followed by
+// exactly one Finished event, or a Failed event if the search panicked.
+// Dropping the worker cancels the search.
+pub struct PlanWorker {
+    events: Receiver<WorkerEvent>,
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>, // Until the search is over and joined
+}
+
+impl PlanWorker {
+    pub fn spawn(planner: Planner, playground: Arc<Playground>) -> PlanWorker {
+        return Self::start(planner, playground, false);
+    }
+
+    // Like spawn, but runs Planner::plan_anytime and sends a Solution event for each
+    // improvement as soon as it is found.
+    pub fn spawn_anytime(planner: Planner, playground: Arc<Playground>) -> PlanWorker {
+        return Self::start(planner, playground, true);
+    }
+
+    fn start(mut planner: Planner, playground: Arc<Playground>, anytime: bool) -> PlanWorker {
+        let (sender, events) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        // Forward progress to the caller and to any monitor the planner already had.
+        let previous = planner.monitor.take();
+        let progress = sender.clone();
+        let cancelled = cancel.clone();
+        planner.monitor = Some(Arc::new(move |p: &Progress| {
+            // A closed channel means the caller has gone away, so that cancels too.
+            let sent = progress.send(WorkerEvent::Progress(p.clone())).is_ok();
+            let keep_going = previous.as_ref().is_none_or(|m| m(p));
+            return sent && keep_going && !cancelled.load(Ordering::Relaxed);
+        }));
+
+        let handle = thread::spawn(move || {
+            match anytime {
+                true => {
+                    let solutions = sender.clone();
+                    planner.plan_anytime(&playground, |s| {
+                        return solutions
+                            .send(WorkerEvent::Solution(Box::new(s.clone())))
+                            .is_ok();
+                    });
+                }
+                false => {
+                    planner.compute_path(&playground);
+                }
+            }
+            planner.monitor = None;
+            let _ = sender.send(WorkerEvent::Finished(Box::new(planner)));
+        });
+        return PlanWorker {
+            events,
+            cancel,
+            handle: Some(handle),
+        };
+    }
+
+    // Asks the search to stop at its next progress report. A Finished event still follows.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // The next pending event, without blocking.
+    pub fn try_recv(&mut self) -> Option<WorkerEvent> {
+        return match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => self.failure().map(WorkerEvent::Failed),
+        };
+    }
+
+    // Blocks until the search finishes, passing each progress report to `on_progress`. Returns
+    // the message the search panicked with if it did.
+    pub fn wait(mut self, mut on_progress: impl FnMut(&Progress)) -> Result<Planner, String> {
+        loop {
+            let Ok(event) = self.events.recv() else {
+                return Err(self
+                    .failure()
+                    .unwrap_or_else(|| "planner thread stopped".to_string()));
+            };
+            match event {
+                WorkerEvent::Progress(p) => on_progress(&p),
+                WorkerEvent::Solution(_) => (),
+                WorkerEvent::Finished(planner) => return Ok(*planner),
+                WorkerEvent::Failed(e) => return Err(e),
+            }
+        }
+    }
+
+    // Once the search thread has hung up, joins it and returns the message it panicked with, if
+    // it did. Only the first call after it hangs up reports the panic.
+    fn failure(&mut self) -> Option<String> {
+        let handle = self.handle.take()?;
+        return handle.join().err().map(|e| panic_message(e.as_ref()));
+    }
+}
+
+impl Drop for PlanWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// Runs a long job, like a tracking simulation, on a background thread. The job is handed a flag
+// that is set once it is cancelled, which it checks now and then to stop early.
+// Dropping the job cancels it, like dropping a PlanWorker.
+pub struct Job<T> {
+    handle: Option<JoinHandle<T>>, // Until it is joined
+    cancel: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub fn spawn(job: impl FnOnce(&Arc<AtomicBool>) -> T + Send + 'static) -> Job<T> {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = cancel.clone();
+        let handle = thread::spawn(move || job(&cancelled));
+        return Job {
+            handle: Some(handle),
+            cancel,
+        };
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.handle.as_ref().is_none_or(|h| h.is_finished());
+    }
+
+    // Waits for the job to finish and returns what it made, or the message it panicked with.
+    pub fn join(mut self) -> Result<T, String> {
+        let handle = self.handle.take().expect("job already joined");
+        return handle.join().map_err(|e| panic_message(e.as_ref()));
+    }
+}
+
+impl<T> Drop for Job<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+// A planner monitor that stops the search once `cancel` is set, for jobs that plan.
+pub fn cancel_monitor(cancel: &Arc<AtomicBool>) -> Monitor {
+    let cancel = cancel.clone();
+    return Arc::new(move |_: &Progress| !cancel.load(Ordering::Relaxed));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    return match payload.downcast_ref::<String>() {
+        Some(message) => message.clone(),
+        None => "panicked".to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlannerConfig;
+    use crate::playground::Rect;
+
+    #[test]
+    fn finishes_with_progress() {
+        let playground = Arc::new(Playground::new((400, 400), (50, 50), (350, 350)));
+        let mut planner = Planner::new(&playground);
+        planner.seed = 42;
+        let mut reports = 0;
+        let planner = PlanWorker::spawn(planner, playground)
+            .wait(|_| reports += 1)
+            .unwrap();
+
+        assert!(planner.stats.success);
+        assert!(planner.monitor.is_none());
+        assert!(reports > 0);
+    }
+
+    #[test]
+    fn anytime_streams_solutions() {
+        let mut playground = Playground::new((400, 400), (50, 50), (350, 350));
+        playground.add_obstacles(Rect {
+            anchor: (100, 100),
+            size: (200, 200),
+        });
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                max_iterations: Some(20_000),
+                anytime_rounds: 3,
+                ..Default::default()
+            },
+        );
+        planner.seed = 42;
+        let worker = PlanWorker::spawn_anytime(planner, Arc::new(playground));
+
+        let mut solutions = Vec::new();
+        let planner = loop {
+            match worker.events.recv().unwrap() {
+                WorkerEvent::Progress(_) => (),
+                WorkerEvent::Solution(s) => solutions.push(*s),
+                WorkerEvent::Finished(planner) => break planner,
+                WorkerEvent::Failed(e) => panic!("{e}"),
+            }
+        };
+        assert!(!solutions.is_empty());
+        assert_eq!(planner.compact_path, solutions.last().unwrap().compact_path);
+    }
+
+    #[test]
+    fn cancel() {
+        // The goal is walled in and there is no iteration limit, so the search only stops if
+        // it is cancelled.
+        let mut playground = Playground::new((400, 400), (50, 50), (300, 300));
+        playground.add_obstacles(Rect {
+            anchor: (200, 200),
+            size: (200, 200),
+        });
+        let mut planner = Planner::with_config(
+            &playground,
+            PlannerConfig {
+                max_iterations: None,
+                ..Default::default()
+            },
+        );
+        planner.seed = 1;
+        let worker = PlanWorker::spawn(planner, Arc::new(playground));
+        worker.cancel();
+        let planner = worker.wait(|_| ()).unwrap();
+
+        assert!(planner.stats.cancelled);
+        assert!(!planner.stats.success);
+        assert!(planner.full_path.is_empty());
+    }
+
+    #[test]
+    fn reports_panics() {
+        let playground = Arc::new(Playground::new((400, 400), (50, 50), (350, 350)));
+        let mut planner = Planner::new(&playground);
+        planner.seed = 1;
+        planner.monitor = Some(Arc::new(|_: &Progress| panic!("bad monitor")));
+        let mut worker = PlanWorker::spawn(planner, playground);
+        let error = loop {
+            match worker.try_recv() {
+                Some(WorkerEvent::Failed(e)) => break e,
+                Some(WorkerEvent::Finished(_)) => panic!("finished despite the panic"),
+                _ => thread::yield_now(),
+            }
+        };
+        assert_eq!(error, "bad monitor");
+        assert!(worker.try_recv().is_none());
+    }
+
+    #[test]
+    fn job_stops_when_dropped() {
+        let (sender, stopped) = mpsc::channel();
+        let job = Job::spawn(move |cancel| {
+            while !cancel.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            sender.send(()).unwrap();
+        });
+        assert!(!job.is_finished());
+        drop(job);
+        stopped.recv().unwrap();
+    }
+
+    #[test]
+    fn job_reports_panics() {
+        let job = Job::spawn(|_| 1);
+        assert_eq!(job.join(), Ok(1));
+        let job: Job<()> = Job::spawn(|_| panic!("lost"));
+        assert_eq!(job.join(), Err("lost".to_string()));
+    }
+}